@@ -1,4 +1,4 @@
-﻿use core::error::Error;
+﻿use core::{cmp, error::Error, fmt};
 
 use abs_buff::x_deps::abs_sync;
 use abs_sync::cancellation::TrIntoFutureMayCancel;
@@ -15,6 +15,77 @@ pub trait TrChunkIoAbort {
 
     /// The error causes the abort.
     fn last_error(&self) -> &Self::LastErr;
+
+    /// The same abort, with `len` more units reported as performed.
+    ///
+    /// This is for a wrapper that performs an IO in several IOs of the inner
+    /// filler or loader, and passes the abort of the last one on.
+    fn add_perform_len(self, len: usize) -> Self
+    where
+        Self: Sized;
+
+    /// The classification of the abort, so that generic code can tell the
+    /// end of stream or the cancellation from a failure.
+    #[inline(always)]
+    fn kind(&self) -> ChunkIoAbortKind {
+        ChunkIoAbortKind::Fault
+    }
+
+    /// Whether the IO is aborted because the stream reaches its end, rather
+    /// than because of a failure.
+    #[inline(always)]
+    fn is_end_of_stream(&self) -> bool {
+        self.kind() == ChunkIoAbortKind::EndOfStream
+    }
+
+    /// Whether the IO is aborted because the cancellation token is signalled.
+    ///
+    /// The units already performed are not lost, so that the IO can be
+    /// resumed later from `perform_len`.
+    #[inline(always)]
+    fn is_cancelled(&self) -> bool {
+        self.kind() == ChunkIoAbortKind::Cancelled
+    }
+}
+
+/// The classification of an aborted chunk IO.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChunkIoAbortKind {
+    /// The stream reaches its end, for example the peer side is closed.
+    EndOfStream,
+    /// The cancellation token is signalled.
+    Cancelled,
+    /// Any other failure.
+    Fault,
+}
+
+impl ChunkIoAbortKind {
+    /// Classifies any error as `Fault`, which is the default classification
+    /// of the buffer adapters.
+    pub const fn always_fault<B, E>(_: &B, _: &E) -> Self {
+        ChunkIoAbortKind::Fault
+    }
+
+    /// Classifies an error from a closed buffer as `EndOfStream`, and any
+    /// other error as `Fault`.
+    pub fn from_closed_state<B, E>(buffer: &B, _: &E) -> Self
+    where
+        B: TrBuffClosed,
+    {
+        if buffer.is_closed() {
+            ChunkIoAbortKind::EndOfStream
+        } else {
+            ChunkIoAbortKind::Fault
+        }
+    }
+}
+
+/// A buffer that tells whether it is closed, so that an error from it can be
+/// classified as the end of stream rather than a failure.
+pub trait TrBuffClosed {
+    /// Whether the buffer is closed, that is no more unit will be appended
+    /// to it, or accepted by it.
+    fn is_closed(&self) -> bool;
 }
 
 /// A reader that is supposed to copy the minimum number of units (for example,
@@ -32,10 +103,45 @@ where
         T: 'a,
         Self: 'a;
 
+    /// Fill the whole target buffer, that is exactly `target.len()` units,
+    /// unless the IO is aborted.
     fn fill_async<'a>(
         &'a mut self,
         target: &'a mut [T],
     ) -> Self::FillAsync<'a>;
+
+    /// Fill the target buffer until at least `min_len` units are copied.
+    ///
+    /// The future completes as soon as the threshold is crossed, with the
+    /// number of units copied, which is between `min_len` and `target.len()`.
+    /// A `min_len` larger than `target.len()` is treated as `target.len()`.
+    ///
+    /// The default implementation fills exactly `min_len` units with
+    /// `fill_async`, so implementors that can copy more in one go should
+    /// override it.
+    #[inline(always)]
+    fn fill_at_least_async<'a>(
+        &'a mut self,
+        target: &'a mut [T],
+        min_len: usize,
+    ) -> Self::FillAsync<'a> {
+        let min_len = cmp::min(min_len, target.len());
+        self.fill_async(&mut target[..min_len])
+    }
+
+    /// Fill whatever is available right now, that is the future completes
+    /// once any unit is copied into the target buffer.
+    ///
+    /// While the future is pending, no unit is copied yet, so that a pending
+    /// fill can be dropped and issued again without losing any unit. The
+    /// wrappers in this crate rely on it to resume across polls.
+    #[inline(always)]
+    fn fill_some_async<'a>(
+        &'a mut self,
+        target: &'a mut [T],
+    ) -> Self::FillAsync<'a> {
+        self.fill_at_least_async(target, 1)
+    }
 }
 
 /// A writer that is supposed to copy the minimum number of units (for example, 
@@ -53,10 +159,45 @@ where
         T: 'a,
         Self: 'a;
 
+    /// Load the whole source buffer, that is exactly `source.len()` units,
+    /// unless the IO is aborted.
     fn load_async<'a>(
         &'a mut self,
         source: &'a [T],
     ) -> Self::LoadAsync<'a>;
+
+    /// Load the source buffer until at least `min_len` units are copied.
+    ///
+    /// The future completes as soon as the threshold is crossed, with the
+    /// number of units copied, which is between `min_len` and `source.len()`.
+    /// A `min_len` larger than `source.len()` is treated as `source.len()`.
+    ///
+    /// The default implementation loads exactly `min_len` units with
+    /// `load_async`, so implementors that can copy more in one go should
+    /// override it.
+    #[inline(always)]
+    fn load_at_least_async<'a>(
+        &'a mut self,
+        source: &'a [T],
+        min_len: usize,
+    ) -> Self::LoadAsync<'a> {
+        let min_len = cmp::min(min_len, source.len());
+        self.load_async(&source[..min_len])
+    }
+
+    /// Load as many units as the writer can accept right now, that is the
+    /// future completes once any unit is copied from the source buffer.
+    ///
+    /// While the future is pending, no unit is copied yet, so that a pending
+    /// load can be dropped and issued again without loading any unit twice.
+    /// The wrappers in this crate rely on it to resume across polls.
+    #[inline(always)]
+    fn load_some_async<'a>(
+        &'a mut self,
+        source: &'a [T],
+    ) -> Self::LoadAsync<'a> {
+        self.load_at_least_async(source, 1)
+    }
 }
 
 #[derive(Debug)]
@@ -66,6 +207,7 @@ where
 {
     perform_len_: usize,
     last_error_: E,
+    kind_: ChunkIoAbortKind,
 }
 
 impl<E> ChunkIoAbort<E>
@@ -73,9 +215,31 @@ where
     E: Error,
 {
     pub const fn new(perform_len: usize, last_error: E) -> Self {
+        let kind = ChunkIoAbortKind::Fault;
+        ChunkIoAbort::with_kind(perform_len, last_error, kind)
+    }
+
+    /// The IO is aborted because the stream reaches its end.
+    pub const fn end_of_stream(perform_len: usize, last_error: E) -> Self {
+        let kind = ChunkIoAbortKind::EndOfStream;
+        ChunkIoAbort::with_kind(perform_len, last_error, kind)
+    }
+
+    /// The IO is aborted because the cancellation token is signalled.
+    pub const fn cancelled(perform_len: usize, last_error: E) -> Self {
+        let kind = ChunkIoAbortKind::Cancelled;
+        ChunkIoAbort::with_kind(perform_len, last_error, kind)
+    }
+
+    pub const fn with_kind(
+        perform_len: usize,
+        last_error: E,
+        kind: ChunkIoAbortKind,
+    ) -> Self {
         ChunkIoAbort {
             perform_len_: perform_len,
             last_error_: last_error,
+            kind_: kind,
         }
     }
 
@@ -86,6 +250,25 @@ where
     pub const fn last_error(&self) -> &E {
         &self.last_error_
     }
+
+    pub fn add_perform_len(self, len: usize) -> Self {
+        ChunkIoAbort {
+            perform_len_: self.perform_len_ + len,
+            ..self
+        }
+    }
+
+    pub const fn kind(&self) -> ChunkIoAbortKind {
+        self.kind_
+    }
+
+    pub const fn is_end_of_stream(&self) -> bool {
+        matches!(self.kind_, ChunkIoAbortKind::EndOfStream)
+    }
+
+    pub const fn is_cancelled(&self) -> bool {
+        matches!(self.kind_, ChunkIoAbortKind::Cancelled)
+    }
 }
 
 impl<E> TrChunkIoAbort for ChunkIoAbort<E>
@@ -103,4 +286,33 @@ where
     fn last_error(&self) -> &E {
         ChunkIoAbort::last_error(self)
     }
+
+    #[inline]
+    fn add_perform_len(self, len: usize) -> Self {
+        ChunkIoAbort::add_perform_len(self, len)
+    }
+
+    #[inline]
+    fn kind(&self) -> ChunkIoAbortKind {
+        ChunkIoAbort::kind(self)
+    }
+}
+
+impl<E> fmt::Display for ChunkIoAbort<E>
+where
+    E: Error,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let action = if self.is_cancelled() { "cancelled" } else { "aborted" };
+        write!(
+            f,
+            "chunk IO {action} after {} units: {}",
+            self.perform_len_, self.last_error_,
+        )
+    }
 }
+
+impl<E> Error for ChunkIoAbort<E>
+where
+    E: Error,
+{}
@@ -0,0 +1,189 @@
+use core::{
+    borrow::BorrowMut,
+    error::Error,
+    fmt,
+    marker::PhantomData,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use abs_buff::x_deps::abs_sync;
+use abs_sync::cancellation::*;
+
+use crate::{
+    chunk_async_::{poll_fill_some, ChunkFillAsync, TrChunkFillWith},
+    ChunkIoAbort, TrChunkFiller, TrChunkIoAbort,
+};
+
+/// Identifies which filler of a `ChainFiller` is aborted.
+#[derive(Debug)]
+pub enum ChainError<A1, A2> {
+    First(A1),
+    Second(A2),
+}
+
+impl<A1, A2> fmt::Display for ChainError<A1, A2>
+where
+    A1: Error,
+    A2: Error,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChainError::First(a) => write!(f, "first filler: {a}"),
+            ChainError::Second(a) => write!(f, "second filler: {a}"),
+        }
+    }
+}
+
+impl<A1, A2> Error for ChainError<A1, A2>
+where
+    A1: Error,
+    A2: Error,
+{}
+
+/// Fills from the first filler until it reaches the end of stream, and then
+/// continues with the second.
+///
+/// A fill may span both fillers, and the `perform_len` of the abort reports
+/// the units filled from both. The end of stream of the second filler is
+/// reported as the end of stream of the chain.
+pub struct ChainFiller<B1, F1, B2, F2, T>
+where
+    B1: BorrowMut<F1>,
+    F1: TrChunkFiller<T>,
+    B2: BorrowMut<F2>,
+    F2: TrChunkFiller<T>,
+    T: Clone,
+{
+    _use_f_: PhantomData<(F1, F2)>,
+    _use_t_: PhantomData<[T]>,
+    first_: B1,
+    second_: B2,
+    first_done_: bool,
+}
+
+impl<B1, F1, B2, F2, T> ChainFiller<B1, F1, B2, F2, T>
+where
+    B1: BorrowMut<F1>,
+    F1: TrChunkFiller<T>,
+    B2: BorrowMut<F2>,
+    F2: TrChunkFiller<T>,
+    T: Clone,
+{
+    pub const fn new(first: B1, second: B2) -> Self {
+        ChainFiller {
+            _use_f_: PhantomData,
+            _use_t_: PhantomData,
+            first_: first,
+            second_: second,
+            first_done_: false,
+        }
+    }
+
+    /// Whether the first filler has reached the end of stream.
+    pub const fn is_first_done(&self) -> bool {
+        self.first_done_
+    }
+
+    pub fn into_inner(self) -> (B1, B2) {
+        (self.first_, self.second_)
+    }
+}
+
+impl<B1, F1, B2, F2, T> TrChunkFiller<T> for ChainFiller<B1, F1, B2, F2, T>
+where
+    B1: BorrowMut<F1>,
+    F1: TrChunkFiller<T>,
+    F1::IoAbort: Error,
+    B2: BorrowMut<F2>,
+    F2: TrChunkFiller<T>,
+    F2::IoAbort: Error,
+    T: Clone,
+{
+    type IoAbort = ChunkIoAbort<ChainError<F1::IoAbort, F2::IoAbort>>;
+    type FillAsync<'a> = ChunkFillAsync<'a, Self, T> where Self: 'a;
+
+    #[inline(always)]
+    fn fill_async<'a>(
+        &'a mut self,
+        target: &'a mut [T],
+    ) -> Self::FillAsync<'a> {
+        ChunkFillAsync::new(self, target)
+    }
+
+    #[inline(always)]
+    fn fill_at_least_async<'a>(
+        &'a mut self,
+        target: &'a mut [T],
+        min_len: usize,
+    ) -> Self::FillAsync<'a> {
+        ChunkFillAsync::new_at_least(self, target, min_len)
+    }
+}
+
+impl<B1, F1, B2, F2, T> TrChunkFillWith<T> for ChainFiller<B1, F1, B2, F2, T>
+where
+    B1: BorrowMut<F1>,
+    F1: TrChunkFiller<T>,
+    F1::IoAbort: Error,
+    B2: BorrowMut<F2>,
+    F2: TrChunkFiller<T>,
+    F2::IoAbort: Error,
+    T: Clone,
+{
+    fn poll_fill_with<C>(
+        &mut self,
+        target: &mut [T],
+        min_len: usize,
+        perform_len: &mut usize,
+        mut cancel: Pin<&mut C>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<usize, Self::IoAbort>>
+    where
+        C: TrCancellationToken,
+    {
+        while *perform_len < min_len {
+            let rest = &mut target[*perform_len..];
+            if !self.first_done_ {
+                let first = self.first_.borrow_mut();
+                let r = poll_fill_some(first, rest, cancel.as_mut(), cx);
+                let Poll::Ready(r) = r else {
+                    return Poll::Pending;
+                };
+                match r {
+                    Result::Ok(0) => break,
+                    Result::Ok(n) => *perform_len += n,
+                    Result::Err(a) if a.is_end_of_stream() => {
+                        *perform_len += a.perform_len();
+                        self.first_done_ = true;
+                    },
+                    Result::Err(a) => {
+                        let n = *perform_len + a.perform_len();
+                        let kind = a.kind();
+                        let e = ChainError::First(a);
+                        let a = ChunkIoAbort::with_kind(n, e, kind);
+                        return Poll::Ready(Result::Err(a));
+                    },
+                }
+            } else {
+                let second = self.second_.borrow_mut();
+                let r = poll_fill_some(second, rest, cancel.as_mut(), cx);
+                let Poll::Ready(r) = r else {
+                    return Poll::Pending;
+                };
+                match r {
+                    Result::Ok(0) => break,
+                    Result::Ok(n) => *perform_len += n,
+                    Result::Err(a) => {
+                        let n = *perform_len + a.perform_len();
+                        let kind = a.kind();
+                        let e = ChainError::Second(a);
+                        let a = ChunkIoAbort::with_kind(n, e, kind);
+                        return Poll::Ready(Result::Err(a));
+                    },
+                }
+            }
+        }
+        Poll::Ready(Result::Ok(*perform_len))
+    }
+}
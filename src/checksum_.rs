@@ -0,0 +1,371 @@
+use core::{error::Error, fmt};
+
+use crate::{TrDigest, TrEndianNum};
+
+/// A running checksum over a stream of bytes, which can be verified against
+/// or written as a trailing checksum by `DigestFiller` and `DigestLoader`.
+pub trait TrChecksum: TrDigest {
+    /// The value of the checksum.
+    type Sum: Copy + Eq + fmt::Debug + TrEndianNum;
+
+    /// The checksum of the bytes fed so far.
+    fn sum(&self) -> Self::Sum;
+
+    /// Starts over as if no bytes were fed.
+    fn reset(&mut self);
+}
+
+/// A CRC-16 with the parameters of the Rocksoft model, where the input and
+/// output are either both reflected or both not.
+#[derive(Clone, Copy, Debug)]
+pub struct Crc16 {
+    poly_: u16,
+    init_: u16,
+    xorout_: u16,
+    reflect_: bool,
+    crc_: u16,
+}
+
+impl Crc16 {
+    /// CRC-16/X-25, the FCS-16 of HDLC (RFC 1662), sent little-endian.
+    pub const X25: Crc16 = Crc16::new(0x1021, 0xFFFF, 0xFFFF, true);
+
+    /// CRC-16/MODBUS, sent little-endian.
+    pub const MODBUS: Crc16 = Crc16::new(0x8005, 0xFFFF, 0x0000, true);
+
+    /// CRC-16/ARC.
+    pub const ARC: Crc16 = Crc16::new(0x8005, 0x0000, 0x0000, true);
+
+    /// CRC-16/CCITT-FALSE, also known as CRC-16/IBM-3740.
+    pub const CCITT_FALSE: Crc16 = Crc16::new(0x1021, 0xFFFF, 0x0000, false);
+
+    /// The `poly` and `init` are in the normal (not reflected) form.
+    pub const fn new(poly: u16, init: u16, xorout: u16, reflect: bool) -> Self {
+        let (poly, init) = if reflect {
+            (poly.reverse_bits(), init.reverse_bits())
+        } else {
+            (poly, init)
+        };
+        Crc16 {
+            poly_: poly,
+            init_: init,
+            xorout_: xorout,
+            reflect_: reflect,
+            crc_: init,
+        }
+    }
+}
+
+impl TrDigest for Crc16 {
+    fn update(&mut self, data: &[u8]) {
+        let mut crc = self.crc_;
+        for &byte in data.iter() {
+            if self.reflect_ {
+                crc ^= byte as u16;
+                for _ in 0..8 {
+                    let carry = crc & 1 != 0;
+                    crc >>= 1;
+                    if carry {
+                        crc ^= self.poly_;
+                    }
+                }
+            } else {
+                crc ^= (byte as u16) << 8;
+                for _ in 0..8 {
+                    let carry = crc & 0x8000 != 0;
+                    crc <<= 1;
+                    if carry {
+                        crc ^= self.poly_;
+                    }
+                }
+            }
+        }
+        self.crc_ = crc;
+    }
+}
+
+impl TrChecksum for Crc16 {
+    type Sum = u16;
+
+    #[inline]
+    fn sum(&self) -> u16 {
+        self.crc_ ^ self.xorout_
+    }
+
+    #[inline]
+    fn reset(&mut self) {
+        self.crc_ = self.init_;
+    }
+}
+
+/// The lookup table of a reflected CRC-32 with the reflected `poly`.
+const fn crc32_table(poly: u32) -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0usize;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut k = 0;
+        while k < 8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ poly } else { crc >> 1 };
+            k += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+static CRC32_TABLE: [u32; 256] = crc32_table(0xEDB8_8320);
+static CRC32C_TABLE: [u32; 256] = crc32_table(0x82F6_3B78);
+
+fn crc32_update(table: &[u32; 256], mut crc: u32, data: &[u8]) -> u32 {
+    for &byte in data.iter() {
+        crc = table[((crc ^ byte as u32) & 0xFF) as usize] ^ (crc >> 8);
+    }
+    crc
+}
+
+/// CRC-32/ISO-HDLC, as used by Ethernet, zlib and PNG.
+#[derive(Clone, Copy, Debug)]
+pub struct Crc32(u32);
+
+impl Crc32 {
+    pub const fn new() -> Self {
+        Crc32(u32::MAX)
+    }
+}
+
+impl Default for Crc32 {
+    fn default() -> Self {
+        Crc32::new()
+    }
+}
+
+impl TrDigest for Crc32 {
+    #[inline]
+    fn update(&mut self, data: &[u8]) {
+        self.0 = crc32_update(&CRC32_TABLE, self.0, data);
+    }
+}
+
+impl TrChecksum for Crc32 {
+    type Sum = u32;
+
+    #[inline]
+    fn sum(&self) -> u32 {
+        !self.0
+    }
+
+    #[inline]
+    fn reset(&mut self) {
+        self.0 = u32::MAX;
+    }
+}
+
+/// CRC-32C (Castagnoli), as used by iSCSI, ext4 and SCTP.
+#[derive(Clone, Copy, Debug)]
+pub struct Crc32c(u32);
+
+impl Crc32c {
+    pub const fn new() -> Self {
+        Crc32c(u32::MAX)
+    }
+}
+
+impl Default for Crc32c {
+    fn default() -> Self {
+        Crc32c::new()
+    }
+}
+
+impl TrDigest for Crc32c {
+    #[inline]
+    fn update(&mut self, data: &[u8]) {
+        self.0 = crc32_update(&CRC32C_TABLE, self.0, data);
+    }
+}
+
+impl TrChecksum for Crc32c {
+    type Sum = u32;
+
+    #[inline]
+    fn sum(&self) -> u32 {
+        !self.0
+    }
+
+    #[inline]
+    fn reset(&mut self) {
+        self.0 = u32::MAX;
+    }
+}
+
+const ADLER32_MOD: u32 = 65521;
+/// The max number of bytes before the sums of Adler-32 may overflow `u32`.
+const ADLER32_NMAX: usize = 5552;
+
+/// Adler-32, as used by zlib, sent big-endian.
+#[derive(Clone, Copy, Debug)]
+pub struct Adler32 {
+    a_: u32,
+    b_: u32,
+}
+
+impl Adler32 {
+    pub const fn new() -> Self {
+        Adler32 { a_: 1, b_: 0 }
+    }
+}
+
+impl Default for Adler32 {
+    fn default() -> Self {
+        Adler32::new()
+    }
+}
+
+impl TrDigest for Adler32 {
+    fn update(&mut self, data: &[u8]) {
+        let (mut a, mut b) = (self.a_, self.b_);
+        for chunk in data.chunks(ADLER32_NMAX) {
+            for &byte in chunk.iter() {
+                a += byte as u32;
+                b += a;
+            }
+            a %= ADLER32_MOD;
+            b %= ADLER32_MOD;
+        }
+        self.a_ = a;
+        self.b_ = b;
+    }
+}
+
+impl TrChecksum for Adler32 {
+    type Sum = u32;
+
+    #[inline]
+    fn sum(&self) -> u32 {
+        (self.b_ << 16) | self.a_
+    }
+
+    #[inline]
+    fn reset(&mut self) {
+        *self = Adler32::new();
+    }
+}
+
+/// The error of verifying a trailing checksum.
+#[derive(Debug)]
+pub enum ChecksumError<S, A> {
+    /// The trailing checksum does not match the one computed.
+    Mismatch {
+        expected: S,
+        computed: S,
+    },
+    /// The IO is aborted on the trailing checksum.
+    Io(A),
+}
+
+impl<S, A> fmt::Display for ChecksumError<S, A>
+where
+    S: fmt::Debug,
+    A: Error,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChecksumError::Mismatch { expected, computed } => write!(
+                f,
+                "checksum mismatch, expected {expected:?}, \
+                computed {computed:?}",
+            ),
+            ChecksumError::Io(a) => write!(f, "{a}"),
+        }
+    }
+}
+
+impl<S, A> Error for ChecksumError<S, A>
+where
+    S: fmt::Debug,
+    A: Error,
+{}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        test_utils_::*, DigestFiller, DigestLoader, Endian, TrChunkFiller,
+        TrChunkLoader,
+    };
+
+    const CHECK_INPUT: &[u8] = b"123456789";
+
+    fn check<K: TrChecksum>(mut checksum: K) -> K::Sum {
+        // Fed in two pieces, to cover the running update.
+        checksum.update(&CHECK_INPUT[..4]);
+        checksum.update(&CHECK_INPUT[4..]);
+        let sum = checksum.sum();
+        checksum.reset();
+        checksum.update(CHECK_INPUT);
+        assert_eq!(checksum.sum(), sum);
+        sum
+    }
+
+    #[test]
+    fn known_answers() {
+        assert_eq!(check(Crc16::X25), 0x906E);
+        assert_eq!(check(Crc16::MODBUS), 0x4B37);
+        assert_eq!(check(Crc16::ARC), 0xBB3D);
+        assert_eq!(check(Crc16::CCITT_FALSE), 0x29B1);
+        assert_eq!(check(Crc32::new()), 0xCBF4_3926);
+        assert_eq!(check(Crc32c::new()), 0xE306_9283);
+        assert_eq!(check(Adler32::new()), 0x091E_01DE);
+    }
+
+    #[test]
+    fn adler32_long_input() {
+        // Long enough for the sums to be reduced in the middle.
+        let data = [0xFFu8; 3 * ADLER32_NMAX + 7];
+        let (mut a, mut b) = (1u64, 0u64);
+        for &byte in data.iter() {
+            a = (a + byte as u64) % ADLER32_MOD as u64;
+            b = (b + a) % ADLER32_MOD as u64;
+        }
+        let mut adler = Adler32::new();
+        adler.update(&data);
+        assert_eq!(adler.sum() as u64, (b << 16) | a);
+    }
+
+    #[test]
+    fn trail_and_verify() {
+        let loader = VecLoader::default();
+        let mut loader = DigestLoader::new(loader, Crc32::new());
+        for message in [CHECK_INPUT, b"abc"] {
+            let load = loader.load_async(message);
+            block_on(load.may_cancel_with(no_cancel())).unwrap();
+            let trail = loader.write_trailing_async(Endian::Big);
+            block_on(trail.may_cancel_with(no_cancel())).unwrap();
+        }
+        let (VecLoader(mut data), _) = loader.into_inner();
+        assert_eq!(&data[9..13], [0xCB, 0xF4, 0x39, 0x26]);
+
+        let filler = SliceFiller::new(&data, 5);
+        let mut filler = DigestFiller::new(filler, Crc32::new());
+        let mut target = [0u8; 9];
+        let fill = filler.fill_async(&mut target);
+        block_on(fill.may_cancel_with(no_cancel())).unwrap();
+        let verify = filler.verify_trailing_async(Endian::Big);
+        block_on(verify.may_cancel_with(no_cancel())).unwrap();
+
+        data[14] ^= 0x01;
+        let filler = SliceFiller::new(&data[13..], 5);
+        let mut filler = DigestFiller::new(filler, Crc32::new());
+        let fill = filler.fill_async(&mut target[..3]);
+        block_on(fill.may_cancel_with(no_cancel())).unwrap();
+        let verify = filler.verify_trailing_async(Endian::Big);
+        let r = block_on(verify.may_cancel_with(no_cancel()));
+        let Result::Err(ChecksumError::Mismatch { expected, computed }) = r
+        else {
+            panic!("the corrupted message is not reported");
+        };
+        assert_ne!(expected, computed);
+        assert_eq!(filler.sum(), Crc32::new().sum());
+    }
+}
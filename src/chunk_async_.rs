@@ -0,0 +1,372 @@
+use core::{
+    cmp,
+    future::{Future, IntoFuture},
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use pin_utils::pin_mut;
+
+use abs_buff::x_deps::abs_sync;
+use abs_sync::{cancellation::*, x_deps::pin_utils};
+
+use crate::{TrChunkFiller, TrChunkLoader};
+
+/// Polls a `fill_some_async` of the filler once.
+///
+/// A pending fill has not copied any unit, so it is dropped here, and issued
+/// again by the next poll of the caller.
+pub(crate) fn poll_fill_some<F, C, T>(
+    filler: &mut F,
+    target: &mut [T],
+    cancel: Pin<&mut C>,
+    cx: &mut Context<'_>,
+) -> Poll<Result<usize, F::IoAbort>>
+where
+    F: TrChunkFiller<T> + ?Sized,
+    C: TrCancellationToken,
+    T: Clone,
+{
+    let fill = filler.fill_some_async(target).may_cancel_with(cancel);
+    pin_mut!(fill);
+    fill.poll(cx)
+}
+
+/// Polls a `load_some_async` of the loader once.
+///
+/// A pending load has not copied any unit, so it is dropped here, and issued
+/// again by the next poll of the caller.
+pub(crate) fn poll_load_some<L, C, T>(
+    loader: &mut L,
+    source: &[T],
+    cancel: Pin<&mut C>,
+    cx: &mut Context<'_>,
+) -> Poll<Result<usize, L::IoAbort>>
+where
+    L: TrChunkLoader<T> + ?Sized,
+    C: TrCancellationToken,
+    T: Clone,
+{
+    let load = loader.load_some_async(source).may_cancel_with(cancel);
+    pin_mut!(load);
+    load.poll(cx)
+}
+
+/// A chunk filler whose fill is written as a poll function, so that its
+/// `FillAsync` can be `ChunkFillAsync`.
+///
+/// The number of units copied into the target is kept in the future, and
+/// passed in as `perform_len` on every poll. Any other progress of a fill
+/// that spans several polls is kept in the filler, and the inner IO pending
+/// on a poll is issued again on the next one.
+pub trait TrChunkFillWith<T = u8>: TrChunkFiller<T>
+where
+    T: Clone,
+{
+    /// Polls the fill of `target`, of which `perform_len` units are already
+    /// copied, until at least `min_len` units are copied.
+    ///
+    /// The `min_len` is not larger than `target.len()`.
+    fn poll_fill_with<C>(
+        &mut self,
+        target: &mut [T],
+        min_len: usize,
+        perform_len: &mut usize,
+        cancel: Pin<&mut C>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<usize, Self::IoAbort>>
+    where
+        C: TrCancellationToken;
+}
+
+/// A chunk loader whose load is written as a poll function, so that its
+/// `LoadAsync` can be `ChunkLoadAsync`.
+///
+/// The number of units copied from the source is kept in the future, and
+/// passed in as `perform_len` on every poll. Any other progress of a load
+/// that spans several polls is kept in the loader, and the inner IO pending
+/// on a poll is issued again on the next one.
+pub trait TrChunkLoadWith<T = u8>: TrChunkLoader<T>
+where
+    T: Clone,
+{
+    /// Polls the load of `source`, of which `perform_len` units are already
+    /// copied, until at least `min_len` units are copied.
+    ///
+    /// The `min_len` is not larger than `source.len()`.
+    fn poll_load_with<C>(
+        &mut self,
+        source: &[T],
+        min_len: usize,
+        perform_len: &mut usize,
+        cancel: Pin<&mut C>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<usize, Self::IoAbort>>
+    where
+        C: TrCancellationToken;
+}
+
+pub struct ChunkFillAsync<'a, F, T>
+where
+    F: TrChunkFillWith<T> + ?Sized,
+    T: Clone,
+{
+    filler_: &'a mut F,
+    target_: &'a mut [T],
+    min_len_: usize,
+}
+
+impl<'a, F, T> ChunkFillAsync<'a, F, T>
+where
+    F: TrChunkFillWith<T> + ?Sized,
+    T: Clone,
+{
+    pub fn new(filler: &'a mut F, target: &'a mut [T]) -> Self {
+        let min_len = target.len();
+        ChunkFillAsync::new_at_least(filler, target, min_len)
+    }
+
+    pub fn new_at_least(
+        filler: &'a mut F,
+        target: &'a mut [T],
+        min_len: usize,
+    ) -> Self {
+        let min_len = cmp::min(min_len, target.len());
+        ChunkFillAsync {
+            filler_: filler,
+            target_: target,
+            min_len_: min_len,
+        }
+    }
+
+    pub fn may_cancel_with<C>(
+        self,
+        cancel: Pin<&'a mut C>,
+    ) -> ChunkFillFuture<'a, C, F, T>
+    where
+        C: TrCancellationToken,
+    {
+        ChunkFillFuture::new(self, cancel)
+    }
+}
+
+impl<'a, F, T> IntoFuture for ChunkFillAsync<'a, F, T>
+where
+    F: TrChunkFillWith<T> + ?Sized,
+    T: Clone,
+{
+    type IntoFuture = ChunkFillFuture<'a, NonCancellableToken, F, T>;
+    type Output = <Self::IntoFuture as Future>::Output;
+
+    fn into_future(self) -> Self::IntoFuture {
+        let cancel = NonCancellableToken::pinned();
+        ChunkFillFuture::new(self, cancel)
+    }
+}
+
+impl<'a, F, T> TrIntoFutureMayCancel<'a> for ChunkFillAsync<'a, F, T>
+where
+    F: TrChunkFillWith<T> + ?Sized,
+    T: Clone,
+{
+    type MayCancelOutput = <Self as IntoFuture>::Output;
+
+    #[inline(always)]
+    fn may_cancel_with<C>(
+        self,
+        cancel: Pin<&'a mut C>,
+    ) -> impl Future<Output = Self::MayCancelOutput>
+    where
+        C: TrCancellationToken,
+    {
+        ChunkFillAsync::may_cancel_with(self, cancel)
+    }
+}
+
+/// The future of a fill by a `TrChunkFillWith` filler.
+pub struct ChunkFillFuture<'a, C, F, T>
+where
+    C: TrCancellationToken,
+    F: TrChunkFillWith<T> + ?Sized,
+    T: Clone,
+{
+    filler_: &'a mut F,
+    target_: &'a mut [T],
+    min_len_: usize,
+    perform_len_: usize,
+    cancel_: Pin<&'a mut C>,
+}
+
+impl<'a, C, F, T> ChunkFillFuture<'a, C, F, T>
+where
+    C: TrCancellationToken,
+    F: TrChunkFillWith<T> + ?Sized,
+    T: Clone,
+{
+    pub fn new(fill: ChunkFillAsync<'a, F, T>, cancel: Pin<&'a mut C>) -> Self {
+        ChunkFillFuture {
+            filler_: fill.filler_,
+            target_: fill.target_,
+            min_len_: fill.min_len_,
+            perform_len_: 0usize,
+            cancel_: cancel,
+        }
+    }
+
+    /// Number of units that has been copied into the target so far.
+    pub fn perform_len(&self) -> usize {
+        self.perform_len_
+    }
+}
+
+impl<C, F, T> Future for ChunkFillFuture<'_, C, F, T>
+where
+    C: TrCancellationToken,
+    F: TrChunkFillWith<T> + ?Sized,
+    T: Clone,
+{
+    type Output = Result<usize, F::IoAbort>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        this.filler_.poll_fill_with(
+            this.target_,
+            this.min_len_,
+            &mut this.perform_len_,
+            this.cancel_.as_mut(),
+            cx,
+        )
+    }
+}
+
+pub struct ChunkLoadAsync<'a, L, T>
+where
+    L: TrChunkLoadWith<T> + ?Sized,
+    T: Clone,
+{
+    loader_: &'a mut L,
+    source_: &'a [T],
+    min_len_: usize,
+}
+
+impl<'a, L, T> ChunkLoadAsync<'a, L, T>
+where
+    L: TrChunkLoadWith<T> + ?Sized,
+    T: Clone,
+{
+    pub fn new(loader: &'a mut L, source: &'a [T]) -> Self {
+        ChunkLoadAsync::new_at_least(loader, source, source.len())
+    }
+
+    pub fn new_at_least(
+        loader: &'a mut L,
+        source: &'a [T],
+        min_len: usize,
+    ) -> Self {
+        let min_len = cmp::min(min_len, source.len());
+        ChunkLoadAsync {
+            loader_: loader,
+            source_: source,
+            min_len_: min_len,
+        }
+    }
+
+    pub fn may_cancel_with<C>(
+        self,
+        cancel: Pin<&'a mut C>,
+    ) -> ChunkLoadFuture<'a, C, L, T>
+    where
+        C: TrCancellationToken,
+    {
+        ChunkLoadFuture::new(self, cancel)
+    }
+}
+
+impl<'a, L, T> IntoFuture for ChunkLoadAsync<'a, L, T>
+where
+    L: TrChunkLoadWith<T> + ?Sized,
+    T: Clone,
+{
+    type IntoFuture = ChunkLoadFuture<'a, NonCancellableToken, L, T>;
+    type Output = <Self::IntoFuture as Future>::Output;
+
+    fn into_future(self) -> Self::IntoFuture {
+        let cancel = NonCancellableToken::pinned();
+        ChunkLoadFuture::new(self, cancel)
+    }
+}
+
+impl<'a, L, T> TrIntoFutureMayCancel<'a> for ChunkLoadAsync<'a, L, T>
+where
+    L: TrChunkLoadWith<T> + ?Sized,
+    T: Clone,
+{
+    type MayCancelOutput = <Self as IntoFuture>::Output;
+
+    #[inline(always)]
+    fn may_cancel_with<C>(
+        self,
+        cancel: Pin<&'a mut C>,
+    ) -> impl Future<Output = Self::MayCancelOutput>
+    where
+        C: TrCancellationToken,
+    {
+        ChunkLoadAsync::may_cancel_with(self, cancel)
+    }
+}
+
+/// The future of a load by a `TrChunkLoadWith` loader.
+pub struct ChunkLoadFuture<'a, C, L, T>
+where
+    C: TrCancellationToken,
+    L: TrChunkLoadWith<T> + ?Sized,
+    T: Clone,
+{
+    loader_: &'a mut L,
+    source_: &'a [T],
+    min_len_: usize,
+    perform_len_: usize,
+    cancel_: Pin<&'a mut C>,
+}
+
+impl<'a, C, L, T> ChunkLoadFuture<'a, C, L, T>
+where
+    C: TrCancellationToken,
+    L: TrChunkLoadWith<T> + ?Sized,
+    T: Clone,
+{
+    pub fn new(load: ChunkLoadAsync<'a, L, T>, cancel: Pin<&'a mut C>) -> Self {
+        ChunkLoadFuture {
+            loader_: load.loader_,
+            source_: load.source_,
+            min_len_: load.min_len_,
+            perform_len_: 0usize,
+            cancel_: cancel,
+        }
+    }
+
+    /// Number of units that has been copied from the source so far.
+    pub fn perform_len(&self) -> usize {
+        self.perform_len_
+    }
+}
+
+impl<C, L, T> Future for ChunkLoadFuture<'_, C, L, T>
+where
+    C: TrCancellationToken,
+    L: TrChunkLoadWith<T> + ?Sized,
+    T: Clone,
+{
+    type Output = Result<usize, L::IoAbort>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        this.loader_.poll_load_with(
+            this.source_,
+            this.min_len_,
+            &mut this.perform_len_,
+            this.cancel_.as_mut(),
+            cx,
+        )
+    }
+}
@@ -0,0 +1,656 @@
+use core::{
+    borrow::BorrowMut,
+    error::Error,
+    fmt,
+    future::{Future, IntoFuture},
+    marker::PhantomData,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use abs_buff::x_deps::abs_sync;
+use abs_sync::cancellation::*;
+
+use crate::{
+    chunk_async_::{
+        poll_load_some,
+        ChunkFillAsync, ChunkLoadAsync, TrChunkFillWith, TrChunkLoadWith,
+    },
+    stuff_::StuffStage,
+    ChunkIoAbort, TrChunkFiller, TrChunkIoAbort, TrChunkLoader,
+};
+
+/// The max number of data units in a COBS block.
+const COBS_BLOCK_LEN: usize = 254;
+
+/// The error of COBS encoding or decoding.
+#[derive(Debug)]
+pub enum CobsError<A> {
+    /// The frame ends in the middle of a block. The terminator is consumed,
+    /// and the next fill starts with the next frame.
+    Malformed,
+    /// The frame ends before the fill is satisfied. The terminator is
+    /// consumed, and the next fill starts with the next frame.
+    EndOfFrame,
+    /// The inner filler or loader is aborted.
+    Io(A),
+}
+
+impl<A> fmt::Display for CobsError<A>
+where
+    A: Error,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CobsError::Malformed => write!(f, "malformed COBS frame"),
+            CobsError::EndOfFrame => write!(f, "end of COBS frame"),
+            CobsError::Io(a) => write!(f, "{a}"),
+        }
+    }
+}
+
+impl<A> Error for CobsError<A>
+where
+    A: Error,
+{}
+
+/// COBS-encodes the units loaded through it into the inner loader.
+///
+/// The units are buffered up to a block of 254 units, so the units loaded
+/// are not necessarily passed to the inner loader until the frame is ended
+/// by `finish_frame_async`, which also emits the `0x00` terminator. If the
+/// inner loader is aborted, the unfinished block is kept and resumed by the
+/// next load or finish.
+pub struct CobsEncodeLoader<BL, L>
+where
+    BL: BorrowMut<L>,
+    L: TrChunkLoader<u8>,
+{
+    _use_l_: PhantomData<L>,
+    loader_: BL,
+    block_: [u8; COBS_BLOCK_LEN + 2],
+    block_len_: usize,
+    seal_len_: usize,
+    emit_len_: usize,
+}
+
+impl<BL, L> CobsEncodeLoader<BL, L>
+where
+    BL: BorrowMut<L>,
+    L: TrChunkLoader<u8>,
+{
+    pub const fn new(loader: BL) -> Self {
+        CobsEncodeLoader {
+            _use_l_: PhantomData,
+            loader_: loader,
+            block_: [0u8; COBS_BLOCK_LEN + 2],
+            block_len_: 0usize,
+            seal_len_: 0usize,
+            emit_len_: 0usize,
+        }
+    }
+
+    /// Emits the buffered units of the frame and the terminator.
+    pub fn finish_frame_async(&mut self) -> CobsFinishAsync<'_, BL, L> {
+        CobsFinishAsync::new(self)
+    }
+
+    /// Returns the inner loader, discarding the unfinished frame.
+    pub fn into_inner(self) -> BL {
+        self.loader_
+    }
+
+    /// Prepends the code to the buffered block and optionally appends the
+    /// terminator, ready to be emitted.
+    fn seal_(&mut self, code: u8, terminate: bool) {
+        self.block_[0] = code;
+        let mut seal_len = self.block_len_ + 1;
+        if terminate {
+            self.block_[seal_len] = 0;
+            seal_len += 1;
+        }
+        self.block_len_ = 0;
+        self.seal_len_ = seal_len;
+        self.emit_len_ = 0;
+    }
+
+    /// Polls the emission of the sealed block.
+    fn poll_emit_<C>(
+        &mut self,
+        mut cancel: Pin<&mut C>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), L::IoAbort>>
+    where
+        C: TrCancellationToken,
+    {
+        let loader = self.loader_.borrow_mut();
+        while self.emit_len_ < self.seal_len_ {
+            let source = &self.block_[self.emit_len_..self.seal_len_];
+            let r = poll_load_some(loader, source, cancel.as_mut(), cx);
+            let Poll::Ready(r) = r else {
+                return Poll::Pending;
+            };
+            match r {
+                Result::Ok(n) => self.emit_len_ += n,
+                Result::Err(a) => {
+                    self.emit_len_ += a.perform_len();
+                    return Poll::Ready(Result::Err(a));
+                },
+            }
+        }
+        self.seal_len_ = 0;
+        self.emit_len_ = 0;
+        Poll::Ready(Result::Ok(()))
+    }
+
+    fn poll_finish_<C>(
+        &mut self,
+        mut cancel: Pin<&mut C>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), L::IoAbort>>
+    where
+        C: TrCancellationToken,
+    {
+        if self.seal_len_ > 0 {
+            // Only the terminator is zero in a sealed block.
+            let terminated = self.block_[self.seal_len_ - 1] == 0;
+            let r = self.poll_emit_(cancel.as_mut(), cx);
+            let Poll::Ready(r) = r else {
+                return Poll::Pending;
+            };
+            if r.is_err() || terminated {
+                return Poll::Ready(r);
+            }
+        }
+        let code = if self.block_len_ == COBS_BLOCK_LEN {
+            u8::MAX
+        } else {
+            self.block_len_ as u8 + 1
+        };
+        self.seal_(code, true);
+        self.poll_emit_(cancel, cx)
+    }
+}
+
+impl<BL, L> TrChunkLoader<u8> for CobsEncodeLoader<BL, L>
+where
+    BL: BorrowMut<L>,
+    L: TrChunkLoader<u8>,
+    L::IoAbort: Error,
+{
+    type IoAbort = ChunkIoAbort<CobsError<L::IoAbort>>;
+    type LoadAsync<'a> = ChunkLoadAsync<'a, Self, u8> where Self: 'a;
+
+    #[inline(always)]
+    fn load_async<'a>(
+        &'a mut self,
+        source: &'a [u8],
+    ) -> Self::LoadAsync<'a> {
+        ChunkLoadAsync::new(self, source)
+    }
+
+    #[inline(always)]
+    fn load_at_least_async<'a>(
+        &'a mut self,
+        source: &'a [u8],
+        min_len: usize,
+    ) -> Self::LoadAsync<'a> {
+        ChunkLoadAsync::new_at_least(self, source, min_len)
+    }
+}
+
+impl<BL, L> TrChunkLoadWith<u8> for CobsEncodeLoader<BL, L>
+where
+    BL: BorrowMut<L>,
+    L: TrChunkLoader<u8>,
+    L::IoAbort: Error,
+{
+    fn poll_load_with<C>(
+        &mut self,
+        source: &[u8],
+        min_len: usize,
+        perform_len: &mut usize,
+        mut cancel: Pin<&mut C>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<usize, Self::IoAbort>>
+    where
+        C: TrCancellationToken,
+    {
+        while *perform_len < source.len() {
+            if self.seal_len_ > 0 {
+                if *perform_len >= min_len {
+                    break;
+                }
+                let r = self.poll_emit_(cancel.as_mut(), cx);
+                let Poll::Ready(r) = r else {
+                    return Poll::Pending;
+                };
+                if let Result::Err(a) = r {
+                    let kind = a.kind();
+                    let e = CobsError::Io(a);
+                    return Poll::Ready(Result::Err(
+                        ChunkIoAbort::with_kind(*perform_len, e, kind),
+                    ));
+                }
+            }
+            if self.block_len_ == COBS_BLOCK_LEN {
+                self.seal_(u8::MAX, false);
+                continue;
+            }
+            let byte = source[*perform_len];
+            if byte == 0 {
+                self.seal_(self.block_len_ as u8 + 1, false);
+            } else {
+                self.block_len_ += 1;
+                self.block_[self.block_len_] = byte;
+            }
+            *perform_len += 1;
+        }
+        Poll::Ready(Result::Ok(*perform_len))
+    }
+}
+
+pub struct CobsFinishAsync<'a, BL, L>
+where
+    BL: BorrowMut<L>,
+    L: TrChunkLoader<u8>,
+{
+    loader_: &'a mut CobsEncodeLoader<BL, L>,
+}
+
+impl<'a, BL, L> CobsFinishAsync<'a, BL, L>
+where
+    BL: BorrowMut<L>,
+    L: TrChunkLoader<u8>,
+{
+    pub fn new(loader: &'a mut CobsEncodeLoader<BL, L>) -> Self {
+        CobsFinishAsync { loader_: loader }
+    }
+
+    pub fn may_cancel_with<C>(
+        self,
+        cancel: Pin<&'a mut C>,
+    ) -> CobsFinishFuture<'a, C, BL, L>
+    where
+        C: TrCancellationToken,
+    {
+        CobsFinishFuture::new(self, cancel)
+    }
+}
+
+impl<'a, BL, L> IntoFuture for CobsFinishAsync<'a, BL, L>
+where
+    BL: BorrowMut<L>,
+    L: TrChunkLoader<u8>,
+{
+    type IntoFuture = CobsFinishFuture<'a, NonCancellableToken, BL, L>;
+    type Output = <Self::IntoFuture as Future>::Output;
+
+    fn into_future(self) -> Self::IntoFuture {
+        let cancel = NonCancellableToken::pinned();
+        CobsFinishFuture::new(self, cancel)
+    }
+}
+
+impl<'a, BL, L> TrIntoFutureMayCancel<'a> for CobsFinishAsync<'a, BL, L>
+where
+    BL: BorrowMut<L>,
+    L: TrChunkLoader<u8>,
+{
+    type MayCancelOutput = <Self as IntoFuture>::Output;
+
+    #[inline(always)]
+    fn may_cancel_with<C>(
+        self,
+        cancel: Pin<&'a mut C>,
+    ) -> impl Future<Output = Self::MayCancelOutput>
+    where
+        C: TrCancellationToken,
+    {
+        CobsFinishAsync::may_cancel_with(self, cancel)
+    }
+}
+
+/// The future that finishes the frame of a `CobsEncodeLoader`.
+///
+/// The units of the frame left to emit are kept in the loader, so that a
+/// finish that spans several wakeups resumes from where it stops.
+pub struct CobsFinishFuture<'a, C, BL, L>
+where
+    C: TrCancellationToken,
+    BL: BorrowMut<L>,
+    L: TrChunkLoader<u8>,
+{
+    loader_: &'a mut CobsEncodeLoader<BL, L>,
+    cancel_: Pin<&'a mut C>,
+}
+
+impl<'a, C, BL, L> CobsFinishFuture<'a, C, BL, L>
+where
+    C: TrCancellationToken,
+    BL: BorrowMut<L>,
+    L: TrChunkLoader<u8>,
+{
+    pub fn new(
+        finish: CobsFinishAsync<'a, BL, L>,
+        cancel: Pin<&'a mut C>,
+    ) -> Self {
+        CobsFinishFuture {
+            loader_: finish.loader_,
+            cancel_: cancel,
+        }
+    }
+}
+
+impl<C, BL, L> Future for CobsFinishFuture<'_, C, BL, L>
+where
+    C: TrCancellationToken,
+    BL: BorrowMut<L>,
+    L: TrChunkLoader<u8>,
+{
+    type Output = Result<(), L::IoAbort>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        this.loader_.poll_finish_(this.cancel_.as_mut(), cx)
+    }
+}
+
+/// Decodes COBS frames from the inner filler.
+///
+/// The encoded units are filled from the inner filler in chunks, and those
+/// after the end of a frame are kept for the next fill. If the inner filler
+/// fills nothing, the fill completes with the units decoded so far. The
+/// decoded units of a frame are filled into the target, and a fill never
+/// crosses the end of a frame. If the frame ends before the fill is
+/// satisfied, the fill is aborted with `CobsError::EndOfFrame`, and the
+/// `perform_len` reports the units of the frame filled. The `0x00` units
+/// between frames are skipped.
+pub struct CobsDecodeFiller<BF, F>
+where
+    BF: BorrowMut<F>,
+    F: TrChunkFiller<u8>,
+{
+    _use_f_: PhantomData<F>,
+    filler_: BF,
+    stage_: StuffStage,
+    remain_len_: u8,
+    zero_pending_: bool,
+    in_frame_: bool,
+}
+
+impl<BF, F> CobsDecodeFiller<BF, F>
+where
+    BF: BorrowMut<F>,
+    F: TrChunkFiller<u8>,
+{
+    pub const fn new(filler: BF) -> Self {
+        CobsDecodeFiller {
+            _use_f_: PhantomData,
+            filler_: filler,
+            stage_: StuffStage::new(),
+            remain_len_: 0u8,
+            zero_pending_: false,
+            in_frame_: false,
+        }
+    }
+
+    /// Whether the fills have started decoding a frame that is not ended yet.
+    pub const fn is_in_frame(&self) -> bool {
+        self.in_frame_
+    }
+
+    /// Number of units filled from the inner filler but not yet decoded.
+    pub const fn staged_len(&self) -> usize {
+        self.stage_.staged_len()
+    }
+
+    /// Returns the inner filler, dropping the units not yet decoded.
+    pub fn into_inner(self) -> BF {
+        self.filler_
+    }
+
+    fn end_frame_(&mut self) {
+        self.remain_len_ = 0;
+        self.zero_pending_ = false;
+        self.in_frame_ = false;
+    }
+}
+
+impl<BF, F> TrChunkFiller<u8> for CobsDecodeFiller<BF, F>
+where
+    BF: BorrowMut<F>,
+    F: TrChunkFiller<u8>,
+    F::IoAbort: Error,
+{
+    type IoAbort = ChunkIoAbort<CobsError<F::IoAbort>>;
+    type FillAsync<'a> = ChunkFillAsync<'a, Self, u8> where Self: 'a;
+
+    #[inline(always)]
+    fn fill_async<'a>(
+        &'a mut self,
+        target: &'a mut [u8],
+    ) -> Self::FillAsync<'a> {
+        ChunkFillAsync::new(self, target)
+    }
+
+    #[inline(always)]
+    fn fill_at_least_async<'a>(
+        &'a mut self,
+        target: &'a mut [u8],
+        min_len: usize,
+    ) -> Self::FillAsync<'a> {
+        ChunkFillAsync::new_at_least(self, target, min_len)
+    }
+}
+
+impl<BF, F> TrChunkFillWith<u8> for CobsDecodeFiller<BF, F>
+where
+    BF: BorrowMut<F>,
+    F: TrChunkFiller<u8>,
+    F::IoAbort: Error,
+{
+    fn poll_fill_with<C>(
+        &mut self,
+        target: &mut [u8],
+        min_len: usize,
+        perform_len: &mut usize,
+        mut cancel: Pin<&mut C>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<usize, Self::IoAbort>>
+    where
+        C: TrCancellationToken,
+    {
+        while *perform_len < min_len {
+            let Option::Some(byte) = self.stage_.pop() else {
+                let filler = self.filler_.borrow_mut();
+                let r = self.stage_.poll_fill(filler, cancel.as_mut(), cx);
+                let Poll::Ready(r) = r else {
+                    return Poll::Pending;
+                };
+                match r {
+                    Result::Ok(0) => break,
+                    Result::Ok(_) => continue,
+                    Result::Err(a) => {
+                        let kind = a.kind();
+                        let e = CobsError::Io(a);
+                        return Poll::Ready(Result::Err(
+                            ChunkIoAbort::with_kind(*perform_len, e, kind),
+                        ));
+                    },
+                }
+            };
+            if self.remain_len_ > 0 {
+                if byte == 0 {
+                    self.end_frame_();
+                    return Poll::Ready(Result::Err(
+                        ChunkIoAbort::new(*perform_len, CobsError::Malformed),
+                    ));
+                }
+                target[*perform_len] = byte;
+                *perform_len += 1;
+                self.remain_len_ -= 1;
+            } else if byte == 0 {
+                if self.in_frame_ {
+                    self.end_frame_();
+                    return Poll::Ready(Result::Err(
+                        ChunkIoAbort::end_of_stream(
+                            *perform_len,
+                            CobsError::EndOfFrame,
+                        ),
+                    ));
+                }
+            } else {
+                if self.zero_pending_ {
+                    target[*perform_len] = 0;
+                    *perform_len += 1;
+                }
+                self.remain_len_ = byte - 1;
+                self.zero_pending_ = byte != u8::MAX;
+                self.in_frame_ = true;
+            }
+        }
+        Poll::Ready(Result::Ok(*perform_len))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::vec::Vec;
+
+    use super::*;
+    use crate::test_utils_::*;
+
+    fn encode(frames: &[&[u8]]) -> Vec<u8> {
+        let mut encoder = CobsEncodeLoader::new(VecLoader::default());
+        for frame in frames {
+            let n = block_on(
+                encoder.load_async(frame).may_cancel_with(no_cancel()),
+            );
+            assert_eq!(n.unwrap(), frame.len());
+            block_on(encoder.finish_frame_async().may_cancel_with(no_cancel()))
+                .unwrap();
+        }
+        encoder.into_inner().0
+    }
+
+    /// Decodes the next frame, which is ended before the target is full.
+    fn decode_frame<F>(
+        decoder: &mut CobsDecodeFiller<F, F>,
+        target: &mut [u8],
+    ) -> Result<usize, ChunkIoAbort<CobsError<F::IoAbort>>>
+    where
+        F: TrChunkFiller<u8>,
+        F::IoAbort: Error,
+    {
+        let r = block_on(
+            decoder.fill_async(target).may_cancel_with(no_cancel()),
+        );
+        let a = r.unwrap_err();
+        match a.last_error() {
+            CobsError::EndOfFrame => Result::Ok(a.perform_len()),
+            _ => Result::Err(a),
+        }
+    }
+
+    #[test]
+    fn encode_zero_runs() {
+        assert_eq!(encode(&[&[]]), [0x01, 0x00]);
+        assert_eq!(encode(&[&[0x00]]), [0x01, 0x01, 0x00]);
+        assert_eq!(encode(&[&[0x00, 0x00]]), [0x01, 0x01, 0x01, 0x00]);
+        assert_eq!(
+            encode(&[&[0x11, 0x22, 0x00, 0x33]]),
+            [0x03, 0x11, 0x22, 0x02, 0x33, 0x00],
+        );
+        assert_eq!(
+            encode(&[&[0x11, 0x00, 0x00, 0x00]]),
+            [0x02, 0x11, 0x01, 0x01, 0x01, 0x00],
+        );
+    }
+
+    #[test]
+    fn encode_block_boundary() {
+        let data: Vec<u8> = (1..=255u8).collect();
+        let mut expected = Vec::from([0xFF]);
+        expected.extend_from_slice(&data[..254]);
+        expected.push(0x00);
+        assert_eq!(encode(&[&data[..254]]), expected);
+
+        let mut expected = Vec::from([0xFF]);
+        expected.extend_from_slice(&data[..254]);
+        expected.extend_from_slice(&[0x02, 0xFF, 0x00]);
+        assert_eq!(encode(&[&data]), expected);
+
+        let mut frame = Vec::from([0x00]);
+        frame.extend_from_slice(&data[..254]);
+        let mut expected = Vec::from([0x01, 0xFF]);
+        expected.extend_from_slice(&data[..254]);
+        expected.push(0x00);
+        assert_eq!(encode(&[&frame]), expected);
+    }
+
+    #[test]
+    fn round_trip() {
+        let long: Vec<u8> = (0..1000usize).map(|i| (i % 7) as u8).collect();
+        let block: Vec<u8> = (1..=254u8).collect();
+        let frames: [&[u8]; 6] =
+            [b"hello", &[], &[0, 0, 0], &long, &block, &[0xFF, 0x00]];
+        let encoded = encode(&frames);
+        let zero_count = encoded.iter().filter(|&&b| b == 0).count();
+        assert_eq!(zero_count, frames.len());
+        for seg_len in [1, 3, 64, 4096] {
+            let mut decoder =
+                CobsDecodeFiller::new(SliceFiller::new(&encoded, seg_len));
+            let mut target = [0u8; 2048];
+            for frame in frames {
+                let n = decode_frame(&mut decoder, &mut target).unwrap();
+                assert_eq!(&target[..n], frame);
+                assert!(!decoder.is_in_frame());
+            }
+            let r = block_on(
+                decoder.fill_async(&mut target).may_cancel_with(no_cancel()),
+            );
+            let a = r.unwrap_err();
+            assert!(matches!(a.last_error(), CobsError::Io(_)));
+            assert!(a.is_end_of_stream());
+        }
+    }
+
+    #[test]
+    fn fill_in_chunks() {
+        let frame: Vec<u8> = (1..=200u8).collect();
+        let encoded = encode(&[&frame, b"next"]);
+        let mut decoder = CobsDecodeFiller::new(SliceFiller::new(&encoded, 64));
+        let mut target = [0u8; 256];
+        let n = block_on(
+            decoder.fill_async(&mut target[..100]).may_cancel_with(no_cancel()),
+        );
+        assert_eq!(n.unwrap(), 100);
+        assert_eq!(&target[..100], &frame[..100]);
+        assert_eq!(decoder.filler_.fill_count(), 2);
+        let n = decode_frame(&mut decoder, &mut target).unwrap();
+        assert_eq!(&target[..n], &frame[100..]);
+        let n = decode_frame(&mut decoder, &mut target).unwrap();
+        assert_eq!(&target[..n], b"next");
+        assert_eq!(decoder.filler_.fill_count(), 4);
+    }
+
+    #[test]
+    fn malformed() {
+        // The frame ends in the middle of a block, followed by a valid one.
+        let encoded = [0x00, 0x04, 0x11, 0x22, 0x00, 0x02, 0x33, 0x00];
+        let mut decoder = CobsDecodeFiller::new(SliceFiller::new(&encoded, 3));
+        let mut target = [0u8; 16];
+        let r = block_on(
+            decoder.fill_async(&mut target).may_cancel_with(no_cancel()),
+        );
+        let a = r.unwrap_err();
+        assert!(matches!(a.last_error(), CobsError::Malformed));
+        assert_eq!(a.perform_len(), 2);
+        assert!(!decoder.is_in_frame());
+        let n = decode_frame(&mut decoder, &mut target).unwrap();
+        assert_eq!(&target[..n], [0x33]);
+        assert_eq!(decoder.staged_len(), 0);
+        assert_eq!(decoder.filler_.remain_len(), 0);
+    }
+}
@@ -0,0 +1,616 @@
+use core::{
+    borrow::{Borrow, BorrowMut},
+    cmp,
+    error::Error,
+    fmt,
+    future::{Future, IntoFuture},
+    marker::PhantomData,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use pin_utils::pin_mut;
+
+use abs_buff::{x_deps::abs_sync, TrBuffIterPeek, TrBuffIterRead};
+use abs_sync::{cancellation::*, x_deps::pin_utils};
+
+use crate::{ChunkIoAbort, ChunkIoAbortKind, TrBuffClosed};
+
+/// The error of reading a delimited frame.
+#[derive(Debug)]
+pub enum DelimError<PE, RE>
+where
+    PE: Error,
+    RE: Error,
+{
+    /// No delimiter is found within the limit after `scan_len` units are
+    /// scanned. Nothing is consumed from the buffer, unless the reader has
+    /// waited for more units, in which case the first `consume_len` units
+    /// are consumed and filled into the front of the target, as far as they
+    /// fit.
+    Oversize {
+        scan_len: usize,
+        consume_len: usize,
+    },
+    /// The buffer has no more units before the delimiter is found. Reports
+    /// the number of units consumed, which are all the units scanned, and
+    /// are filled into the front of the target, as far as they fit.
+    EndOfStream(usize),
+    /// The peek is aborted while scanning for the delimiter, the
+    /// `perform_len` of the abort reports the number of units consumed.
+    Peek(ChunkIoAbort<PE>),
+    /// The read is aborted while consuming the frame.
+    Read(ChunkIoAbort<RE>),
+}
+
+impl<PE, RE> DelimError<PE, RE>
+where
+    PE: Error,
+    RE: Error,
+{
+    /// The classification of the aborted IO. An oversize frame is classified
+    /// as `ChunkIoAbortKind::Fault`.
+    pub fn kind(&self) -> ChunkIoAbortKind {
+        match self {
+            DelimError::Oversize { .. } => ChunkIoAbortKind::Fault,
+            DelimError::EndOfStream(_) => ChunkIoAbortKind::EndOfStream,
+            DelimError::Peek(a) => a.kind(),
+            DelimError::Read(a) => a.kind(),
+        }
+    }
+}
+
+impl<PE, RE> fmt::Display for DelimError<PE, RE>
+where
+    PE: Error,
+    RE: Error,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DelimError::Oversize { scan_len, .. } => {
+                write!(f, "no delimiter within {scan_len} units")
+            },
+            DelimError::EndOfStream(n) => {
+                write!(f, "end of stream after {n} units without delimiter")
+            },
+            DelimError::Peek(a) => write!(f, "delimiter scan: {a}"),
+            DelimError::Read(a) => write!(f, "frame consume: {a}"),
+        }
+    }
+}
+
+impl<PE, RE> Error for DelimError<PE, RE>
+where
+    PE: Error,
+    RE: Error,
+{}
+
+/// The delimiter given to `DelimFrameReader` is empty, which would end every
+/// frame before its first unit.
+#[derive(Debug)]
+pub struct EmptyDelimError;
+
+impl fmt::Display for EmptyDelimError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "empty delimiter")
+    }
+}
+
+impl Error for EmptyDelimError {}
+
+/// Reads frames terminated by a delimiter from a buffer.
+///
+/// The buffer is peeked for the delimiter first, which can be a single byte
+/// or a multi-byte sequence spanning segment boundaries. Once found, exactly
+/// the frame, including the delimiter, is consumed from the buffer.
+///
+/// When the peek shows no more units than those already scanned, the reader
+/// consumes the scanned units into the target, so that the next peek waits
+/// for the buffer to be appended.
+pub struct DelimFrameReader<B, P, D>
+where
+    B: BorrowMut<P>,
+    P: TrBuffIterPeek<u8> + TrBuffIterRead<u8>,
+    D: Borrow<[u8]>,
+{
+    _use_p_: PhantomData<P>,
+    buffer_: B,
+    delim_: D,
+    max_len_: usize,
+    strip_delim_: bool,
+    classify_peek_: fn(&P, &<P as TrBuffIterPeek<u8>>::Err) -> ChunkIoAbortKind,
+    classify_read_: fn(&P, &<P as TrBuffIterRead<u8>>::Err) -> ChunkIoAbortKind,
+}
+
+impl<B, P, D> DelimFrameReader<B, P, D>
+where
+    B: BorrowMut<P>,
+    P: TrBuffIterPeek<u8> + TrBuffIterRead<u8> + TrBuffClosed,
+    D: Borrow<[u8]>,
+{
+    /// An error from the peek or the read of the buffer is classified as the
+    /// end of stream once the buffer is closed, and as a fault otherwise.
+    pub fn new_with_closed_state(
+        buffer: B,
+        delim: D,
+        max_len: usize,
+    ) -> Result<Self, EmptyDelimError> {
+        DelimFrameReader::new_with_classify(
+            buffer,
+            delim,
+            max_len,
+            ChunkIoAbortKind::from_closed_state,
+            ChunkIoAbortKind::from_closed_state,
+        )
+    }
+}
+
+impl<B, P, D> DelimFrameReader<B, P, D>
+where
+    B: BorrowMut<P>,
+    P: TrBuffIterPeek<u8> + TrBuffIterRead<u8>,
+    D: Borrow<[u8]>,
+{
+    /// Reads frames terminated by `delim`. Frames longer than `max_len`,
+    /// excluding the delimiter, are reported as `DelimError::Oversize`.
+    ///
+    /// Fails if `delim` is empty. Every error from the peek or the read of
+    /// the buffer is classified as a fault, see `new_with_closed_state` and
+    /// `new_with_classify` to tell the end of stream.
+    pub fn new(
+        buffer: B,
+        delim: D,
+        max_len: usize,
+    ) -> Result<Self, EmptyDelimError> {
+        DelimFrameReader::new_with_classify(
+            buffer,
+            delim,
+            max_len,
+            ChunkIoAbortKind::always_fault,
+            ChunkIoAbortKind::always_fault,
+        )
+    }
+
+    /// Create with how an error from the peek and from the read of the buffer
+    /// are classified, for example for a buffer that does not tell whether it
+    /// is closed.
+    ///
+    /// An error while the cancellation token is signalled is always
+    /// classified as `ChunkIoAbortKind::Cancelled`.
+    pub fn new_with_classify(
+        buffer: B,
+        delim: D,
+        max_len: usize,
+        classify_peek: fn(
+            &P,
+            &<P as TrBuffIterPeek<u8>>::Err,
+        ) -> ChunkIoAbortKind,
+        classify_read: fn(
+            &P,
+            &<P as TrBuffIterRead<u8>>::Err,
+        ) -> ChunkIoAbortKind,
+    ) -> Result<Self, EmptyDelimError> {
+        if delim.borrow().is_empty() {
+            return Result::Err(EmptyDelimError);
+        }
+        Result::Ok(DelimFrameReader {
+            _use_p_: PhantomData,
+            buffer_: buffer,
+            delim_: delim,
+            max_len_: max_len,
+            strip_delim_: false,
+            classify_peek_: classify_peek,
+            classify_read_: classify_read,
+        })
+    }
+
+    /// Whether to leave the delimiter out of the frame filled into the target.
+    pub fn with_strip_delim(self, strip_delim: bool) -> Self {
+        DelimFrameReader {
+            strip_delim_: strip_delim,
+            ..self
+        }
+    }
+
+    /// Reads a frame terminated by the delimiter into the front of `target`.
+    ///
+    /// Completes with the length of the frame filled into the target. A frame
+    /// that does not fit in the target is reported as oversize as well.
+    pub fn read_frame_async<'a>(
+        &'a mut self,
+        target: &'a mut [u8],
+    ) -> DelimFrameReadAsync<'a, B, P, D> {
+        DelimFrameReadAsync::new(self, target)
+    }
+
+    pub fn into_inner(self) -> (B, D) {
+        (self.buffer_, self.delim_)
+    }
+}
+
+pub struct DelimFrameReadAsync<'a, B, P, D>
+where
+    B: BorrowMut<P>,
+    P: TrBuffIterPeek<u8> + TrBuffIterRead<u8>,
+    D: Borrow<[u8]>,
+{
+    reader_: &'a mut DelimFrameReader<B, P, D>,
+    target_: &'a mut [u8],
+}
+
+impl<'a, B, P, D> DelimFrameReadAsync<'a, B, P, D>
+where
+    B: BorrowMut<P>,
+    P: TrBuffIterPeek<u8> + TrBuffIterRead<u8>,
+    D: Borrow<[u8]>,
+{
+    pub fn new(
+        reader: &'a mut DelimFrameReader<B, P, D>,
+        target: &'a mut [u8],
+    ) -> Self {
+        DelimFrameReadAsync {
+            reader_: reader,
+            target_: target,
+        }
+    }
+
+    pub fn may_cancel_with<C>(
+        self,
+        cancel: Pin<&'a mut C>,
+    ) -> DelimFrameReadFuture<'a, C, B, P, D>
+    where
+        C: TrCancellationToken,
+    {
+        DelimFrameReadFuture::new(self, cancel)
+    }
+}
+
+impl<'a, B, P, D> IntoFuture for DelimFrameReadAsync<'a, B, P, D>
+where
+    B: BorrowMut<P>,
+    P: TrBuffIterPeek<u8> + TrBuffIterRead<u8>,
+    D: Borrow<[u8]>,
+{
+    type IntoFuture = DelimFrameReadFuture<'a, NonCancellableToken, B, P, D>;
+    type Output = <Self::IntoFuture as Future>::Output;
+
+    fn into_future(self) -> Self::IntoFuture {
+        let cancel = NonCancellableToken::pinned();
+        DelimFrameReadFuture::new(self, cancel)
+    }
+}
+
+impl<'a, B, P, D> TrIntoFutureMayCancel<'a> for DelimFrameReadAsync<'a, B, P, D>
+where
+    B: BorrowMut<P>,
+    P: TrBuffIterPeek<u8> + TrBuffIterRead<u8>,
+    D: Borrow<[u8]>,
+{
+    type MayCancelOutput = <Self as IntoFuture>::Output;
+
+    #[inline(always)]
+    fn may_cancel_with<C>(
+        self,
+        cancel: Pin<&'a mut C>,
+    ) -> impl Future<Output = Self::MayCancelOutput>
+    where
+        C: TrCancellationToken,
+    {
+        DelimFrameReadAsync::may_cancel_with(self, cancel)
+    }
+}
+
+/// The future that reads a delimited frame with a `DelimFrameReader`.
+///
+/// The progress of the search for the delimiter, and of the units consumed
+/// from the buffer, is kept in the future, so that a read that spans several
+/// wakeups resumes from where it stops.
+pub struct DelimFrameReadFuture<'a, C, B, P, D>
+where
+    C: TrCancellationToken,
+    B: BorrowMut<P>,
+    P: TrBuffIterPeek<u8> + TrBuffIterRead<u8>,
+    D: Borrow<[u8]>,
+{
+    reader_: &'a mut DelimFrameReader<B, P, D>,
+    target_: &'a mut [u8],
+    scan_: DelimScan,
+    /// The end of the units to consume before scanning on.
+    consume_to_: Option<usize>,
+    /// The length of the frame found, including the delimiter.
+    frame_len_: Option<usize>,
+    cancel_: Pin<&'a mut C>,
+}
+
+impl<'a, C, B, P, D> DelimFrameReadFuture<'a, C, B, P, D>
+where
+    C: TrCancellationToken,
+    B: BorrowMut<P>,
+    P: TrBuffIterPeek<u8> + TrBuffIterRead<u8>,
+    D: Borrow<[u8]>,
+{
+    pub fn new(
+        read: DelimFrameReadAsync<'a, B, P, D>,
+        cancel: Pin<&'a mut C>,
+    ) -> Self {
+        let reader = read.reader_;
+        let delim = reader.delim_.borrow();
+        let delim_len = if reader.strip_delim_ { 0 } else { delim.len() };
+        let max_len = cmp::min(
+            reader.max_len_,
+            read.target_.len().saturating_sub(delim_len),
+        );
+        let scan_limit = max_len.saturating_add(delim.len());
+        DelimFrameReadFuture {
+            reader_: reader,
+            target_: read.target_,
+            scan_: DelimScan::new(scan_limit),
+            consume_to_: Option::None,
+            frame_len_: Option::None,
+            cancel_: cancel,
+        }
+    }
+}
+
+impl<C, B, P, D> Future for DelimFrameReadFuture<'_, C, B, P, D>
+where
+    C: TrCancellationToken,
+    B: BorrowMut<P>,
+    P: TrBuffIterPeek<u8> + TrBuffIterRead<u8>,
+    D: Borrow<[u8]>,
+{
+    type Output = Result<
+        usize,
+        DelimError<
+            <P as TrBuffIterPeek<u8>>::Err,
+            <P as TrBuffIterRead<u8>>::Err,
+        >,
+    >;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        let reader = &mut *this.reader_;
+        let classify_peek = reader.classify_peek_;
+        let classify_read = reader.classify_read_;
+        let strip_delim = reader.strip_delim_;
+        let delim = reader.delim_.borrow();
+        let buffer = reader.buffer_.borrow_mut();
+        let scan = &mut this.scan_;
+        loop {
+            if let Option::Some(frame_len) = this.frame_len_ {
+                let delim_len = delim.len();
+                let copy_len = if strip_delim {
+                    frame_len - delim_len
+                } else {
+                    frame_len
+                };
+                let r = poll_consume(
+                    buffer,
+                    &mut scan.consume_len_,
+                    frame_len,
+                    &mut this.target_[..copy_len],
+                    classify_read,
+                    this.cancel_.as_mut(),
+                    cx,
+                );
+                let Poll::Ready(r) = r else {
+                    return Poll::Pending;
+                };
+                let r = r.map(|_| copy_len).map_err(DelimError::Read);
+                return Poll::Ready(r);
+            }
+            if let Option::Some(consume_to) = this.consume_to_ {
+                let r = poll_consume(
+                    buffer,
+                    &mut scan.consume_len_,
+                    consume_to,
+                    this.target_,
+                    classify_read,
+                    this.cancel_.as_mut(),
+                    cx,
+                );
+                let Poll::Ready(r) = r else {
+                    return Poll::Pending;
+                };
+                if let Result::Err(a) = r {
+                    return Poll::Ready(Result::Err(DelimError::Read(a)));
+                }
+                this.consume_to_ = Option::None;
+            }
+            let cancel = this.cancel_.as_mut();
+            let r = scan.poll_scan(buffer, delim, cancel, cx);
+            let Poll::Ready(r) = r else {
+                return Poll::Pending;
+            };
+            let step = match r {
+                Result::Ok(step) => step,
+                Result::Err(last_error) => {
+                    let consume_len = scan.consume_len_;
+                    let abort = if this.cancel_.is_cancelled() {
+                        ChunkIoAbort::cancelled(consume_len, last_error)
+                    } else {
+                        let kind = classify_peek(buffer, &last_error);
+                        ChunkIoAbort::with_kind(consume_len, last_error, kind)
+                    };
+                    return Poll::Ready(Result::Err(DelimError::Peek(abort)));
+                },
+            };
+            match step {
+                ScanStep::Found(frame_len) => {
+                    this.frame_len_ = Option::Some(frame_len);
+                },
+                ScanStep::Oversize => {
+                    let e = DelimError::Oversize {
+                        scan_len: scan.scan_len_,
+                        consume_len: scan.consume_len_,
+                    };
+                    return Poll::Ready(Result::Err(e));
+                },
+                ScanStep::Scanned => (),
+                ScanStep::Stalled if scan.scan_len_ == scan.consume_len_ => {
+                    let e = DelimError::EndOfStream(scan.consume_len_);
+                    return Poll::Ready(Result::Err(e));
+                },
+                ScanStep::Stalled => {
+                    this.consume_to_ = Option::Some(scan.scan_len_);
+                },
+            }
+        }
+    }
+}
+
+/// Returns the number of units of `delim` matched after `byte`, given that
+/// `matched` units are matched before `byte`.
+fn advance_match(delim: &[u8], matched: usize, byte: u8) -> usize {
+    if delim[matched] == byte {
+        return matched + 1;
+    }
+    // Fall back to the longest prefix of `delim` that ends with `byte`.
+    (1..=matched)
+        .rev()
+        .find(|&k| {
+            delim[k - 1] == byte
+                && delim[..k - 1] == delim[matched + 1 - k..matched]
+        })
+        .unwrap_or(0)
+}
+
+/// The result of a single peek in search of the delimiter.
+enum ScanStep {
+    /// The delimiter is found, with the length of the frame including it.
+    Found(usize),
+    /// The delimiter is not found within the limit.
+    Oversize,
+    /// More units are scanned, but the delimiter is not found yet.
+    Scanned,
+    /// The peek shows no more units than those already scanned.
+    Stalled,
+}
+
+/// The progress of the search for the delimiter across peeks.
+struct DelimScan {
+    scan_limit_: usize,
+    /// Number of units of the frame scanned.
+    scan_len_: usize,
+    /// Number of units of the frame consumed from the buffer, which are not
+    /// shown by the peek any more.
+    consume_len_: usize,
+    match_len_: usize,
+}
+
+impl DelimScan {
+    const fn new(scan_limit: usize) -> Self {
+        DelimScan {
+            scan_limit_: scan_limit,
+            scan_len_: 0,
+            consume_len_: 0,
+            match_len_: 0,
+        }
+    }
+
+    /// Peeks the buffer once, and scans the units not yet scanned for the
+    /// non-empty `delim`.
+    fn poll_scan<P, C>(
+        &mut self,
+        buffer: &mut P,
+        delim: &[u8],
+        cancel: Pin<&mut C>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<ScanStep, <P as TrBuffIterPeek<u8>>::Err>>
+    where
+        P: TrBuffIterPeek<u8>,
+        C: TrCancellationToken,
+    {
+        if self.scan_len_ >= self.scan_limit_ {
+            return Poll::Ready(Result::Ok(ScanStep::Oversize));
+        }
+        let peek = buffer.peek_async().may_cancel_with(cancel);
+        pin_mut!(peek);
+        let Poll::Ready(r) = peek.poll(cx) else {
+            return Poll::Pending;
+        };
+        let src_iter = match r {
+            Result::Ok(src_iter) => src_iter,
+            Result::Err(e) => return Poll::Ready(Result::Err(e)),
+        };
+        let prev_len = self.scan_len_;
+        let mut skip_len = self.scan_len_ - self.consume_len_;
+        for src in src_iter.into_iter() {
+            let src_len = src.len();
+            if skip_len >= src_len {
+                skip_len -= src_len;
+                continue;
+            }
+            for &byte in src[skip_len..].iter() {
+                if self.scan_len_ >= self.scan_limit_ {
+                    return Poll::Ready(Result::Ok(ScanStep::Oversize));
+                }
+                self.match_len_ = advance_match(delim, self.match_len_, byte);
+                self.scan_len_ += 1;
+                if self.match_len_ == delim.len() {
+                    let step = ScanStep::Found(self.scan_len_);
+                    return Poll::Ready(Result::Ok(step));
+                }
+            }
+            skip_len = 0;
+        }
+        if self.scan_len_ == prev_len {
+            Poll::Ready(Result::Ok(ScanStep::Stalled))
+        } else {
+            Poll::Ready(Result::Ok(ScanStep::Scanned))
+        }
+    }
+}
+
+/// Polls the consumption of the units of a frame from the buffer, from
+/// `perform_len` up to `frame_len`, copying them into the same positions of
+/// `target` as far as it goes, and discarding the rest.
+///
+/// The `perform_len` is advanced as the units are consumed, so that the next
+/// poll resumes from there.
+pub(crate) fn poll_consume<R, C>(
+    buffer: &mut R,
+    perform_len: &mut usize,
+    frame_len: usize,
+    target: &mut [u8],
+    classify: fn(&R, &<R as TrBuffIterRead<u8>>::Err) -> ChunkIoAbortKind,
+    mut cancel: Pin<&mut C>,
+    cx: &mut Context<'_>,
+) -> Poll<Result<(), ChunkIoAbort<<R as TrBuffIterRead<u8>>::Err>>>
+where
+    R: TrBuffIterRead<u8>,
+    C: TrCancellationToken,
+{
+    let last_error = loop {
+        if *perform_len >= frame_len {
+            return Poll::Ready(Result::Ok(()));
+        }
+        let read = buffer
+            .read_async(frame_len - *perform_len)
+            .may_cancel_with(cancel.as_mut());
+        pin_mut!(read);
+        let Poll::Ready(r) = read.poll(cx) else {
+            return Poll::Pending;
+        };
+        let src_iter = match r {
+            Result::Ok(src_iter) => src_iter,
+            Result::Err(last_error) => break last_error,
+        };
+        for src in src_iter.into_iter() {
+            let opr_len = cmp::min(src.len(), frame_len - *perform_len);
+            if *perform_len < target.len() {
+                let copy_len = cmp::min(opr_len, target.len() - *perform_len);
+                let dst = &mut target[*perform_len..*perform_len + copy_len];
+                dst.copy_from_slice(&src[..copy_len]);
+            }
+            *perform_len += opr_len;
+        }
+    };
+    let abort = if cancel.is_cancelled() {
+        ChunkIoAbort::cancelled(*perform_len, last_error)
+    } else {
+        let kind = classify(buffer, &last_error);
+        ChunkIoAbort::with_kind(*perform_len, last_error, kind)
+    };
+    Poll::Ready(Result::Err(abort))
+}
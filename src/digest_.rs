@@ -0,0 +1,693 @@
+use core::{
+    borrow::BorrowMut,
+    cmp,
+    future::{Future, IntoFuture},
+    marker::PhantomData,
+    mem,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use abs_buff::x_deps::abs_sync;
+use abs_sync::cancellation::*;
+
+use crate::{
+    chunk_async_::{
+        poll_fill_some, poll_load_some,
+        ChunkFillAsync, ChunkLoadAsync, TrChunkFillWith, TrChunkLoadWith,
+    },
+    ChecksumError, Endian, TrChecksum, TrChunkFiller, TrChunkIoAbort,
+    TrChunkLoader, TrEndianNum,
+};
+
+/// Anything that digests a stream of bytes incrementally, such as a checksum
+/// or a hash function.
+///
+/// How the digest value is obtained is up to the implementor, see
+/// `TrChecksum` for the checksums of this crate.
+pub trait TrDigest {
+    /// Feeds more bytes into the digest.
+    fn update(&mut self, data: &[u8]);
+}
+
+/// Any RustCrypto hash function is a `TrDigest`.
+#[cfg(feature = "digest")]
+impl<D> TrDigest for D
+where
+    D: digest::Update,
+{
+    #[inline]
+    fn update(&mut self, data: &[u8]) {
+        digest::Update::update(self, data)
+    }
+}
+
+/// Feeds the units filled through it into a digest, such as a checksum or a
+/// hash function.
+///
+/// Only the units actually filled are fed into the digest, including those
+/// filled before an abortion as reported by `perform_len`.
+pub struct DigestFiller<BF, F, D>
+where
+    BF: BorrowMut<F>,
+    F: TrChunkFiller<u8>,
+    D: TrDigest,
+{
+    _use_f_: PhantomData<F>,
+    filler_: BF,
+    digest_: D,
+}
+
+impl<BF, F, D> DigestFiller<BF, F, D>
+where
+    BF: BorrowMut<F>,
+    F: TrChunkFiller<u8>,
+    D: TrDigest,
+{
+    pub const fn new(filler: BF, digest: D) -> Self {
+        DigestFiller {
+            _use_f_: PhantomData,
+            filler_: filler,
+            digest_: digest,
+        }
+    }
+
+    pub const fn digest(&self) -> &D {
+        &self.digest_
+    }
+
+    pub fn digest_mut(&mut self) -> &mut D {
+        &mut self.digest_
+    }
+
+    pub fn into_inner(self) -> (BF, D) {
+        (self.filler_, self.digest_)
+    }
+}
+
+#[cfg(feature = "digest")]
+impl<BF, F, D> DigestFiller<BF, F, D>
+where
+    BF: BorrowMut<F>,
+    F: TrChunkFiller<u8>,
+    D: TrDigest + digest::FixedOutputReset,
+{
+    /// Returns the digest of the units filled so far, and starts over.
+    pub fn finalize_reset(&mut self) -> digest::Output<D> {
+        digest::FixedOutputReset::finalize_fixed_reset(&mut self.digest_)
+    }
+}
+
+impl<BF, F, D> DigestFiller<BF, F, D>
+where
+    BF: BorrowMut<F>,
+    F: TrChunkFiller<u8>,
+    D: TrChecksum,
+{
+    /// The checksum of the units filled since the last reset.
+    pub fn sum(&self) -> D::Sum {
+        self.digest_.sum()
+    }
+
+    pub fn reset_checksum(&mut self) {
+        self.digest_.reset()
+    }
+
+    /// Reads the checksum trailing the units filled, in the byte order of
+    /// `endian`, and compares it with the one computed.
+    ///
+    /// The trailing checksum is not fed into the running checksum. Unless the
+    /// IO is aborted, the running checksum is reset afterwards, ready for
+    /// the next message.
+    pub fn verify_trailing_async(
+        &mut self,
+        endian: Endian,
+    ) -> ChecksumVerifyAsync<'_, BF, F, D> {
+        ChecksumVerifyAsync::new(self, endian)
+    }
+
+    fn verify_(
+        &mut self,
+        bytes: <D::Sum as TrEndianNum>::Bytes,
+        endian: Endian,
+    ) -> Result<(), ChecksumError<D::Sum, F::IoAbort>> {
+        let expected = D::Sum::from_bytes(bytes, endian);
+        let computed = self.digest_.sum();
+        self.digest_.reset();
+        if expected == computed {
+            Result::Ok(())
+        } else {
+            Result::Err(ChecksumError::Mismatch { expected, computed })
+        }
+    }
+}
+
+impl<BF, F, D> TrChunkFiller<u8> for DigestFiller<BF, F, D>
+where
+    BF: BorrowMut<F>,
+    F: TrChunkFiller<u8>,
+    D: TrDigest,
+{
+    type IoAbort = F::IoAbort;
+    type FillAsync<'a> = ChunkFillAsync<'a, Self, u8> where Self: 'a;
+
+    #[inline(always)]
+    fn fill_async<'a>(
+        &'a mut self,
+        target: &'a mut [u8],
+    ) -> Self::FillAsync<'a> {
+        ChunkFillAsync::new(self, target)
+    }
+
+    #[inline(always)]
+    fn fill_at_least_async<'a>(
+        &'a mut self,
+        target: &'a mut [u8],
+        min_len: usize,
+    ) -> Self::FillAsync<'a> {
+        ChunkFillAsync::new_at_least(self, target, min_len)
+    }
+}
+
+impl<BF, F, D> TrChunkFillWith<u8> for DigestFiller<BF, F, D>
+where
+    BF: BorrowMut<F>,
+    F: TrChunkFiller<u8>,
+    D: TrDigest,
+{
+    fn poll_fill_with<C>(
+        &mut self,
+        target: &mut [u8],
+        min_len: usize,
+        perform_len: &mut usize,
+        mut cancel: Pin<&mut C>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<usize, Self::IoAbort>>
+    where
+        C: TrCancellationToken,
+    {
+        let filler = self.filler_.borrow_mut();
+        while *perform_len < min_len {
+            let rest = &mut target[*perform_len..];
+            let r = poll_fill_some(filler, &mut *rest, cancel.as_mut(), cx);
+            let Poll::Ready(r) = r else {
+                return Poll::Pending;
+            };
+            let n = match &r {
+                Result::Ok(n) => *n,
+                Result::Err(a) => a.perform_len(),
+            };
+            let n = cmp::min(n, rest.len());
+            self.digest_.update(&rest[..n]);
+            let done_len = *perform_len;
+            *perform_len += n;
+            match r {
+                Result::Ok(0) => break,
+                Result::Ok(_) => (),
+                Result::Err(a) => {
+                    let a = a.add_perform_len(done_len);
+                    return Poll::Ready(Result::Err(a));
+                },
+            }
+        }
+        Poll::Ready(Result::Ok(*perform_len))
+    }
+}
+
+pub struct ChecksumVerifyAsync<'a, BF, F, D>
+where
+    BF: BorrowMut<F>,
+    F: TrChunkFiller<u8>,
+    D: TrChecksum,
+{
+    filler_: &'a mut DigestFiller<BF, F, D>,
+    endian_: Endian,
+}
+
+impl<'a, BF, F, D> ChecksumVerifyAsync<'a, BF, F, D>
+where
+    BF: BorrowMut<F>,
+    F: TrChunkFiller<u8>,
+    D: TrChecksum,
+{
+    pub fn new(filler: &'a mut DigestFiller<BF, F, D>, endian: Endian) -> Self {
+        ChecksumVerifyAsync {
+            filler_: filler,
+            endian_: endian,
+        }
+    }
+}
+
+impl<'a, BF, F, D> ChecksumVerifyAsync<'a, BF, F, D>
+where
+    BF: BorrowMut<F>,
+    F: TrChunkFiller<u8>,
+    D: TrChecksum,
+{
+    pub fn may_cancel_with<C>(
+        self,
+        cancel: Pin<&'a mut C>,
+    ) -> ChecksumVerifyFuture<'a, C, BF, F, D>
+    where
+        C: TrCancellationToken,
+    {
+        ChecksumVerifyFuture::new(self, cancel)
+    }
+}
+
+impl<'a, BF, F, D> IntoFuture for ChecksumVerifyAsync<'a, BF, F, D>
+where
+    BF: BorrowMut<F>,
+    F: TrChunkFiller<u8>,
+    D: TrChecksum,
+{
+    type IntoFuture =
+        ChecksumVerifyFuture<'a, NonCancellableToken, BF, F, D>;
+    type Output = <Self::IntoFuture as Future>::Output;
+
+    fn into_future(self) -> Self::IntoFuture {
+        let cancel = NonCancellableToken::pinned();
+        ChecksumVerifyFuture::new(self, cancel)
+    }
+}
+
+impl<'a, BF, F, D> TrIntoFutureMayCancel<'a>
+for ChecksumVerifyAsync<'a, BF, F, D>
+where
+    BF: BorrowMut<F>,
+    F: TrChunkFiller<u8>,
+    D: TrChecksum,
+{
+    type MayCancelOutput = <Self as IntoFuture>::Output;
+
+    #[inline(always)]
+    fn may_cancel_with<C>(
+        self,
+        cancel: Pin<&'a mut C>,
+    ) -> impl Future<Output = Self::MayCancelOutput>
+    where
+        C: TrCancellationToken,
+    {
+        ChecksumVerifyAsync::may_cancel_with(self, cancel)
+    }
+}
+
+/// The future that reads and verifies the checksum trailing the units filled.
+///
+/// The bytes of the trailing checksum filled so far are kept in the future,
+/// so that a read that spans several wakeups resumes from where it stops.
+pub struct ChecksumVerifyFuture<'a, C, BF, F, D>
+where
+    C: TrCancellationToken,
+    BF: BorrowMut<F>,
+    F: TrChunkFiller<u8>,
+    D: TrChecksum,
+{
+    filler_: &'a mut DigestFiller<BF, F, D>,
+    endian_: Endian,
+    bytes_: <D::Sum as TrEndianNum>::Bytes,
+    perform_len_: usize,
+    cancel_: Pin<&'a mut C>,
+}
+
+// None of the fields is pinned.
+impl<C, BF, F, D> Unpin for ChecksumVerifyFuture<'_, C, BF, F, D>
+where
+    C: TrCancellationToken,
+    BF: BorrowMut<F>,
+    F: TrChunkFiller<u8>,
+    D: TrChecksum,
+{}
+
+impl<'a, C, BF, F, D> ChecksumVerifyFuture<'a, C, BF, F, D>
+where
+    C: TrCancellationToken,
+    BF: BorrowMut<F>,
+    F: TrChunkFiller<u8>,
+    D: TrChecksum,
+{
+    pub fn new(
+        verify: ChecksumVerifyAsync<'a, BF, F, D>,
+        cancel: Pin<&'a mut C>,
+    ) -> Self {
+        ChecksumVerifyFuture {
+            filler_: verify.filler_,
+            endian_: verify.endian_,
+            bytes_: Default::default(),
+            perform_len_: 0usize,
+            cancel_: cancel,
+        }
+    }
+}
+
+impl<C, BF, F, D> Future for ChecksumVerifyFuture<'_, C, BF, F, D>
+where
+    C: TrCancellationToken,
+    BF: BorrowMut<F>,
+    F: TrChunkFiller<u8>,
+    D: TrChecksum,
+{
+    type Output = Result<(), ChecksumError<D::Sum, F::IoAbort>>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        let filler = this.filler_.filler_.borrow_mut();
+        let bytes = this.bytes_.as_mut();
+        while this.perform_len_ < bytes.len() {
+            let target = &mut bytes[this.perform_len_..];
+            let cancel = this.cancel_.as_mut();
+            let r = poll_fill_some(filler, target, cancel, cx);
+            let Poll::Ready(r) = r else {
+                return Poll::Pending;
+            };
+            match r {
+                Result::Ok(0) => break,
+                Result::Ok(n) => this.perform_len_ += n,
+                Result::Err(a) => {
+                    let a = a.add_perform_len(this.perform_len_);
+                    return Poll::Ready(Result::Err(ChecksumError::Io(a)));
+                },
+            }
+        }
+        let bytes = mem::take(&mut this.bytes_);
+        Poll::Ready(this.filler_.verify_(bytes, this.endian_))
+    }
+}
+
+/// Feeds the units loaded through it into a digest, such as a checksum or a
+/// hash function.
+///
+/// Only the units actually loaded are fed into the digest, including those
+/// loaded before an abortion as reported by `perform_len`.
+pub struct DigestLoader<BL, L, D>
+where
+    BL: BorrowMut<L>,
+    L: TrChunkLoader<u8>,
+    D: TrDigest,
+{
+    _use_l_: PhantomData<L>,
+    loader_: BL,
+    digest_: D,
+}
+
+impl<BL, L, D> DigestLoader<BL, L, D>
+where
+    BL: BorrowMut<L>,
+    L: TrChunkLoader<u8>,
+    D: TrDigest,
+{
+    pub const fn new(loader: BL, digest: D) -> Self {
+        DigestLoader {
+            _use_l_: PhantomData,
+            loader_: loader,
+            digest_: digest,
+        }
+    }
+
+    pub const fn digest(&self) -> &D {
+        &self.digest_
+    }
+
+    pub fn digest_mut(&mut self) -> &mut D {
+        &mut self.digest_
+    }
+
+    pub fn into_inner(self) -> (BL, D) {
+        (self.loader_, self.digest_)
+    }
+}
+
+#[cfg(feature = "digest")]
+impl<BL, L, D> DigestLoader<BL, L, D>
+where
+    BL: BorrowMut<L>,
+    L: TrChunkLoader<u8>,
+    D: TrDigest + digest::FixedOutputReset,
+{
+    /// Returns the digest of the units loaded so far, and starts over.
+    pub fn finalize_reset(&mut self) -> digest::Output<D> {
+        digest::FixedOutputReset::finalize_fixed_reset(&mut self.digest_)
+    }
+}
+
+impl<BL, L, D> DigestLoader<BL, L, D>
+where
+    BL: BorrowMut<L>,
+    L: TrChunkLoader<u8>,
+    D: TrChecksum,
+{
+    /// The checksum of the units loaded since the last reset.
+    pub fn sum(&self) -> D::Sum {
+        self.digest_.sum()
+    }
+
+    pub fn reset_checksum(&mut self) {
+        self.digest_.reset()
+    }
+
+    /// Writes the checksum of the units loaded, in the byte order of
+    /// `endian`.
+    ///
+    /// The trailing checksum is not fed into the running checksum. Unless the
+    /// IO is aborted, the running checksum is reset afterwards, ready for
+    /// the next message.
+    pub fn write_trailing_async(
+        &mut self,
+        endian: Endian,
+    ) -> ChecksumTrailAsync<'_, BL, L, D> {
+        ChecksumTrailAsync::new(self, endian)
+    }
+
+}
+
+impl<BL, L, D> TrChunkLoader<u8> for DigestLoader<BL, L, D>
+where
+    BL: BorrowMut<L>,
+    L: TrChunkLoader<u8>,
+    D: TrDigest,
+{
+    type IoAbort = L::IoAbort;
+    type LoadAsync<'a> = ChunkLoadAsync<'a, Self, u8> where Self: 'a;
+
+    #[inline(always)]
+    fn load_async<'a>(
+        &'a mut self,
+        source: &'a [u8],
+    ) -> Self::LoadAsync<'a> {
+        ChunkLoadAsync::new(self, source)
+    }
+
+    #[inline(always)]
+    fn load_at_least_async<'a>(
+        &'a mut self,
+        source: &'a [u8],
+        min_len: usize,
+    ) -> Self::LoadAsync<'a> {
+        ChunkLoadAsync::new_at_least(self, source, min_len)
+    }
+}
+
+impl<BL, L, D> TrChunkLoadWith<u8> for DigestLoader<BL, L, D>
+where
+    BL: BorrowMut<L>,
+    L: TrChunkLoader<u8>,
+    D: TrDigest,
+{
+    fn poll_load_with<C>(
+        &mut self,
+        source: &[u8],
+        min_len: usize,
+        perform_len: &mut usize,
+        mut cancel: Pin<&mut C>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<usize, Self::IoAbort>>
+    where
+        C: TrCancellationToken,
+    {
+        let loader = self.loader_.borrow_mut();
+        while *perform_len < min_len {
+            let rest = &source[*perform_len..];
+            let r = poll_load_some(loader, rest, cancel.as_mut(), cx);
+            let Poll::Ready(r) = r else {
+                return Poll::Pending;
+            };
+            let n = match &r {
+                Result::Ok(n) => *n,
+                Result::Err(a) => a.perform_len(),
+            };
+            let n = cmp::min(n, rest.len());
+            self.digest_.update(&rest[..n]);
+            let done_len = *perform_len;
+            *perform_len += n;
+            match r {
+                Result::Ok(0) => break,
+                Result::Ok(_) => (),
+                Result::Err(a) => {
+                    let a = a.add_perform_len(done_len);
+                    return Poll::Ready(Result::Err(a));
+                },
+            }
+        }
+        Poll::Ready(Result::Ok(*perform_len))
+    }
+}
+
+pub struct ChecksumTrailAsync<'a, BL, L, D>
+where
+    BL: BorrowMut<L>,
+    L: TrChunkLoader<u8>,
+    D: TrChecksum,
+{
+    loader_: &'a mut DigestLoader<BL, L, D>,
+    endian_: Endian,
+}
+
+impl<'a, BL, L, D> ChecksumTrailAsync<'a, BL, L, D>
+where
+    BL: BorrowMut<L>,
+    L: TrChunkLoader<u8>,
+    D: TrChecksum,
+{
+    pub fn new(loader: &'a mut DigestLoader<BL, L, D>, endian: Endian) -> Self {
+        ChecksumTrailAsync {
+            loader_: loader,
+            endian_: endian,
+        }
+    }
+}
+
+impl<'a, BL, L, D> ChecksumTrailAsync<'a, BL, L, D>
+where
+    BL: BorrowMut<L>,
+    L: TrChunkLoader<u8>,
+    D: TrChecksum,
+{
+    pub fn may_cancel_with<C>(
+        self,
+        cancel: Pin<&'a mut C>,
+    ) -> ChecksumTrailFuture<'a, C, BL, L, D>
+    where
+        C: TrCancellationToken,
+    {
+        ChecksumTrailFuture::new(self, cancel)
+    }
+}
+
+impl<'a, BL, L, D> IntoFuture for ChecksumTrailAsync<'a, BL, L, D>
+where
+    BL: BorrowMut<L>,
+    L: TrChunkLoader<u8>,
+    D: TrChecksum,
+{
+    type IntoFuture =
+        ChecksumTrailFuture<'a, NonCancellableToken, BL, L, D>;
+    type Output = <Self::IntoFuture as Future>::Output;
+
+    fn into_future(self) -> Self::IntoFuture {
+        let cancel = NonCancellableToken::pinned();
+        ChecksumTrailFuture::new(self, cancel)
+    }
+}
+
+impl<'a, BL, L, D> TrIntoFutureMayCancel<'a>
+for ChecksumTrailAsync<'a, BL, L, D>
+where
+    BL: BorrowMut<L>,
+    L: TrChunkLoader<u8>,
+    D: TrChecksum,
+{
+    type MayCancelOutput = <Self as IntoFuture>::Output;
+
+    #[inline(always)]
+    fn may_cancel_with<C>(
+        self,
+        cancel: Pin<&'a mut C>,
+    ) -> impl Future<Output = Self::MayCancelOutput>
+    where
+        C: TrCancellationToken,
+    {
+        ChecksumTrailAsync::may_cancel_with(self, cancel)
+    }
+}
+
+/// The future that writes the checksum of the units loaded.
+///
+/// The bytes of the checksum are taken when the future is created, and how
+/// many of them are loaded is kept in the future, so that a write that spans
+/// several wakeups resumes from where it stops.
+pub struct ChecksumTrailFuture<'a, C, BL, L, D>
+where
+    C: TrCancellationToken,
+    BL: BorrowMut<L>,
+    L: TrChunkLoader<u8>,
+    D: TrChecksum,
+{
+    loader_: &'a mut DigestLoader<BL, L, D>,
+    bytes_: <D::Sum as TrEndianNum>::Bytes,
+    perform_len_: usize,
+    cancel_: Pin<&'a mut C>,
+}
+
+// None of the fields is pinned.
+impl<C, BL, L, D> Unpin for ChecksumTrailFuture<'_, C, BL, L, D>
+where
+    C: TrCancellationToken,
+    BL: BorrowMut<L>,
+    L: TrChunkLoader<u8>,
+    D: TrChecksum,
+{}
+
+impl<'a, C, BL, L, D> ChecksumTrailFuture<'a, C, BL, L, D>
+where
+    C: TrCancellationToken,
+    BL: BorrowMut<L>,
+    L: TrChunkLoader<u8>,
+    D: TrChecksum,
+{
+    pub fn new(
+        trail: ChecksumTrailAsync<'a, BL, L, D>,
+        cancel: Pin<&'a mut C>,
+    ) -> Self {
+        let bytes = trail.loader_.digest_.sum().to_bytes(trail.endian_);
+        ChecksumTrailFuture {
+            loader_: trail.loader_,
+            bytes_: bytes,
+            perform_len_: 0usize,
+            cancel_: cancel,
+        }
+    }
+}
+
+impl<C, BL, L, D> Future for ChecksumTrailFuture<'_, C, BL, L, D>
+where
+    C: TrCancellationToken,
+    BL: BorrowMut<L>,
+    L: TrChunkLoader<u8>,
+    D: TrChecksum,
+{
+    type Output = Result<(), L::IoAbort>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        let loader = this.loader_.loader_.borrow_mut();
+        let bytes = this.bytes_.as_ref();
+        while this.perform_len_ < bytes.len() {
+            let source = &bytes[this.perform_len_..];
+            let cancel = this.cancel_.as_mut();
+            let r = poll_load_some(loader, source, cancel, cx);
+            let Poll::Ready(r) = r else {
+                return Poll::Pending;
+            };
+            match r {
+                Result::Ok(0) => break,
+                Result::Ok(n) => this.perform_len_ += n,
+                Result::Err(a) => {
+                    let a = a.add_perform_len(this.perform_len_);
+                    return Poll::Ready(Result::Err(a));
+                },
+            }
+        }
+        this.loader_.digest_.reset();
+        Poll::Ready(Result::Ok(()))
+    }
+}
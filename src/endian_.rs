@@ -0,0 +1,549 @@
+use core::{
+    error::Error,
+    fmt,
+    future::{Future, IntoFuture},
+    marker::PhantomData,
+    mem,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use abs_buff::x_deps::abs_sync;
+use abs_sync::cancellation::*;
+
+use crate::{
+    chunk_async_::{poll_fill_some, poll_load_some},
+    ChunkIoAbortKind, TrChunkFiller, TrChunkIoAbort, TrChunkLoader,
+};
+
+/// The byte order of a number.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Endian {
+    Little,
+    Big,
+}
+
+/// A number that can be read from or written as its byte representation.
+pub trait TrEndianNum: Sized {
+    /// The byte array of the number, i.e. `[u8; size_of::<Self>()]`.
+    type Bytes: AsRef<[u8]> + AsMut<[u8]> + Default;
+
+    fn from_le_bytes(bytes: Self::Bytes) -> Self;
+
+    fn from_be_bytes(bytes: Self::Bytes) -> Self;
+
+    #[inline(always)]
+    fn from_bytes(bytes: Self::Bytes, endian: Endian) -> Self {
+        match endian {
+            Endian::Little => Self::from_le_bytes(bytes),
+            Endian::Big => Self::from_be_bytes(bytes),
+        }
+    }
+
+    fn to_le_bytes(self) -> Self::Bytes;
+
+    fn to_be_bytes(self) -> Self::Bytes;
+
+    #[inline(always)]
+    fn to_bytes(self, endian: Endian) -> Self::Bytes {
+        match endian {
+            Endian::Little => self.to_le_bytes(),
+            Endian::Big => self.to_be_bytes(),
+        }
+    }
+}
+
+macro_rules! impl_endian_num {
+    ($($t:ty),* $(,)?) => {$(
+        impl TrEndianNum for $t {
+            type Bytes = [u8; core::mem::size_of::<$t>()];
+
+            #[inline(always)]
+            fn from_le_bytes(bytes: Self::Bytes) -> Self {
+                <$t>::from_le_bytes(bytes)
+            }
+
+            #[inline(always)]
+            fn from_be_bytes(bytes: Self::Bytes) -> Self {
+                <$t>::from_be_bytes(bytes)
+            }
+
+            #[inline(always)]
+            fn to_le_bytes(self) -> Self::Bytes {
+                <$t>::to_le_bytes(self)
+            }
+
+            #[inline(always)]
+            fn to_be_bytes(self) -> Self::Bytes {
+                <$t>::to_be_bytes(self)
+            }
+        }
+    )*};
+}
+
+impl_endian_num!(
+    u8, i8, u16, i16, u32, i32, u64, i64, u128, i128, f32, f64,
+);
+
+/// The error of reading or writing a number.
+#[derive(Debug)]
+pub enum NumError<A> {
+    /// The filler or loader completes without transferring the whole number,
+    /// and without an abort. Reports the number of bytes transferred.
+    Short(usize),
+    /// The filler or loader is aborted, the `perform_len` of the abort reports
+    /// how many bytes of the number have been transferred.
+    Io(A),
+}
+
+impl<A> NumError<A>
+where
+    A: TrChunkIoAbort,
+{
+    /// Number of bytes of the number that has been transferred.
+    pub fn perform_len(&self) -> usize {
+        match self {
+            NumError::Short(n) => *n,
+            NumError::Io(a) => a.perform_len(),
+        }
+    }
+
+    /// The classification of the aborted IO. A short number is classified
+    /// as `ChunkIoAbortKind::Fault`.
+    pub fn kind(&self) -> ChunkIoAbortKind {
+        match self {
+            NumError::Short(_) => ChunkIoAbortKind::Fault,
+            NumError::Io(a) => a.kind(),
+        }
+    }
+}
+
+impl<A> fmt::Display for NumError<A>
+where
+    A: Error,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NumError::Short(n) => write!(f, "number short after {n} bytes"),
+            NumError::Io(a) => write!(f, "number aborted: {a}"),
+        }
+    }
+}
+
+impl<A> Error for NumError<A>
+where
+    A: Error,
+{}
+
+macro_rules! fn_read_num {
+    ($($name:ident: $t:ty, $endian:ident;)*) => {$(
+        #[inline(always)]
+        fn $name(&mut self) -> ChunkReadNumAsync<'_, Self, $t> {
+            ChunkReadNumAsync::new(self, Endian::$endian)
+        }
+    )*};
+}
+
+/// Reads numbers in the specified byte order from a chunk filler.
+///
+/// Each read fills exactly the size of the number. If it is aborted, or the
+/// filler completes short, `NumError` reports how many bytes of the number
+/// have been filled.
+pub trait TrChunkNumFiller: TrChunkFiller<u8> {
+    #[inline(always)]
+    fn read_num<N>(&mut self, endian: Endian) -> ChunkReadNumAsync<'_, Self, N>
+    where
+        N: TrEndianNum,
+    {
+        ChunkReadNumAsync::new(self, endian)
+    }
+
+    fn_read_num! {
+        read_u8: u8, Little;
+        read_i8: i8, Little;
+        read_u16_le: u16, Little;
+        read_u16_be: u16, Big;
+        read_i16_le: i16, Little;
+        read_i16_be: i16, Big;
+        read_u32_le: u32, Little;
+        read_u32_be: u32, Big;
+        read_i32_le: i32, Little;
+        read_i32_be: i32, Big;
+        read_u64_le: u64, Little;
+        read_u64_be: u64, Big;
+        read_i64_le: i64, Little;
+        read_i64_be: i64, Big;
+        read_u128_le: u128, Little;
+        read_u128_be: u128, Big;
+        read_i128_le: i128, Little;
+        read_i128_be: i128, Big;
+        read_f32_le: f32, Little;
+        read_f32_be: f32, Big;
+        read_f64_le: f64, Little;
+        read_f64_be: f64, Big;
+    }
+}
+
+impl<F> TrChunkNumFiller for F
+where
+    F: TrChunkFiller<u8> + ?Sized,
+{}
+
+pub struct ChunkReadNumAsync<'a, F, N>
+where
+    F: TrChunkFiller<u8> + ?Sized,
+    N: TrEndianNum,
+{
+    _use_n_: PhantomData<N>,
+    filler_: &'a mut F,
+    endian_: Endian,
+}
+
+impl<'a, F, N> ChunkReadNumAsync<'a, F, N>
+where
+    F: TrChunkFiller<u8> + ?Sized,
+    N: TrEndianNum,
+{
+    pub fn new(filler: &'a mut F, endian: Endian) -> Self {
+        ChunkReadNumAsync {
+            _use_n_: PhantomData,
+            filler_: filler,
+            endian_: endian,
+        }
+    }
+
+    pub fn may_cancel_with<C>(
+        self,
+        cancel: Pin<&'a mut C>,
+    ) -> ChunkReadNumFuture<'a, C, F, N>
+    where
+        C: TrCancellationToken,
+    {
+        ChunkReadNumFuture::new(self, cancel)
+    }
+}
+
+impl<'a, F, N> IntoFuture for ChunkReadNumAsync<'a, F, N>
+where
+    F: TrChunkFiller<u8> + ?Sized,
+    N: TrEndianNum,
+{
+    type IntoFuture = ChunkReadNumFuture<'a, NonCancellableToken, F, N>;
+    type Output = <Self::IntoFuture as Future>::Output;
+
+    fn into_future(self) -> Self::IntoFuture {
+        let cancel = NonCancellableToken::pinned();
+        ChunkReadNumFuture::new(self, cancel)
+    }
+}
+
+impl<'a, F, N> TrIntoFutureMayCancel<'a> for ChunkReadNumAsync<'a, F, N>
+where
+    F: TrChunkFiller<u8> + ?Sized,
+    N: 'a + TrEndianNum,
+{
+    type MayCancelOutput = <Self as IntoFuture>::Output;
+
+    #[inline(always)]
+    fn may_cancel_with<C>(
+        self,
+        cancel: Pin<&'a mut C>,
+    ) -> impl Future<Output = Self::MayCancelOutput>
+    where
+        C: TrCancellationToken,
+    {
+        ChunkReadNumAsync::may_cancel_with(self, cancel)
+    }
+}
+
+/// The future that reads a number from a chunk filler.
+///
+/// The bytes of the number filled so far are kept in the future, and the
+/// rest are filled with `fill_some_async`, so that a read that spans several
+/// wakeups resumes from where it stops.
+pub struct ChunkReadNumFuture<'a, C, F, N>
+where
+    C: TrCancellationToken,
+    F: TrChunkFiller<u8> + ?Sized,
+    N: TrEndianNum,
+{
+    filler_: &'a mut F,
+    endian_: Endian,
+    bytes_: N::Bytes,
+    perform_len_: usize,
+    cancel_: Pin<&'a mut C>,
+}
+
+// None of the fields is pinned.
+impl<C, F, N> Unpin for ChunkReadNumFuture<'_, C, F, N>
+where
+    C: TrCancellationToken,
+    F: TrChunkFiller<u8> + ?Sized,
+    N: TrEndianNum,
+{}
+
+impl<'a, C, F, N> ChunkReadNumFuture<'a, C, F, N>
+where
+    C: TrCancellationToken,
+    F: TrChunkFiller<u8> + ?Sized,
+    N: TrEndianNum,
+{
+    pub fn new(
+        read: ChunkReadNumAsync<'a, F, N>,
+        cancel: Pin<&'a mut C>,
+    ) -> Self {
+        ChunkReadNumFuture {
+            filler_: read.filler_,
+            endian_: read.endian_,
+            bytes_: N::Bytes::default(),
+            perform_len_: 0usize,
+            cancel_: cancel,
+        }
+    }
+
+    /// Number of bytes of the number that has been filled so far.
+    pub fn perform_len(&self) -> usize {
+        self.perform_len_
+    }
+}
+
+impl<C, F, N> Future for ChunkReadNumFuture<'_, C, F, N>
+where
+    C: TrCancellationToken,
+    F: TrChunkFiller<u8> + ?Sized,
+    N: TrEndianNum,
+{
+    type Output = Result<N, NumError<F::IoAbort>>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        let bytes = this.bytes_.as_mut();
+        while this.perform_len_ < bytes.len() {
+            let target = &mut bytes[this.perform_len_..];
+            let cancel = this.cancel_.as_mut();
+            let r = poll_fill_some(this.filler_, target, cancel, cx);
+            let Poll::Ready(r) = r else {
+                return Poll::Pending;
+            };
+            match r {
+                Result::Ok(0) => {
+                    let e = NumError::Short(this.perform_len_);
+                    return Poll::Ready(Result::Err(e));
+                },
+                Result::Ok(n) => this.perform_len_ += n,
+                Result::Err(a) => {
+                    let a = a.add_perform_len(this.perform_len_);
+                    return Poll::Ready(Result::Err(NumError::Io(a)));
+                },
+            }
+        }
+        let bytes = mem::take(&mut this.bytes_);
+        Poll::Ready(Result::Ok(N::from_bytes(bytes, this.endian_)))
+    }
+}
+
+macro_rules! fn_write_num {
+    ($($name:ident: $t:ty, $endian:ident;)*) => {$(
+        #[inline(always)]
+        fn $name(&mut self, num: $t) -> ChunkWriteNumAsync<'_, Self, $t> {
+            ChunkWriteNumAsync::new(self, num, Endian::$endian)
+        }
+    )*};
+}
+
+/// Writes numbers in the specified byte order into a chunk loader.
+///
+/// The number is serialized into a byte array on the stack, and then loaded
+/// as a whole. If it is aborted, or the loader accepts no more, `NumError`
+/// reports how many bytes of the number have been written.
+pub trait TrChunkNumLoader: TrChunkLoader<u8> {
+    #[inline(always)]
+    fn write_num<N>(
+        &mut self,
+        num: N,
+        endian: Endian,
+    ) -> ChunkWriteNumAsync<'_, Self, N>
+    where
+        N: TrEndianNum,
+    {
+        ChunkWriteNumAsync::new(self, num, endian)
+    }
+
+    fn_write_num! {
+        write_u8: u8, Little;
+        write_i8: i8, Little;
+        write_u16_le: u16, Little;
+        write_u16_be: u16, Big;
+        write_i16_le: i16, Little;
+        write_i16_be: i16, Big;
+        write_u32_le: u32, Little;
+        write_u32_be: u32, Big;
+        write_i32_le: i32, Little;
+        write_i32_be: i32, Big;
+        write_u64_le: u64, Little;
+        write_u64_be: u64, Big;
+        write_i64_le: i64, Little;
+        write_i64_be: i64, Big;
+        write_u128_le: u128, Little;
+        write_u128_be: u128, Big;
+        write_i128_le: i128, Little;
+        write_i128_be: i128, Big;
+        write_f32_le: f32, Little;
+        write_f32_be: f32, Big;
+        write_f64_le: f64, Little;
+        write_f64_be: f64, Big;
+    }
+}
+
+impl<L> TrChunkNumLoader for L
+where
+    L: TrChunkLoader<u8> + ?Sized,
+{}
+
+pub struct ChunkWriteNumAsync<'a, L, N>
+where
+    L: TrChunkLoader<u8> + ?Sized,
+    N: TrEndianNum,
+{
+    loader_: &'a mut L,
+    bytes_: N::Bytes,
+}
+
+impl<'a, L, N> ChunkWriteNumAsync<'a, L, N>
+where
+    L: TrChunkLoader<u8> + ?Sized,
+    N: TrEndianNum,
+{
+    pub fn new(loader: &'a mut L, num: N, endian: Endian) -> Self {
+        ChunkWriteNumAsync {
+            loader_: loader,
+            bytes_: num.to_bytes(endian),
+        }
+    }
+
+    pub fn may_cancel_with<C>(
+        self,
+        cancel: Pin<&'a mut C>,
+    ) -> ChunkWriteNumFuture<'a, C, L, N>
+    where
+        C: TrCancellationToken,
+    {
+        ChunkWriteNumFuture::new(self, cancel)
+    }
+}
+
+impl<'a, L, N> IntoFuture for ChunkWriteNumAsync<'a, L, N>
+where
+    L: TrChunkLoader<u8> + ?Sized,
+    N: TrEndianNum,
+{
+    type IntoFuture = ChunkWriteNumFuture<'a, NonCancellableToken, L, N>;
+    type Output = <Self::IntoFuture as Future>::Output;
+
+    fn into_future(self) -> Self::IntoFuture {
+        let cancel = NonCancellableToken::pinned();
+        ChunkWriteNumFuture::new(self, cancel)
+    }
+}
+
+impl<'a, L, N> TrIntoFutureMayCancel<'a> for ChunkWriteNumAsync<'a, L, N>
+where
+    L: TrChunkLoader<u8> + ?Sized,
+    N: 'a + TrEndianNum,
+{
+    type MayCancelOutput = <Self as IntoFuture>::Output;
+
+    #[inline(always)]
+    fn may_cancel_with<C>(
+        self,
+        cancel: Pin<&'a mut C>,
+    ) -> impl Future<Output = Self::MayCancelOutput>
+    where
+        C: TrCancellationToken,
+    {
+        ChunkWriteNumAsync::may_cancel_with(self, cancel)
+    }
+}
+
+/// The future that writes a number into a chunk loader.
+///
+/// The bytes of the number, and how many of them are loaded so far, are kept
+/// in the future, and the rest are loaded with `load_some_async`, so that a
+/// write that spans several wakeups resumes from where it stops.
+pub struct ChunkWriteNumFuture<'a, C, L, N>
+where
+    C: TrCancellationToken,
+    L: TrChunkLoader<u8> + ?Sized,
+    N: TrEndianNum,
+{
+    loader_: &'a mut L,
+    bytes_: N::Bytes,
+    perform_len_: usize,
+    cancel_: Pin<&'a mut C>,
+}
+
+// None of the fields is pinned.
+impl<C, L, N> Unpin for ChunkWriteNumFuture<'_, C, L, N>
+where
+    C: TrCancellationToken,
+    L: TrChunkLoader<u8> + ?Sized,
+    N: TrEndianNum,
+{}
+
+impl<'a, C, L, N> ChunkWriteNumFuture<'a, C, L, N>
+where
+    C: TrCancellationToken,
+    L: TrChunkLoader<u8> + ?Sized,
+    N: TrEndianNum,
+{
+    pub fn new(
+        write: ChunkWriteNumAsync<'a, L, N>,
+        cancel: Pin<&'a mut C>,
+    ) -> Self {
+        ChunkWriteNumFuture {
+            loader_: write.loader_,
+            bytes_: write.bytes_,
+            perform_len_: 0usize,
+            cancel_: cancel,
+        }
+    }
+
+    /// Number of bytes of the number that has been loaded so far.
+    pub fn perform_len(&self) -> usize {
+        self.perform_len_
+    }
+}
+
+impl<C, L, N> Future for ChunkWriteNumFuture<'_, C, L, N>
+where
+    C: TrCancellationToken,
+    L: TrChunkLoader<u8> + ?Sized,
+    N: TrEndianNum,
+{
+    type Output = Result<(), NumError<L::IoAbort>>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        let bytes = this.bytes_.as_ref();
+        while this.perform_len_ < bytes.len() {
+            let source = &bytes[this.perform_len_..];
+            let cancel = this.cancel_.as_mut();
+            let r = poll_load_some(this.loader_, source, cancel, cx);
+            let Poll::Ready(r) = r else {
+                return Poll::Pending;
+            };
+            match r {
+                Result::Ok(0) => {
+                    let e = NumError::Short(this.perform_len_);
+                    return Poll::Ready(Result::Err(e));
+                },
+                Result::Ok(n) => this.perform_len_ += n,
+                Result::Err(a) => {
+                    let a = a.add_perform_len(this.perform_len_);
+                    return Poll::Ready(Result::Err(NumError::Io(a)));
+                },
+            }
+        }
+        Poll::Ready(Result::Ok(()))
+    }
+}
@@ -0,0 +1,270 @@
+use core::{
+    borrow::BorrowMut,
+    cmp,
+    error::Error,
+    fmt,
+    future::{Future, IntoFuture},
+    marker::PhantomData,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use abs_buff::{x_deps::abs_sync, TrBuffIterRead};
+use abs_sync::cancellation::*;
+
+use crate::{chunk_async_::poll_fill_some, TrChunkFiller, TrChunkIoAbort};
+
+/// The staging buffer given to `ChunkFillerAsBuffRead` or
+/// `ChunkLoaderAsBuffWrite` is empty, so that it could never hand out any
+/// segment.
+#[derive(Debug)]
+pub struct EmptyStagingError;
+
+impl fmt::Display for EmptyStagingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "empty staging buffer")
+    }
+}
+
+impl Error for EmptyStagingError {}
+
+/// Exposes a chunk filler as a buffer reader.
+///
+/// The units are filled into the staging buffer `S` first, and then handed
+/// out as segments of the staging buffer. The abortion of the filler is
+/// reported as the error of the reader. Units filled before the abortion
+/// are kept in the staging buffer, and handed out by the next read.
+pub struct ChunkFillerAsBuffRead<BF, F, S, T>
+where
+    BF: BorrowMut<F>,
+    F: TrChunkFiller<T>,
+    S: BorrowMut<[T]>,
+    T: Clone,
+{
+    _use_f_: PhantomData<F>,
+    _use_t_: PhantomData<[T]>,
+    filler_: BF,
+    staging_: S,
+    head_: usize,
+    tail_: usize,
+}
+
+impl<BF, F, S, T> ChunkFillerAsBuffRead<BF, F, S, T>
+where
+    BF: BorrowMut<F>,
+    F: TrChunkFiller<T>,
+    S: BorrowMut<[T]>,
+    T: Clone,
+{
+    /// Fails if the staging buffer is empty, which could never hand out any
+    /// segment.
+    pub fn new(filler: BF, staging: S) -> Result<Self, EmptyStagingError> {
+        if staging.borrow().is_empty() {
+            return Result::Err(EmptyStagingError);
+        }
+        Result::Ok(ChunkFillerAsBuffRead {
+            _use_f_: PhantomData,
+            _use_t_: PhantomData,
+            filler_: filler,
+            staging_: staging,
+            head_: 0usize,
+            tail_: 0usize,
+        })
+    }
+
+    /// Number of units filled into the staging buffer but not yet read.
+    pub const fn staged_len(&self) -> usize {
+        self.tail_ - self.head_
+    }
+
+    pub fn read_async(
+        &mut self,
+        length: usize,
+    ) -> ChunkFillerReadAsync<'_, BF, F, S, T> {
+        ChunkFillerReadAsync::new(self, length)
+    }
+
+    pub fn into_inner(self) -> (BF, S) {
+        (self.filler_, self.staging_)
+    }
+}
+
+impl<BF, F, S, T> TrBuffIterRead<T> for ChunkFillerAsBuffRead<BF, F, S, T>
+where
+    BF: BorrowMut<F>,
+    F: TrChunkFiller<T>,
+    F::IoAbort: Error,
+    S: BorrowMut<[T]>,
+    T: Clone,
+{
+    type Err = F::IoAbort;
+    type SliceRef<'a> = &'a [T] where Self: 'a;
+    type BuffIter<'a> = core::option::IntoIter<&'a [T]> where Self: 'a;
+    type ReadAsync<'a> = ChunkFillerReadAsync<'a, BF, F, S, T> where Self: 'a;
+
+    #[inline(always)]
+    fn read_async(&mut self, length: usize) -> Self::ReadAsync<'_> {
+        ChunkFillerAsBuffRead::read_async(self, length)
+    }
+}
+
+pub struct ChunkFillerReadAsync<'a, BF, F, S, T>
+where
+    BF: BorrowMut<F>,
+    F: TrChunkFiller<T>,
+    S: BorrowMut<[T]>,
+    T: Clone,
+{
+    reader_: &'a mut ChunkFillerAsBuffRead<BF, F, S, T>,
+    length_: usize,
+}
+
+impl<'a, BF, F, S, T> ChunkFillerReadAsync<'a, BF, F, S, T>
+where
+    BF: BorrowMut<F>,
+    F: TrChunkFiller<T>,
+    S: BorrowMut<[T]>,
+    T: Clone,
+{
+    pub fn new(
+        reader: &'a mut ChunkFillerAsBuffRead<BF, F, S, T>,
+        length: usize,
+    ) -> Self {
+        ChunkFillerReadAsync {
+            reader_: reader,
+            length_: length,
+        }
+    }
+
+    pub fn may_cancel_with<C>(
+        self,
+        cancel: Pin<&'a mut C>,
+    ) -> ChunkFillerReadFuture<'a, C, BF, F, S, T>
+    where
+        C: TrCancellationToken,
+    {
+        ChunkFillerReadFuture::new(self, cancel)
+    }
+}
+
+impl<'a, BF, F, S, T> IntoFuture for ChunkFillerReadAsync<'a, BF, F, S, T>
+where
+    BF: BorrowMut<F>,
+    F: TrChunkFiller<T>,
+    S: BorrowMut<[T]>,
+    T: Clone,
+{
+    type IntoFuture =
+        ChunkFillerReadFuture<'a, NonCancellableToken, BF, F, S, T>;
+    type Output = <Self::IntoFuture as Future>::Output;
+
+    fn into_future(self) -> Self::IntoFuture {
+        let cancel = NonCancellableToken::pinned();
+        ChunkFillerReadFuture::new(self, cancel)
+    }
+}
+
+impl<'a, BF, F, S, T> TrIntoFutureMayCancel<'a>
+for ChunkFillerReadAsync<'a, BF, F, S, T>
+where
+    BF: BorrowMut<F>,
+    F: TrChunkFiller<T>,
+    S: BorrowMut<[T]>,
+    T: Clone,
+{
+    type MayCancelOutput = <Self as IntoFuture>::Output;
+
+    #[inline(always)]
+    fn may_cancel_with<C>(
+        self,
+        cancel: Pin<&'a mut C>,
+    ) -> impl Future<Output = Self::MayCancelOutput>
+    where
+        C: TrCancellationToken,
+    {
+        ChunkFillerReadAsync::may_cancel_with(self, cancel)
+    }
+}
+
+/// The future that reads a segment from the staging buffer of a
+/// `ChunkFillerAsBuffRead`, filling it first if it is empty.
+pub struct ChunkFillerReadFuture<'a, C, BF, F, S, T>
+where
+    C: TrCancellationToken,
+    BF: BorrowMut<F>,
+    F: TrChunkFiller<T>,
+    S: BorrowMut<[T]>,
+    T: Clone,
+{
+    reader_: Option<&'a mut ChunkFillerAsBuffRead<BF, F, S, T>>,
+    length_: usize,
+    cancel_: Pin<&'a mut C>,
+}
+
+impl<'a, C, BF, F, S, T> ChunkFillerReadFuture<'a, C, BF, F, S, T>
+where
+    C: TrCancellationToken,
+    BF: BorrowMut<F>,
+    F: TrChunkFiller<T>,
+    S: BorrowMut<[T]>,
+    T: Clone,
+{
+    pub fn new(
+        read: ChunkFillerReadAsync<'a, BF, F, S, T>,
+        cancel: Pin<&'a mut C>,
+    ) -> Self {
+        ChunkFillerReadFuture {
+            reader_: Option::Some(read.reader_),
+            length_: read.length_,
+            cancel_: cancel,
+        }
+    }
+}
+
+impl<'a, C, BF, F, S, T> Future for ChunkFillerReadFuture<'a, C, BF, F, S, T>
+where
+    C: TrCancellationToken,
+    BF: BorrowMut<F>,
+    F: TrChunkFiller<T>,
+    S: BorrowMut<[T]>,
+    T: Clone,
+{
+    type Output = Result<core::option::IntoIter<&'a [T]>, F::IoAbort>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        let Option::Some(reader) = this.reader_.as_mut() else {
+            panic!("ChunkFillerReadFuture polled after completion");
+        };
+        if this.length_ == 0 {
+            return Poll::Ready(Result::Ok(Option::None.into_iter()));
+        }
+        if reader.head_ >= reader.tail_ {
+            reader.head_ = 0;
+            reader.tail_ = 0;
+            let filler = reader.filler_.borrow_mut();
+            let staging = reader.staging_.borrow_mut();
+            let cancel = this.cancel_.as_mut();
+            let r = poll_fill_some(filler, staging, cancel, cx);
+            let Poll::Ready(r) = r else {
+                return Poll::Pending;
+            };
+            match r {
+                Result::Ok(n) => reader.tail_ = n,
+                Result::Err(a) => {
+                    reader.tail_ = a.perform_len();
+                    return Poll::Ready(Result::Err(a));
+                },
+            }
+        }
+        let Option::Some(reader) = this.reader_.take() else {
+            unreachable!()
+        };
+        let ChunkFillerAsBuffRead { staging_, head_, tail_, .. } = reader;
+        let staging: &'a [T] = staging_.borrow_mut();
+        let opr_len = cmp::min(this.length_, *tail_ - *head_);
+        let src = &staging[*head_..*head_ + opr_len];
+        *head_ += opr_len;
+        Poll::Ready(Result::Ok(Option::Some(src).into_iter()))
+    }
+}
@@ -0,0 +1,768 @@
+use core::{
+    borrow::BorrowMut,
+    cmp,
+    error::Error,
+    fmt,
+    future::{Future, IntoFuture},
+    marker::PhantomData,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use abs_buff::x_deps::abs_sync;
+use abs_sync::cancellation::*;
+
+use crate::{
+    chunk_async_::{poll_fill_some, poll_load_some},
+    varint_::{decode_varint, encode_varint, VARINT_BUF_LEN},
+    ChunkIoAbortKind, Endian, TrChunkFiller, TrChunkIoAbort, TrChunkLoader,
+    TrEndianNum, TrVarInt,
+};
+
+/// The length header in front of the payload of a frame.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FrameHeader {
+    U8,
+    U16(Endian),
+    U32(Endian),
+    /// Unsigned LEB128.
+    VarInt,
+}
+
+impl FrameHeader {
+    /// The largest frame length that the header can represent.
+    pub const fn max_frame_len(self) -> usize {
+        let max = match self {
+            FrameHeader::U8 => u8::MAX as u64,
+            FrameHeader::U16(_) => u16::MAX as u64,
+            FrameHeader::U32(_) => u32::MAX as u64,
+            FrameHeader::VarInt => u64::MAX,
+        };
+        if max > usize::MAX as u64 {
+            usize::MAX
+        } else {
+            max as usize
+        }
+    }
+
+    /// The largest number of bytes of the header.
+    const fn max_header_len(self) -> usize {
+        match self {
+            FrameHeader::U8 => 1,
+            FrameHeader::U16(_) => 2,
+            FrameHeader::U32(_) => 4,
+            FrameHeader::VarInt => <u64 as TrVarInt>::MAX_LEN,
+        }
+    }
+
+    /// Decodes the frame length from the header bytes read so far.
+    ///
+    /// Returns `Ok(None)` if more bytes of the header are needed, or `Err`
+    /// if the header is malformed.
+    fn decode(self, bytes: &[u8]) -> Result<Option<u64>, ()> {
+        let frame_len = match self {
+            FrameHeader::U8 => bytes[0] as u64,
+            FrameHeader::U16(endian) => {
+                let mut b = [0u8; 2];
+                b.copy_from_slice(bytes);
+                u16::from_bytes(b, endian) as u64
+            },
+            FrameHeader::U32(endian) => {
+                let mut b = [0u8; 4];
+                b.copy_from_slice(bytes);
+                u32::from_bytes(b, endian) as u64
+            },
+            FrameHeader::VarInt => {
+                if bytes[bytes.len() - 1] & 0x80 != 0 {
+                    return if bytes.len() < self.max_header_len() {
+                        Result::Ok(Option::None)
+                    } else {
+                        Result::Err(())
+                    };
+                }
+                decode_varint(bytes)
+                    .and_then(|x| u64::try_from(x).ok())
+                    .ok_or(())?
+            },
+        };
+        Result::Ok(Option::Some(frame_len))
+    }
+
+    fn encode(self, frame_len: usize) -> ([u8; VARINT_BUF_LEN], usize) {
+        let mut bytes = [0u8; VARINT_BUF_LEN];
+        let len = match self {
+            FrameHeader::U8 => {
+                bytes[0] = frame_len as u8;
+                1
+            },
+            FrameHeader::U16(endian) => {
+                let b = (frame_len as u16).to_bytes(endian);
+                bytes[..b.len()].copy_from_slice(&b);
+                b.len()
+            },
+            FrameHeader::U32(endian) => {
+                let b = (frame_len as u32).to_bytes(endian);
+                bytes[..b.len()].copy_from_slice(&b);
+                b.len()
+            },
+            FrameHeader::VarInt => return encode_varint(frame_len as u128),
+        };
+        (bytes, len)
+    }
+}
+
+/// The error of reading or writing a length-prefixed frame.
+///
+/// A frame that is partially transferred when the IO is aborted is kept by
+/// the reader or writer, and the next call resumes it.
+#[derive(Debug)]
+pub enum FrameError<A> {
+    /// The frame length exceeds the limit. On reading, the frame stays
+    /// pending, to be read with a larger target or skipped by
+    /// `LenPrefixFrameReader::discard_frame`, and a length beyond `usize` is
+    /// reported as `usize::MAX`. On writing, nothing is written.
+    Oversize(usize),
+    /// The length header is not a valid LEB128. The header is consumed.
+    MalformedHeader,
+    /// The IO is aborted on the length header, or on skipping a discarded
+    /// frame.
+    Io(A),
+    /// The IO is aborted on the payload, the `perform_len` of the abort
+    /// reports how many units of the payload are transferred in this call.
+    Truncated {
+        frame_len: usize,
+        abort: A,
+    },
+    /// The filler or loader completes without transferring any unit, and
+    /// without an abort. The frame stays pending, as on an abort.
+    Stalled,
+    /// The write of a pending frame is resumed with a payload of another
+    /// length. Nothing is written, and the frame of `pending_len` units stays
+    /// pending, to be resumed with its own payload.
+    PayloadMismatch {
+        pending_len: usize,
+        payload_len: usize,
+    },
+}
+
+impl<A> FrameError<A>
+where
+    A: TrChunkIoAbort,
+{
+    /// The classification of the aborted IO, so that a cancelled frame can be
+    /// told from a failure and resumed. A malformed or oversize frame is
+    /// classified as `ChunkIoAbortKind::Fault`.
+    pub fn kind(&self) -> ChunkIoAbortKind {
+        match self {
+            FrameError::Io(a) => a.kind(),
+            FrameError::Truncated { abort, .. } => abort.kind(),
+            _ => ChunkIoAbortKind::Fault,
+        }
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.kind() == ChunkIoAbortKind::Cancelled
+    }
+}
+
+impl<A> fmt::Display for FrameError<A>
+where
+    A: Error,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FrameError::Oversize(n) => write!(f, "frame of {n} units oversize"),
+            FrameError::MalformedHeader => write!(f, "malformed frame header"),
+            FrameError::Io(a) => write!(f, "frame header aborted: {a}"),
+            FrameError::Truncated { frame_len, abort } => {
+                write!(f, "frame of {frame_len} units truncated: {abort}")
+            },
+            FrameError::Stalled => write!(f, "frame IO stalled"),
+            FrameError::PayloadMismatch { pending_len, payload_len } => write!(
+                f,
+                "pending frame of {pending_len} units resumed with \
+                {payload_len} units",
+            ),
+        }
+    }
+}
+
+impl<A> Error for FrameError<A>
+where
+    A: Error,
+{}
+
+/// Reads length-prefixed frames from a chunk filler.
+///
+/// The header is read into the reader itself, so that an aborted read of the
+/// header is resumed by the next read. An aborted read of the payload is
+/// resumed by the next read as well, which is supposed to be given the same
+/// target, unless the frame is discarded with `discard_frame`.
+pub struct LenPrefixFrameReader<BF, F>
+where
+    BF: BorrowMut<F>,
+    F: TrChunkFiller<u8>,
+{
+    _use_f_: PhantomData<F>,
+    filler_: BF,
+    header_: FrameHeader,
+    max_len_: usize,
+    head_buf_: [u8; VARINT_BUF_LEN],
+    head_len_: usize,
+    frame_len_: Option<u64>,
+    fill_len_: usize,
+    skip_len_: u64,
+}
+
+impl<BF, F> LenPrefixFrameReader<BF, F>
+where
+    BF: BorrowMut<F>,
+    F: TrChunkFiller<u8>,
+{
+    /// Frames longer than `max_len` are reported as `FrameError::Oversize`.
+    pub const fn new(filler: BF, header: FrameHeader, max_len: usize) -> Self {
+        LenPrefixFrameReader {
+            _use_f_: PhantomData,
+            filler_: filler,
+            header_: header,
+            max_len_: max_len,
+            head_buf_: [0u8; VARINT_BUF_LEN],
+            head_len_: 0,
+            frame_len_: Option::None,
+            fill_len_: 0,
+            skip_len_: 0,
+        }
+    }
+
+    /// The length of the frame whose header is read but whose payload is not
+    /// yet, for example an oversize frame, which may not fit in `usize`.
+    pub const fn pending_frame_len(&self) -> Option<u64> {
+        self.frame_len_
+    }
+
+    /// Discards the rest of the pending frame, which is skipped from the
+    /// filler by the next read.
+    pub fn discard_frame(&mut self) {
+        if let Option::Some(frame_len) = self.frame_len_.take() {
+            self.skip_len_ += frame_len - self.fill_len_ as u64;
+            self.fill_len_ = 0;
+        }
+    }
+
+    /// Reads a frame, and fills the payload into the front of `target`.
+    ///
+    /// Completes with the length of the payload. A frame longer than the
+    /// target is reported as oversize as well.
+    pub fn read_frame_async<'a>(
+        &'a mut self,
+        target: &'a mut [u8],
+    ) -> FrameReadAsync<'a, BF, F> {
+        FrameReadAsync::new(self, target)
+    }
+
+    pub fn into_inner(self) -> BF {
+        self.filler_
+    }
+
+    fn poll_skip_<C>(
+        &mut self,
+        mut cancel: Pin<&mut C>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), FrameError<F::IoAbort>>>
+    where
+        C: TrCancellationToken,
+    {
+        let filler = self.filler_.borrow_mut();
+        let mut scratch = [0u8; 64];
+        while self.skip_len_ > 0 {
+            let opr_len = cmp::min(self.skip_len_, scratch.len() as u64);
+            let target = &mut scratch[..opr_len as usize];
+            let r = poll_fill_some(filler, target, cancel.as_mut(), cx);
+            let Poll::Ready(r) = r else {
+                return Poll::Pending;
+            };
+            match r {
+                Result::Ok(0) => {
+                    return Poll::Ready(Result::Err(FrameError::Stalled));
+                },
+                Result::Ok(n) => self.skip_len_ -= n as u64,
+                Result::Err(a) => {
+                    self.skip_len_ -= a.perform_len() as u64;
+                    return Poll::Ready(Result::Err(FrameError::Io(a)));
+                },
+            }
+        }
+        Poll::Ready(Result::Ok(()))
+    }
+
+    fn poll_read_header_<C>(
+        &mut self,
+        mut cancel: Pin<&mut C>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<u64, FrameError<F::IoAbort>>>
+    where
+        C: TrCancellationToken,
+    {
+        let header = self.header_;
+        let filler = self.filler_.borrow_mut();
+        loop {
+            // A varint header is read byte by byte, so that nothing after the
+            // header is consumed.
+            let want_len = match header {
+                FrameHeader::VarInt => self.head_len_ + 1,
+                _ => header.max_header_len(),
+            };
+            let target = &mut self.head_buf_[self.head_len_..want_len];
+            let r = poll_fill_some(filler, target, cancel.as_mut(), cx);
+            let Poll::Ready(r) = r else {
+                return Poll::Pending;
+            };
+            match r {
+                Result::Ok(0) => {
+                    return Poll::Ready(Result::Err(FrameError::Stalled));
+                },
+                Result::Ok(n) => self.head_len_ += n,
+                Result::Err(a) => {
+                    self.head_len_ += a.perform_len();
+                    return Poll::Ready(Result::Err(FrameError::Io(a)));
+                },
+            }
+            if self.head_len_ < want_len {
+                continue;
+            }
+            match header.decode(&self.head_buf_[..self.head_len_]) {
+                Result::Ok(Option::None) => continue,
+                Result::Ok(Option::Some(frame_len)) => {
+                    self.head_len_ = 0;
+                    return Poll::Ready(Result::Ok(frame_len));
+                },
+                Result::Err(()) => {
+                    self.head_len_ = 0;
+                    let e = FrameError::MalformedHeader;
+                    return Poll::Ready(Result::Err(e));
+                },
+            }
+        }
+    }
+}
+
+pub struct FrameReadAsync<'a, BF, F>
+where
+    BF: BorrowMut<F>,
+    F: TrChunkFiller<u8>,
+{
+    reader_: &'a mut LenPrefixFrameReader<BF, F>,
+    target_: &'a mut [u8],
+}
+
+impl<'a, BF, F> FrameReadAsync<'a, BF, F>
+where
+    BF: BorrowMut<F>,
+    F: TrChunkFiller<u8>,
+{
+    pub fn new(
+        reader: &'a mut LenPrefixFrameReader<BF, F>,
+        target: &'a mut [u8],
+    ) -> Self {
+        FrameReadAsync {
+            reader_: reader,
+            target_: target,
+        }
+    }
+
+    pub fn may_cancel_with<C>(
+        self,
+        cancel: Pin<&'a mut C>,
+    ) -> FrameReadFuture<'a, C, BF, F>
+    where
+        C: TrCancellationToken,
+    {
+        FrameReadFuture::new(self, cancel)
+    }
+}
+
+impl<'a, BF, F> IntoFuture for FrameReadAsync<'a, BF, F>
+where
+    BF: BorrowMut<F>,
+    F: TrChunkFiller<u8>,
+{
+    type IntoFuture = FrameReadFuture<'a, NonCancellableToken, BF, F>;
+    type Output = <Self::IntoFuture as Future>::Output;
+
+    fn into_future(self) -> Self::IntoFuture {
+        let cancel = NonCancellableToken::pinned();
+        FrameReadFuture::new(self, cancel)
+    }
+}
+
+impl<'a, BF, F> TrIntoFutureMayCancel<'a> for FrameReadAsync<'a, BF, F>
+where
+    BF: BorrowMut<F>,
+    F: TrChunkFiller<u8>,
+{
+    type MayCancelOutput = <Self as IntoFuture>::Output;
+
+    #[inline(always)]
+    fn may_cancel_with<C>(
+        self,
+        cancel: Pin<&'a mut C>,
+    ) -> impl Future<Output = Self::MayCancelOutput>
+    where
+        C: TrCancellationToken,
+    {
+        FrameReadAsync::may_cancel_with(self, cancel)
+    }
+}
+
+/// The future that reads a frame with a `LenPrefixFrameReader`.
+///
+/// The progress of the frame is kept in the reader, so that it survives an
+/// abort as well as a pending poll. The future only counts the units of the
+/// payload filled by this call, as reported by `FrameError::Truncated`.
+pub struct FrameReadFuture<'a, C, BF, F>
+where
+    C: TrCancellationToken,
+    BF: BorrowMut<F>,
+    F: TrChunkFiller<u8>,
+{
+    reader_: &'a mut LenPrefixFrameReader<BF, F>,
+    target_: &'a mut [u8],
+    perform_len_: usize,
+    cancel_: Pin<&'a mut C>,
+}
+
+impl<'a, C, BF, F> FrameReadFuture<'a, C, BF, F>
+where
+    C: TrCancellationToken,
+    BF: BorrowMut<F>,
+    F: TrChunkFiller<u8>,
+{
+    pub fn new(
+        read: FrameReadAsync<'a, BF, F>,
+        cancel: Pin<&'a mut C>,
+    ) -> Self {
+        FrameReadFuture {
+            reader_: read.reader_,
+            target_: read.target_,
+            perform_len_: 0usize,
+            cancel_: cancel,
+        }
+    }
+}
+
+impl<C, BF, F> Future for FrameReadFuture<'_, C, BF, F>
+where
+    C: TrCancellationToken,
+    BF: BorrowMut<F>,
+    F: TrChunkFiller<u8>,
+{
+    type Output = Result<usize, FrameError<F::IoAbort>>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        let reader = &mut *this.reader_;
+        let Poll::Ready(r) = reader.poll_skip_(this.cancel_.as_mut(), cx) else {
+            return Poll::Pending;
+        };
+        if let Result::Err(e) = r {
+            return Poll::Ready(Result::Err(e));
+        }
+        let frame_len = match reader.frame_len_ {
+            Option::Some(frame_len) => frame_len,
+            Option::None => {
+                let cancel = this.cancel_.as_mut();
+                let Poll::Ready(r) = reader.poll_read_header_(cancel, cx) else {
+                    return Poll::Pending;
+                };
+                let frame_len = match r {
+                    Result::Ok(frame_len) => frame_len,
+                    Result::Err(e) => return Poll::Ready(Result::Err(e)),
+                };
+                reader.frame_len_ = Option::Some(frame_len);
+                reader.fill_len_ = 0;
+                frame_len
+            },
+        };
+        // A length beyond `usize` stays pending as well, to be discarded.
+        let frame_len = usize::try_from(frame_len).unwrap_or(usize::MAX);
+        let max_len = cmp::min(reader.max_len_, this.target_.len());
+        if frame_len > max_len {
+            return Poll::Ready(Result::Err(FrameError::Oversize(frame_len)));
+        }
+        let filler = reader.filler_.borrow_mut();
+        while reader.fill_len_ < frame_len {
+            let target = &mut this.target_[reader.fill_len_..frame_len];
+            let r = poll_fill_some(filler, target, this.cancel_.as_mut(), cx);
+            let Poll::Ready(r) = r else {
+                return Poll::Pending;
+            };
+            match r {
+                Result::Ok(0) => {
+                    return Poll::Ready(Result::Err(FrameError::Stalled));
+                },
+                Result::Ok(n) => {
+                    reader.fill_len_ += n;
+                    this.perform_len_ += n;
+                },
+                Result::Err(a) => {
+                    reader.fill_len_ += a.perform_len();
+                    let abort = a.add_perform_len(this.perform_len_);
+                    let e = FrameError::Truncated { frame_len, abort };
+                    return Poll::Ready(Result::Err(e));
+                },
+            }
+        }
+        reader.frame_len_ = Option::None;
+        reader.fill_len_ = 0;
+        Poll::Ready(Result::Ok(frame_len))
+    }
+}
+
+/// Writes length-prefixed frames into a chunk loader.
+///
+/// The units loaded into the loader cannot be taken back, so a frame whose
+/// write is aborted is kept by the writer, and the next write resumes it from
+/// the units already loaded. The next write must be given the same payload,
+/// and one of another length is rejected with `FrameError::PayloadMismatch`.
+pub struct LenPrefixFrameWriter<BL, L>
+where
+    BL: BorrowMut<L>,
+    L: TrChunkLoader<u8>,
+{
+    _use_l_: PhantomData<L>,
+    loader_: BL,
+    header_: FrameHeader,
+    max_len_: usize,
+    head_buf_: [u8; VARINT_BUF_LEN],
+    head_len_: usize,
+    head_load_len_: usize,
+    frame_len_: Option<usize>,
+    load_len_: usize,
+}
+
+impl<BL, L> LenPrefixFrameWriter<BL, L>
+where
+    BL: BorrowMut<L>,
+    L: TrChunkLoader<u8>,
+{
+    /// Payloads longer than `max_len`, or than the header can represent,
+    /// are reported as `FrameError::Oversize` without writing anything.
+    pub const fn new(loader: BL, header: FrameHeader, max_len: usize) -> Self {
+        LenPrefixFrameWriter {
+            _use_l_: PhantomData,
+            loader_: loader,
+            header_: header,
+            max_len_: max_len,
+            head_buf_: [0u8; VARINT_BUF_LEN],
+            head_len_: 0,
+            head_load_len_: 0,
+            frame_len_: Option::None,
+            load_len_: 0,
+        }
+    }
+
+    /// The length of the frame that is partially written.
+    pub const fn pending_frame_len(&self) -> Option<usize> {
+        self.frame_len_
+    }
+
+    /// Writes the header and the payload as one frame.
+    ///
+    /// Completes with the length of the payload.
+    pub fn write_frame_async<'a>(
+        &'a mut self,
+        payload: &'a [u8],
+    ) -> FrameWriteAsync<'a, BL, L> {
+        FrameWriteAsync::new(self, payload)
+    }
+
+    pub fn into_inner(self) -> BL {
+        self.loader_
+    }
+}
+
+pub struct FrameWriteAsync<'a, BL, L>
+where
+    BL: BorrowMut<L>,
+    L: TrChunkLoader<u8>,
+{
+    writer_: &'a mut LenPrefixFrameWriter<BL, L>,
+    payload_: &'a [u8],
+}
+
+impl<'a, BL, L> FrameWriteAsync<'a, BL, L>
+where
+    BL: BorrowMut<L>,
+    L: TrChunkLoader<u8>,
+{
+    pub fn new(
+        writer: &'a mut LenPrefixFrameWriter<BL, L>,
+        payload: &'a [u8],
+    ) -> Self {
+        FrameWriteAsync {
+            writer_: writer,
+            payload_: payload,
+        }
+    }
+
+    pub fn may_cancel_with<C>(
+        self,
+        cancel: Pin<&'a mut C>,
+    ) -> FrameWriteFuture<'a, C, BL, L>
+    where
+        C: TrCancellationToken,
+    {
+        FrameWriteFuture::new(self, cancel)
+    }
+}
+
+impl<'a, BL, L> IntoFuture for FrameWriteAsync<'a, BL, L>
+where
+    BL: BorrowMut<L>,
+    L: TrChunkLoader<u8>,
+{
+    type IntoFuture = FrameWriteFuture<'a, NonCancellableToken, BL, L>;
+    type Output = <Self::IntoFuture as Future>::Output;
+
+    fn into_future(self) -> Self::IntoFuture {
+        let cancel = NonCancellableToken::pinned();
+        FrameWriteFuture::new(self, cancel)
+    }
+}
+
+impl<'a, BL, L> TrIntoFutureMayCancel<'a> for FrameWriteAsync<'a, BL, L>
+where
+    BL: BorrowMut<L>,
+    L: TrChunkLoader<u8>,
+{
+    type MayCancelOutput = <Self as IntoFuture>::Output;
+
+    #[inline(always)]
+    fn may_cancel_with<C>(
+        self,
+        cancel: Pin<&'a mut C>,
+    ) -> impl Future<Output = Self::MayCancelOutput>
+    where
+        C: TrCancellationToken,
+    {
+        FrameWriteAsync::may_cancel_with(self, cancel)
+    }
+}
+
+/// The future that writes a frame with a `LenPrefixFrameWriter`.
+///
+/// The progress of the frame is kept in the writer, so that it survives an
+/// abort as well as a pending poll. The future only counts the units of the
+/// payload loaded by this call, as reported by `FrameError::Truncated`.
+pub struct FrameWriteFuture<'a, C, BL, L>
+where
+    C: TrCancellationToken,
+    BL: BorrowMut<L>,
+    L: TrChunkLoader<u8>,
+{
+    writer_: &'a mut LenPrefixFrameWriter<BL, L>,
+    payload_: &'a [u8],
+    perform_len_: usize,
+    cancel_: Pin<&'a mut C>,
+}
+
+impl<'a, C, BL, L> FrameWriteFuture<'a, C, BL, L>
+where
+    C: TrCancellationToken,
+    BL: BorrowMut<L>,
+    L: TrChunkLoader<u8>,
+{
+    pub fn new(
+        write: FrameWriteAsync<'a, BL, L>,
+        cancel: Pin<&'a mut C>,
+    ) -> Self {
+        FrameWriteFuture {
+            writer_: write.writer_,
+            payload_: write.payload_,
+            perform_len_: 0usize,
+            cancel_: cancel,
+        }
+    }
+}
+
+impl<C, BL, L> Future for FrameWriteFuture<'_, C, BL, L>
+where
+    C: TrCancellationToken,
+    BL: BorrowMut<L>,
+    L: TrChunkLoader<u8>,
+{
+    type Output = Result<usize, FrameError<L::IoAbort>>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        let writer = &mut *this.writer_;
+        let header = writer.header_;
+        let frame_len = this.payload_.len();
+        match writer.frame_len_ {
+            Option::Some(pending_len) if pending_len != frame_len => {
+                let e = FrameError::PayloadMismatch {
+                    pending_len,
+                    payload_len: frame_len,
+                };
+                return Poll::Ready(Result::Err(e));
+            },
+            Option::Some(_) => (),
+            Option::None => {
+                let max_len = cmp::min(writer.max_len_, header.max_frame_len());
+                if frame_len > max_len {
+                    let e = FrameError::Oversize(frame_len);
+                    return Poll::Ready(Result::Err(e));
+                }
+                let (bytes, len) = header.encode(frame_len);
+                writer.head_buf_ = bytes;
+                writer.head_len_ = len;
+                writer.head_load_len_ = 0;
+                writer.frame_len_ = Option::Some(frame_len);
+                writer.load_len_ = 0;
+            },
+        }
+        let loader = writer.loader_.borrow_mut();
+        while writer.head_load_len_ < writer.head_len_ {
+            let head = &writer.head_buf_[..writer.head_len_];
+            let source = &head[writer.head_load_len_..];
+            let r = poll_load_some(loader, source, this.cancel_.as_mut(), cx);
+            let Poll::Ready(r) = r else {
+                return Poll::Pending;
+            };
+            match r {
+                Result::Ok(0) => {
+                    return Poll::Ready(Result::Err(FrameError::Stalled));
+                },
+                Result::Ok(n) => writer.head_load_len_ += n,
+                Result::Err(a) => {
+                    writer.head_load_len_ += a.perform_len();
+                    return Poll::Ready(Result::Err(FrameError::Io(a)));
+                },
+            }
+        }
+        while writer.load_len_ < frame_len {
+            let source = &this.payload_[writer.load_len_..];
+            let r = poll_load_some(loader, source, this.cancel_.as_mut(), cx);
+            let Poll::Ready(r) = r else {
+                return Poll::Pending;
+            };
+            match r {
+                Result::Ok(0) => {
+                    return Poll::Ready(Result::Err(FrameError::Stalled));
+                },
+                Result::Ok(n) => {
+                    writer.load_len_ += n;
+                    this.perform_len_ += n;
+                },
+                Result::Err(a) => {
+                    writer.load_len_ += a.perform_len();
+                    let abort = a.add_perform_len(this.perform_len_);
+                    let e = FrameError::Truncated { frame_len, abort };
+                    return Poll::Ready(Result::Err(e));
+                },
+            }
+        }
+        writer.frame_len_ = Option::None;
+        Poll::Ready(Result::Ok(frame_len))
+    }
+}
@@ -0,0 +1,643 @@
+use core::{
+    borrow::BorrowMut,
+    error::Error,
+    future::{Future, IntoFuture},
+    marker::PhantomData,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use abs_buff::x_deps::abs_sync;
+use abs_sync::cancellation::*;
+
+use crate::{
+    chunk_async_::{
+        ChunkFillAsync, ChunkLoadAsync, TrChunkFillWith, TrChunkLoadWith,
+    },
+    stuff_::{StuffBuf, StuffStage},
+    ChunkIoAbort, Crc16, StuffError, TrChecksum, TrChunkFiller, TrChunkIoAbort,
+    TrChunkLoader, TrDigest,
+};
+
+const HDLC_FLAG: u8 = 0x7E;
+const HDLC_ESC: u8 = 0x7D;
+const HDLC_XOR: u8 = 0x20;
+
+/// Encodes the units loaded through it into HDLC-like frames (RFC 1662),
+/// delimited by `0x7E` flags, with `0x7E` and `0x7D` escaped.
+///
+/// The encoded units are buffered, and are not necessarily passed to the
+/// inner loader until the frame is ended by `finish_frame_async`, which also
+/// emits the FCS-16 if enabled and the closing flag. The closing flag of a
+/// frame doubles as the opening flag of the next one. If the inner loader is
+/// aborted, the units not yet emitted are kept and emitted by the next load
+/// or finish.
+pub struct HdlcEncodeLoader<BL, L>
+where
+    BL: BorrowMut<L>,
+    L: TrChunkLoader<u8>,
+{
+    _use_l_: PhantomData<L>,
+    loader_: BL,
+    buf_: StuffBuf,
+    fcs16_: bool,
+    fcs_: Crc16,
+    open_: bool,
+    closing_: bool,
+}
+
+impl<BL, L> HdlcEncodeLoader<BL, L>
+where
+    BL: BorrowMut<L>,
+    L: TrChunkLoader<u8>,
+{
+    pub const fn new(loader: BL) -> Self {
+        HdlcEncodeLoader {
+            _use_l_: PhantomData,
+            loader_: loader,
+            buf_: StuffBuf::new(),
+            fcs16_: false,
+            fcs_: Crc16::X25,
+            open_: false,
+            closing_: false,
+        }
+    }
+
+    /// Whether to append the FCS-16 to each frame.
+    pub fn with_fcs16(self, fcs16: bool) -> Self {
+        HdlcEncodeLoader {
+            fcs16_: fcs16,
+            ..self
+        }
+    }
+
+    /// Emits the buffered units of the frame, the FCS-16 if enabled, and the
+    /// closing flag.
+    pub fn finish_frame_async(&mut self) -> HdlcFinishAsync<'_, BL, L> {
+        HdlcFinishAsync::new(self)
+    }
+
+    /// Returns the inner loader, discarding the unfinished frame.
+    pub fn into_inner(self) -> BL {
+        self.loader_
+    }
+
+    fn push_escaped_(&mut self, byte: u8) {
+        if byte == HDLC_FLAG || byte == HDLC_ESC {
+            self.buf_.push(HDLC_ESC);
+            self.buf_.push(byte ^ HDLC_XOR);
+        } else {
+            self.buf_.push(byte);
+        }
+    }
+
+    fn poll_finish_<C>(
+        &mut self,
+        mut cancel: Pin<&mut C>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), L::IoAbort>>
+    where
+        C: TrCancellationToken,
+    {
+        if !self.closing_ {
+            // The opening flag, the escaped FCS-16 and the closing flag.
+            if self.buf_.free_len() < 6 {
+                let loader = self.loader_.borrow_mut();
+                let r = self.buf_.poll_flush(loader, cancel.as_mut(), cx);
+                let Poll::Ready(r) = r else {
+                    return Poll::Pending;
+                };
+                if r.is_err() {
+                    return Poll::Ready(r);
+                }
+            }
+            if !self.open_ {
+                self.buf_.push(HDLC_FLAG);
+            }
+            if self.fcs16_ {
+                let fcs = self.fcs_.sum();
+                self.push_escaped_(fcs as u8);
+                self.push_escaped_((fcs >> 8) as u8);
+            }
+            self.buf_.push(HDLC_FLAG);
+            self.fcs_.reset();
+            self.open_ = true;
+            self.closing_ = true;
+        }
+        let loader = self.loader_.borrow_mut();
+        let r = self.buf_.poll_flush(loader, cancel, cx);
+        let Poll::Ready(r) = r else {
+            return Poll::Pending;
+        };
+        if r.is_ok() {
+            self.closing_ = false;
+        }
+        Poll::Ready(r)
+    }
+}
+
+impl<BL, L> TrChunkLoader<u8> for HdlcEncodeLoader<BL, L>
+where
+    BL: BorrowMut<L>,
+    L: TrChunkLoader<u8>,
+    L::IoAbort: Error,
+{
+    type IoAbort = ChunkIoAbort<StuffError<L::IoAbort>>;
+    type LoadAsync<'a> = ChunkLoadAsync<'a, Self, u8> where Self: 'a;
+
+    #[inline(always)]
+    fn load_async<'a>(
+        &'a mut self,
+        source: &'a [u8],
+    ) -> Self::LoadAsync<'a> {
+        ChunkLoadAsync::new(self, source)
+    }
+
+    #[inline(always)]
+    fn load_at_least_async<'a>(
+        &'a mut self,
+        source: &'a [u8],
+        min_len: usize,
+    ) -> Self::LoadAsync<'a> {
+        ChunkLoadAsync::new_at_least(self, source, min_len)
+    }
+}
+
+impl<BL, L> TrChunkLoadWith<u8> for HdlcEncodeLoader<BL, L>
+where
+    BL: BorrowMut<L>,
+    L: TrChunkLoader<u8>,
+    L::IoAbort: Error,
+{
+    fn poll_load_with<C>(
+        &mut self,
+        source: &[u8],
+        min_len: usize,
+        perform_len: &mut usize,
+        mut cancel: Pin<&mut C>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<usize, Self::IoAbort>>
+    where
+        C: TrCancellationToken,
+    {
+        // The closing flag of the previous frame is already buffered.
+        self.closing_ = false;
+        while *perform_len < source.len() {
+            // The opening flag and an escaped unit.
+            if self.buf_.free_len() < 3 {
+                if *perform_len >= min_len {
+                    break;
+                }
+                let loader = self.loader_.borrow_mut();
+                let r = self.buf_.poll_flush(loader, cancel.as_mut(), cx);
+                let Poll::Ready(r) = r else {
+                    return Poll::Pending;
+                };
+                if let Result::Err(a) = r {
+                    let kind = a.kind();
+                    let e = StuffError::Io(a);
+                    return Poll::Ready(Result::Err(
+                        ChunkIoAbort::with_kind(*perform_len, e, kind),
+                    ));
+                }
+            }
+            if !self.open_ {
+                self.buf_.push(HDLC_FLAG);
+                self.open_ = true;
+            }
+            let byte = source[*perform_len];
+            self.fcs_.update(&[byte]);
+            self.push_escaped_(byte);
+            *perform_len += 1;
+        }
+        Poll::Ready(Result::Ok(*perform_len))
+    }
+}
+
+pub struct HdlcFinishAsync<'a, BL, L>
+where
+    BL: BorrowMut<L>,
+    L: TrChunkLoader<u8>,
+{
+    loader_: &'a mut HdlcEncodeLoader<BL, L>,
+}
+
+impl<'a, BL, L> HdlcFinishAsync<'a, BL, L>
+where
+    BL: BorrowMut<L>,
+    L: TrChunkLoader<u8>,
+{
+    pub fn new(loader: &'a mut HdlcEncodeLoader<BL, L>) -> Self {
+        HdlcFinishAsync { loader_: loader }
+    }
+
+    pub fn may_cancel_with<C>(
+        self,
+        cancel: Pin<&'a mut C>,
+    ) -> HdlcFinishFuture<'a, C, BL, L>
+    where
+        C: TrCancellationToken,
+    {
+        HdlcFinishFuture::new(self, cancel)
+    }
+}
+
+impl<'a, BL, L> IntoFuture for HdlcFinishAsync<'a, BL, L>
+where
+    BL: BorrowMut<L>,
+    L: TrChunkLoader<u8>,
+{
+    type IntoFuture = HdlcFinishFuture<'a, NonCancellableToken, BL, L>;
+    type Output = <Self::IntoFuture as Future>::Output;
+
+    fn into_future(self) -> Self::IntoFuture {
+        let cancel = NonCancellableToken::pinned();
+        HdlcFinishFuture::new(self, cancel)
+    }
+}
+
+impl<'a, BL, L> TrIntoFutureMayCancel<'a> for HdlcFinishAsync<'a, BL, L>
+where
+    BL: BorrowMut<L>,
+    L: TrChunkLoader<u8>,
+{
+    type MayCancelOutput = <Self as IntoFuture>::Output;
+
+    #[inline(always)]
+    fn may_cancel_with<C>(
+        self,
+        cancel: Pin<&'a mut C>,
+    ) -> impl Future<Output = Self::MayCancelOutput>
+    where
+        C: TrCancellationToken,
+    {
+        HdlcFinishAsync::may_cancel_with(self, cancel)
+    }
+}
+
+/// The future that finishes the frame of a `HdlcEncodeLoader`.
+///
+/// The units of the frame left to emit are kept in the loader, so that a
+/// finish that spans several wakeups resumes from where it stops.
+pub struct HdlcFinishFuture<'a, C, BL, L>
+where
+    C: TrCancellationToken,
+    BL: BorrowMut<L>,
+    L: TrChunkLoader<u8>,
+{
+    loader_: &'a mut HdlcEncodeLoader<BL, L>,
+    cancel_: Pin<&'a mut C>,
+}
+
+impl<'a, C, BL, L> HdlcFinishFuture<'a, C, BL, L>
+where
+    C: TrCancellationToken,
+    BL: BorrowMut<L>,
+    L: TrChunkLoader<u8>,
+{
+    pub fn new(
+        finish: HdlcFinishAsync<'a, BL, L>,
+        cancel: Pin<&'a mut C>,
+    ) -> Self {
+        HdlcFinishFuture {
+            loader_: finish.loader_,
+            cancel_: cancel,
+        }
+    }
+}
+
+impl<C, BL, L> Future for HdlcFinishFuture<'_, C, BL, L>
+where
+    C: TrCancellationToken,
+    BL: BorrowMut<L>,
+    L: TrChunkLoader<u8>,
+{
+    type Output = Result<(), L::IoAbort>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        this.loader_.poll_finish_(this.cancel_.as_mut(), cx)
+    }
+}
+
+/// Decodes HDLC-like frames (RFC 1662) from the inner filler.
+///
+/// A fill never crosses the end of a frame. If the frame ends before the fill
+/// is satisfied, the fill is aborted with `StuffError::EndOfFrame`. A frame
+/// aborted by the `0x7D 0x7E` sequence is reported as `StuffError::Malformed`.
+/// With FCS-16 enabled, the last two units of each frame are held back and
+/// verified rather than filled, and a mismatch is reported as
+/// `StuffError::FcsMismatch` at the end of the frame. Empty frames are skipped.
+///
+/// The encoded units are filled from the inner filler in chunks, and those
+/// after the end of a frame are kept for the next fill. If the inner filler
+/// fills nothing, the fill completes with the units decoded so far.
+pub struct HdlcDecodeFiller<BF, F>
+where
+    BF: BorrowMut<F>,
+    F: TrChunkFiller<u8>,
+{
+    _use_f_: PhantomData<F>,
+    filler_: BF,
+    stage_: StuffStage,
+    fcs16_: bool,
+    fcs_: Crc16,
+    held_: [u8; 2],
+    held_len_: usize,
+    escape_: bool,
+    in_frame_: bool,
+}
+
+impl<BF, F> HdlcDecodeFiller<BF, F>
+where
+    BF: BorrowMut<F>,
+    F: TrChunkFiller<u8>,
+{
+    pub const fn new(filler: BF) -> Self {
+        HdlcDecodeFiller {
+            _use_f_: PhantomData,
+            filler_: filler,
+            stage_: StuffStage::new(),
+            fcs16_: false,
+            fcs_: Crc16::X25,
+            held_: [0u8; 2],
+            held_len_: 0usize,
+            escape_: false,
+            in_frame_: false,
+        }
+    }
+
+    /// Whether to verify and strip the FCS-16 of each frame.
+    pub fn with_fcs16(self, fcs16: bool) -> Self {
+        HdlcDecodeFiller {
+            fcs16_: fcs16,
+            ..self
+        }
+    }
+
+    /// Whether the fills have started decoding a frame that is not ended yet.
+    pub const fn is_in_frame(&self) -> bool {
+        self.in_frame_
+    }
+
+    /// Number of units filled from the inner filler but not yet decoded.
+    pub const fn staged_len(&self) -> usize {
+        self.stage_.staged_len()
+    }
+
+    /// Returns the inner filler, dropping the units not yet decoded.
+    pub fn into_inner(self) -> BF {
+        self.filler_
+    }
+
+    /// Resets the state of the frame, and returns the outcome of the frame.
+    fn end_frame_(&mut self) -> Option<StuffError<F::IoAbort>> {
+        let escape = self.escape_;
+        let in_frame = self.in_frame_;
+        let held_len = self.held_len_;
+        let fcs = self.fcs_.sum();
+        self.fcs_.reset();
+        self.held_len_ = 0;
+        self.escape_ = false;
+        self.in_frame_ = false;
+        if escape {
+            return Option::Some(StuffError::Malformed);
+        }
+        if !in_frame {
+            return Option::None;
+        }
+        if self.fcs16_ {
+            if held_len < 2 {
+                return Option::Some(StuffError::Malformed);
+            }
+            if fcs != u16::from_le_bytes(self.held_) {
+                return Option::Some(StuffError::FcsMismatch);
+            }
+        }
+        Option::Some(StuffError::EndOfFrame)
+    }
+}
+
+impl<BF, F> TrChunkFiller<u8> for HdlcDecodeFiller<BF, F>
+where
+    BF: BorrowMut<F>,
+    F: TrChunkFiller<u8>,
+    F::IoAbort: Error,
+{
+    type IoAbort = ChunkIoAbort<StuffError<F::IoAbort>>;
+    type FillAsync<'a> = ChunkFillAsync<'a, Self, u8> where Self: 'a;
+
+    #[inline(always)]
+    fn fill_async<'a>(
+        &'a mut self,
+        target: &'a mut [u8],
+    ) -> Self::FillAsync<'a> {
+        ChunkFillAsync::new(self, target)
+    }
+
+    #[inline(always)]
+    fn fill_at_least_async<'a>(
+        &'a mut self,
+        target: &'a mut [u8],
+        min_len: usize,
+    ) -> Self::FillAsync<'a> {
+        ChunkFillAsync::new_at_least(self, target, min_len)
+    }
+}
+
+impl<BF, F> TrChunkFillWith<u8> for HdlcDecodeFiller<BF, F>
+where
+    BF: BorrowMut<F>,
+    F: TrChunkFiller<u8>,
+    F::IoAbort: Error,
+{
+    fn poll_fill_with<C>(
+        &mut self,
+        target: &mut [u8],
+        min_len: usize,
+        perform_len: &mut usize,
+        mut cancel: Pin<&mut C>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<usize, Self::IoAbort>>
+    where
+        C: TrCancellationToken,
+    {
+        while *perform_len < min_len {
+            let Option::Some(byte) = self.stage_.pop() else {
+                let filler = self.filler_.borrow_mut();
+                let r = self.stage_.poll_fill(filler, cancel.as_mut(), cx);
+                let Poll::Ready(r) = r else {
+                    return Poll::Pending;
+                };
+                match r {
+                    Result::Ok(0) => break,
+                    Result::Ok(_) => continue,
+                    Result::Err(a) => {
+                        let kind = a.kind();
+                        let e = StuffError::Io(a);
+                        return Poll::Ready(Result::Err(
+                            ChunkIoAbort::with_kind(*perform_len, e, kind),
+                        ));
+                    },
+                }
+            };
+            if byte == HDLC_FLAG {
+                let abort = match self.end_frame_() {
+                    Option::None => continue,
+                    Option::Some(e @ StuffError::EndOfFrame) => {
+                        ChunkIoAbort::end_of_stream(*perform_len, e)
+                    },
+                    Option::Some(e) => ChunkIoAbort::new(*perform_len, e),
+                };
+                return Poll::Ready(Result::Err(abort));
+            }
+            self.in_frame_ = true;
+            let decoded = if self.escape_ {
+                self.escape_ = false;
+                byte ^ HDLC_XOR
+            } else if byte == HDLC_ESC {
+                self.escape_ = true;
+                continue;
+            } else {
+                byte
+            };
+            if self.fcs16_ {
+                if self.held_len_ < 2 {
+                    self.held_[self.held_len_] = decoded;
+                    self.held_len_ += 1;
+                    continue;
+                }
+                self.fcs_.update(&self.held_[..1]);
+                target[*perform_len] = self.held_[0];
+                self.held_ = [self.held_[1], decoded];
+            } else {
+                target[*perform_len] = decoded;
+            }
+            *perform_len += 1;
+        }
+        Poll::Ready(Result::Ok(*perform_len))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::vec::Vec;
+
+    use super::*;
+    use crate::test_utils_::*;
+
+    fn encode(frames: &[&[u8]], fcs16: bool) -> Vec<u8> {
+        let loader = VecLoader::default();
+        let mut encoder = HdlcEncodeLoader::new(loader).with_fcs16(fcs16);
+        for frame in frames {
+            let n = block_on(
+                encoder.load_async(frame).may_cancel_with(no_cancel()),
+            );
+            assert_eq!(n.unwrap(), frame.len());
+            block_on(encoder.finish_frame_async().may_cancel_with(no_cancel()))
+                .unwrap();
+        }
+        encoder.into_inner().0
+    }
+
+    /// Decodes the next frame, which is ended before the target is full.
+    fn decode_frame<F>(
+        decoder: &mut HdlcDecodeFiller<F, F>,
+        target: &mut [u8],
+    ) -> Result<usize, ChunkIoAbort<StuffError<F::IoAbort>>>
+    where
+        F: TrChunkFiller<u8>,
+        F::IoAbort: Error,
+    {
+        let r = block_on(
+            decoder.fill_async(target).may_cancel_with(no_cancel()),
+        );
+        let a = r.unwrap_err();
+        match a.last_error() {
+            StuffError::EndOfFrame => Result::Ok(a.perform_len()),
+            _ => Result::Err(a),
+        }
+    }
+
+    #[test]
+    fn encode_escapes_and_flags() {
+        assert_eq!(
+            encode(&[&[HDLC_FLAG, HDLC_ESC, 0x01]], false),
+            [HDLC_FLAG, HDLC_ESC, 0x5E, HDLC_ESC, 0x5D, 0x01, HDLC_FLAG],
+        );
+        // The closing flag doubles as the opening flag of the next frame.
+        assert_eq!(
+            encode(&[b"a", b"b"], false),
+            [HDLC_FLAG, b'a', HDLC_FLAG, b'b', HDLC_FLAG],
+        );
+    }
+
+    #[test]
+    fn encode_fcs16() {
+        let mut expected = Vec::from([HDLC_FLAG]);
+        expected.extend_from_slice(b"123456789");
+        expected.extend_from_slice(&[0x6E, 0x90, HDLC_FLAG]);
+        assert_eq!(encode(&[b"123456789"], true), expected);
+    }
+
+    #[test]
+    fn round_trip() {
+        let long: Vec<u8> = (0..600usize).map(|i| (i * 29) as u8).collect();
+        let frames: [&[u8]; 4] =
+            [b"hello", &[HDLC_FLAG, HDLC_ESC, HDLC_FLAG], &long, b"x"];
+        for fcs16 in [false, true] {
+            let encoded = encode(&frames, fcs16);
+            for seg_len in [1, 5, 64, 4096] {
+                let filler = SliceFiller::new(&encoded, seg_len);
+                let mut decoder =
+                    HdlcDecodeFiller::new(filler).with_fcs16(fcs16);
+                let mut target = [0u8; 1024];
+                for frame in frames {
+                    let n = decode_frame(&mut decoder, &mut target).unwrap();
+                    assert_eq!(&target[..n], frame);
+                    assert!(!decoder.is_in_frame());
+                }
+                let fill = decoder.fill_async(&mut target);
+                let r = block_on(fill.may_cancel_with(no_cancel()));
+                assert!(r.unwrap_err().is_end_of_stream());
+            }
+        }
+    }
+
+    #[test]
+    fn fcs16_mismatch() {
+        let mut encoded = encode(&[b"123456789", b"ok"], true);
+        encoded[3] ^= 0x01;
+        let filler = SliceFiller::new(&encoded, 64);
+        let mut decoder = HdlcDecodeFiller::new(filler).with_fcs16(true);
+        let mut target = [0u8; 16];
+        let a = decode_frame(&mut decoder, &mut target).unwrap_err();
+        assert!(matches!(a.last_error(), StuffError::FcsMismatch));
+        assert_eq!(a.perform_len(), 9);
+        let n = decode_frame(&mut decoder, &mut target).unwrap();
+        assert_eq!(&target[..n], b"ok");
+    }
+
+    #[test]
+    fn malformed() {
+        let encoded = [
+            HDLC_FLAG, b'a', HDLC_ESC, HDLC_FLAG,
+            b'b', HDLC_FLAG,
+            HDLC_FLAG, b'c', b'd', HDLC_FLAG,
+        ];
+        let filler = SliceFiller::new(&encoded, 3);
+        let mut decoder = HdlcDecodeFiller::new(filler).with_fcs16(true);
+        let mut target = [0u8; 8];
+        // The frame aborted by the escape before the flag.
+        let a = decode_frame(&mut decoder, &mut target).unwrap_err();
+        assert!(matches!(a.last_error(), StuffError::Malformed));
+        // The frame too short to hold the FCS-16.
+        let a = decode_frame(&mut decoder, &mut target).unwrap_err();
+        assert!(matches!(a.last_error(), StuffError::Malformed));
+        assert_eq!(a.perform_len(), 0);
+        // The empty frame between the flags is skipped.
+        let a = decode_frame(&mut decoder, &mut target).unwrap_err();
+        assert!(matches!(a.last_error(), StuffError::FcsMismatch));
+        assert_eq!(a.perform_len(), 0);
+    }
+}
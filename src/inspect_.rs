@@ -0,0 +1,138 @@
+use core::{
+    borrow::BorrowMut,
+    cmp,
+    marker::PhantomData,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use abs_buff::x_deps::abs_sync;
+use abs_sync::cancellation::*;
+
+use crate::{
+    chunk_async_::{poll_fill_some, ChunkFillAsync, TrChunkFillWith},
+    TrChunkFiller, TrChunkIoAbort,
+};
+
+/// Passes each slice of units filled by the inner filler to a callback, for
+/// example to log or sniff the traffic.
+///
+/// The callback sees exactly the units the inner filler copies, including the
+/// partial slice before an abort. The counts and the abort of the inner
+/// filler are returned as they are.
+///
+/// There is no variant pushing the units into a `TrChunkLoader`, on purpose:
+/// a loader may pend or abort on its own, which would delay the fill or add
+/// the loader's abort to the error type, so the filler would no longer be
+/// transparent. To capture the traffic into a loader, push the units from
+/// the callback into a buffer that never waits, or mirror the loading side
+/// with a `TeeLoader`.
+pub struct InspectFiller<BF, F, G, T>
+where
+    BF: BorrowMut<F>,
+    F: TrChunkFiller<T>,
+    G: FnMut(&[T]),
+    T: Clone,
+{
+    _use_f_: PhantomData<F>,
+    _use_t_: PhantomData<[T]>,
+    filler_: BF,
+    inspect_: G,
+}
+
+impl<BF, F, G, T> InspectFiller<BF, F, G, T>
+where
+    BF: BorrowMut<F>,
+    F: TrChunkFiller<T>,
+    G: FnMut(&[T]),
+    T: Clone,
+{
+    pub const fn new(filler: BF, inspect: G) -> Self {
+        InspectFiller {
+            _use_f_: PhantomData,
+            _use_t_: PhantomData,
+            filler_: filler,
+            inspect_: inspect,
+        }
+    }
+
+    pub fn into_inner(self) -> (BF, G) {
+        (self.filler_, self.inspect_)
+    }
+}
+
+impl<BF, F, G, T> TrChunkFiller<T> for InspectFiller<BF, F, G, T>
+where
+    BF: BorrowMut<F>,
+    F: TrChunkFiller<T>,
+    G: FnMut(&[T]),
+    T: Clone,
+{
+    type IoAbort = F::IoAbort;
+    type FillAsync<'a> = ChunkFillAsync<'a, Self, T> where Self: 'a;
+
+    #[inline(always)]
+    fn fill_async<'a>(
+        &'a mut self,
+        target: &'a mut [T],
+    ) -> Self::FillAsync<'a> {
+        ChunkFillAsync::new(self, target)
+    }
+
+    #[inline(always)]
+    fn fill_at_least_async<'a>(
+        &'a mut self,
+        target: &'a mut [T],
+        min_len: usize,
+    ) -> Self::FillAsync<'a> {
+        ChunkFillAsync::new_at_least(self, target, min_len)
+    }
+}
+
+impl<BF, F, G, T> TrChunkFillWith<T> for InspectFiller<BF, F, G, T>
+where
+    BF: BorrowMut<F>,
+    F: TrChunkFiller<T>,
+    G: FnMut(&[T]),
+    T: Clone,
+{
+    fn poll_fill_with<C>(
+        &mut self,
+        target: &mut [T],
+        min_len: usize,
+        perform_len: &mut usize,
+        mut cancel: Pin<&mut C>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<usize, Self::IoAbort>>
+    where
+        C: TrCancellationToken,
+    {
+        let filler = self.filler_.borrow_mut();
+        while *perform_len < min_len {
+            let rest = &mut target[*perform_len..];
+            let r = poll_fill_some(filler, &mut *rest, cancel.as_mut(), cx);
+            let Poll::Ready(r) = r else {
+                return Poll::Pending;
+            };
+            let n = match &r {
+                Result::Ok(n) => *n,
+                Result::Err(a) => a.perform_len(),
+            };
+            let n = cmp::min(n, rest.len());
+            if n > 0 {
+                (self.inspect_)(&rest[..n]);
+            }
+            let done_len = *perform_len;
+            *perform_len += n;
+            match r {
+                Result::Ok(0) => break,
+                Result::Ok(_) => (),
+                Result::Err(a) => {
+                    let a = a.add_perform_len(done_len);
+                    return Poll::Ready(Result::Err(a));
+                },
+            }
+        }
+        Poll::Ready(Result::Ok(*perform_len))
+    }
+}
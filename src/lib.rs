@@ -1,15 +1,76 @@
 #![no_std]
 
+#[cfg(test)]
+extern crate std;
+
 mod abs_;
+mod chain_;
+mod checksum_;
+mod chunk_async_;
+mod cobs_;
+mod delim_;
+mod digest_;
+mod endian_;
+mod filler_read_;
+mod frame_;
+mod hdlc_;
+mod inspect_;
+mod line_;
+mod loader_write_;
 mod peeker_;
+mod pump_;
 mod reader_;
+mod relay_;
+mod slip_;
+mod stuff_;
+mod take_;
+mod tee_;
+#[cfg(test)]
+mod test_utils_;
+mod varint_;
 mod writer_;
 
-pub use abs_::{ChunkIoAbort, TrChunkFiller, TrChunkLoader, TrChunkIoAbort};
-pub use peeker_::BuffPeekAsChunkFiller;
+pub use abs_::{
+    ChunkIoAbort, ChunkIoAbortKind, TrBuffClosed, TrChunkFiller, TrChunkLoader,
+    TrChunkIoAbort,
+};
+pub use chain_::{ChainError, ChainFiller};
+pub use checksum_::{
+    Adler32, ChecksumError, Crc16, Crc32, Crc32c, TrChecksum,
+};
+pub use chunk_async_::{
+    ChunkFillAsync, ChunkFillFuture, ChunkLoadAsync, ChunkLoadFuture,
+    TrChunkFillWith, TrChunkLoadWith,
+};
+pub use cobs_::{CobsDecodeFiller, CobsEncodeLoader, CobsError};
+pub use delim_::{DelimError, DelimFrameReader, EmptyDelimError};
+pub use digest_::{DigestFiller, DigestLoader, TrDigest};
+pub use endian_::{
+    Endian, NumError, TrChunkNumFiller, TrChunkNumLoader, TrEndianNum,
+};
+pub use filler_read_::{ChunkFillerAsBuffRead, EmptyStagingError};
+pub use frame_::{
+    FrameError, FrameHeader, LenPrefixFrameReader, LenPrefixFrameWriter,
+};
+pub use hdlc_::{HdlcDecodeFiller, HdlcEncodeLoader};
+pub use inspect_::InspectFiller;
+pub use line_::{Line, LineError, LineReader};
+pub use loader_write_::ChunkLoaderAsBuffWrite;
+pub use peeker_::{BuffPeekAsChunkFiller, PeekError};
+pub use pump_::{
+    ChunkPumpAbort, ChunkPumpAsync, ChunkPumpError, ChunkPumpFuture,
+};
 pub use reader_::BuffReadAsChunkFiller;
+pub use relay_::BuffIterRelay;
+pub use slip_::{SlipDecodeFiller, SlipEncodeLoader};
+pub use stuff_::StuffError;
+pub use take_::{TakeError, TakeFiller, TakeLoader};
+pub use tee_::{TeeAbort, TeeError, TeeLoader};
+pub use varint_::{
+    TrChunkVarIntFiller, TrChunkVarIntLoader, TrVarInt, VarIntError,
+};
 pub use writer_::BuffWriteAsChunkLoader;
 
 pub mod x_deps {
     pub use abs_buff;
-}
\ No newline at end of file
+}
@@ -0,0 +1,601 @@
+use core::{
+    borrow::BorrowMut,
+    cmp,
+    error::Error,
+    fmt,
+    future::{Future, IntoFuture},
+    marker::PhantomData,
+    mem,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use pin_utils::pin_mut;
+
+use abs_buff::{x_deps::abs_sync, TrBuffIterPeek, TrBuffIterRead};
+use abs_sync::{cancellation::*, x_deps::pin_utils};
+
+use crate::{
+    delim_::poll_consume,
+    ChunkIoAbort, ChunkIoAbortKind, TrBuffClosed,
+};
+
+/// The error of reading a line.
+#[derive(Debug)]
+pub enum LineError<PE, RE>
+where
+    PE: Error,
+    RE: Error,
+{
+    /// The line is not valid UTF-8, reports the length of the valid prefix.
+    /// The whole line, including the line end, is consumed from the buffer,
+    /// and the front of it is left in the target.
+    Utf8(usize),
+    /// The buffer has no more units, and there is no line to read.
+    EndOfStream,
+    /// The peek is aborted while scanning for the line end, the
+    /// `perform_len` of the abort reports the number of units consumed.
+    Peek(ChunkIoAbort<PE>),
+    /// The read is aborted while consuming the line.
+    Read(ChunkIoAbort<RE>),
+}
+
+impl<PE, RE> LineError<PE, RE>
+where
+    PE: Error,
+    RE: Error,
+{
+    /// The classification of the aborted IO. A line that is not valid UTF-8
+    /// is classified as `ChunkIoAbortKind::Fault`.
+    pub fn kind(&self) -> ChunkIoAbortKind {
+        match self {
+            LineError::Utf8(_) => ChunkIoAbortKind::Fault,
+            LineError::EndOfStream => ChunkIoAbortKind::EndOfStream,
+            LineError::Peek(a) => a.kind(),
+            LineError::Read(a) => a.kind(),
+        }
+    }
+}
+
+impl<PE, RE> fmt::Display for LineError<PE, RE>
+where
+    PE: Error,
+    RE: Error,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LineError::Utf8(n) => {
+                write!(f, "line not valid UTF-8 after {n} units")
+            },
+            LineError::EndOfStream => write!(f, "no more line"),
+            LineError::Peek(a) => write!(f, "line scan: {a}"),
+            LineError::Read(a) => write!(f, "line consume: {a}"),
+        }
+    }
+}
+
+impl<PE, RE> Error for LineError<PE, RE>
+where
+    PE: Error,
+    RE: Error,
+{}
+
+/// A line read into the target buffer, without the line end.
+#[derive(Clone, Copy, Debug)]
+pub struct Line<'a> {
+    line_: &'a str,
+    truncated_: bool,
+}
+
+impl<'a> Line<'a> {
+    pub const fn as_str(&self) -> &'a str {
+        self.line_
+    }
+
+    /// Whether the line is cut short because the target buffer is full.
+    ///
+    /// The rest of the line is left in the buffer, and will be returned by
+    /// the next read.
+    pub const fn is_truncated(&self) -> bool {
+        self.truncated_
+    }
+}
+
+/// Reads UTF-8 lines ended with either `\n` or `\r\n` from a buffer.
+pub struct LineReader<B, P>
+where
+    B: BorrowMut<P>,
+    P: TrBuffIterPeek<u8> + TrBuffIterRead<u8>,
+{
+    _use_p_: PhantomData<P>,
+    buffer_: B,
+    classify_peek_: fn(&P, &<P as TrBuffIterPeek<u8>>::Err) -> ChunkIoAbortKind,
+    classify_read_: fn(&P, &<P as TrBuffIterRead<u8>>::Err) -> ChunkIoAbortKind,
+}
+
+impl<B, P> LineReader<B, P>
+where
+    B: BorrowMut<P>,
+    P: TrBuffIterPeek<u8> + TrBuffIterRead<u8> + TrBuffClosed,
+{
+    /// An error from the peek or the read of the buffer is classified as the
+    /// end of stream once the buffer is closed, and as a fault otherwise.
+    pub const fn new_with_closed_state(buffer: B) -> Self {
+        LineReader::new_with_classify(
+            buffer,
+            ChunkIoAbortKind::from_closed_state,
+            ChunkIoAbortKind::from_closed_state,
+        )
+    }
+}
+
+impl<B, P> LineReader<B, P>
+where
+    B: BorrowMut<P>,
+    P: TrBuffIterPeek<u8> + TrBuffIterRead<u8>,
+{
+    /// Every error from the peek or the read of the buffer is classified as a
+    /// fault, see `new_with_closed_state` and `new_with_classify` to tell the
+    /// end of stream.
+    pub const fn new(buffer: B) -> Self {
+        LineReader::new_with_classify(
+            buffer,
+            ChunkIoAbortKind::always_fault,
+            ChunkIoAbortKind::always_fault,
+        )
+    }
+
+    /// Create with how an error from the peek and from the read of the buffer
+    /// are classified, for example for a buffer that does not tell whether it
+    /// is closed.
+    ///
+    /// An error while the cancellation token is signalled is always
+    /// classified as `ChunkIoAbortKind::Cancelled`.
+    pub const fn new_with_classify(
+        buffer: B,
+        classify_peek: fn(
+            &P,
+            &<P as TrBuffIterPeek<u8>>::Err,
+        ) -> ChunkIoAbortKind,
+        classify_read: fn(
+            &P,
+            &<P as TrBuffIterRead<u8>>::Err,
+        ) -> ChunkIoAbortKind,
+    ) -> Self {
+        LineReader {
+            _use_p_: PhantomData,
+            buffer_: buffer,
+            classify_peek_: classify_peek,
+            classify_read_: classify_read,
+        }
+    }
+
+    /// Reads a line into the front of `target`.
+    ///
+    /// If the line does not fit in the target, it is cut at the last UTF-8
+    /// character boundary within the target, and reported as truncated. The
+    /// last line of the buffer is read even if it has no line end.
+    pub fn read_line_async<'a>(
+        &'a mut self,
+        target: &'a mut [u8],
+    ) -> LineReadAsync<'a, B, P> {
+        LineReadAsync::new(self, target)
+    }
+
+    pub fn into_inner(self) -> B {
+        self.buffer_
+    }
+}
+
+pub struct LineReadAsync<'a, B, P>
+where
+    B: BorrowMut<P>,
+    P: TrBuffIterPeek<u8> + TrBuffIterRead<u8>,
+{
+    reader_: &'a mut LineReader<B, P>,
+    target_: &'a mut [u8],
+}
+
+impl<'a, B, P> LineReadAsync<'a, B, P>
+where
+    B: BorrowMut<P>,
+    P: TrBuffIterPeek<u8> + TrBuffIterRead<u8>,
+{
+    pub fn new(reader: &'a mut LineReader<B, P>, target: &'a mut [u8]) -> Self {
+        LineReadAsync {
+            reader_: reader,
+            target_: target,
+        }
+    }
+
+    pub fn may_cancel_with<C>(
+        self,
+        cancel: Pin<&'a mut C>,
+    ) -> LineReadFuture<'a, C, B, P>
+    where
+        C: TrCancellationToken,
+    {
+        LineReadFuture::new(self, cancel)
+    }
+}
+
+impl<'a, B, P> IntoFuture for LineReadAsync<'a, B, P>
+where
+    B: BorrowMut<P>,
+    P: TrBuffIterPeek<u8> + TrBuffIterRead<u8>,
+{
+    type IntoFuture = LineReadFuture<'a, NonCancellableToken, B, P>;
+    type Output = <Self::IntoFuture as Future>::Output;
+
+    fn into_future(self) -> Self::IntoFuture {
+        let cancel = NonCancellableToken::pinned();
+        LineReadFuture::new(self, cancel)
+    }
+}
+
+impl<'a, B, P> TrIntoFutureMayCancel<'a> for LineReadAsync<'a, B, P>
+where
+    B: BorrowMut<P>,
+    P: TrBuffIterPeek<u8> + TrBuffIterRead<u8>,
+{
+    type MayCancelOutput = <Self as IntoFuture>::Output;
+
+    #[inline(always)]
+    fn may_cancel_with<C>(
+        self,
+        cancel: Pin<&'a mut C>,
+    ) -> impl Future<Output = Self::MayCancelOutput>
+    where
+        C: TrCancellationToken,
+    {
+        LineReadAsync::may_cancel_with(self, cancel)
+    }
+}
+
+/// The future that reads a line with a `LineReader`.
+///
+/// The progress of the search for the line end, and of the units consumed
+/// from the buffer, is kept in the future, so that a read that spans several
+/// wakeups resumes from where it stops.
+pub struct LineReadFuture<'a, C, B, P>
+where
+    C: TrCancellationToken,
+    B: BorrowMut<P>,
+    P: TrBuffIterPeek<u8> + TrBuffIterRead<u8>,
+{
+    reader_: &'a mut LineReader<B, P>,
+    target_: &'a mut [u8],
+    scan_: LineScan,
+    /// The end of the units to consume before scanning on.
+    consume_to_: Option<usize>,
+    /// The length of the line found including the line end, the length of
+    /// the line to take, and whether it is truncated.
+    line_: Option<(usize, usize, bool)>,
+    cancel_: Pin<&'a mut C>,
+}
+
+impl<'a, C, B, P> LineReadFuture<'a, C, B, P>
+where
+    C: TrCancellationToken,
+    B: BorrowMut<P>,
+    P: TrBuffIterPeek<u8> + TrBuffIterRead<u8>,
+{
+    pub fn new(read: LineReadAsync<'a, B, P>, cancel: Pin<&'a mut C>) -> Self {
+        let capacity = read.target_.len();
+        LineReadFuture {
+            reader_: read.reader_,
+            target_: read.target_,
+            scan_: LineScan::new(capacity),
+            consume_to_: Option::None,
+            line_: Option::None,
+            cancel_: cancel,
+        }
+    }
+}
+
+impl<'a, C, B, P> Future for LineReadFuture<'a, C, B, P>
+where
+    C: TrCancellationToken,
+    B: BorrowMut<P>,
+    P: TrBuffIterPeek<u8> + TrBuffIterRead<u8>,
+{
+    type Output = Result<
+        Line<'a>,
+        LineError<
+            <P as TrBuffIterPeek<u8>>::Err,
+            <P as TrBuffIterRead<u8>>::Err,
+        >,
+    >;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        let classify_peek = this.reader_.classify_peek_;
+        let classify_read = this.reader_.classify_read_;
+        let buffer = this.reader_.buffer_.borrow_mut();
+        let scan = &mut this.scan_;
+        loop {
+            if let Option::Some((frame_len, line_len, truncated)) = this.line_ {
+                let copy_len = cmp::min(line_len, this.target_.len());
+                let r = poll_consume(
+                    buffer,
+                    &mut scan.consume_len_,
+                    frame_len,
+                    &mut this.target_[..copy_len],
+                    classify_read,
+                    this.cancel_.as_mut(),
+                    cx,
+                );
+                let Poll::Ready(r) = r else {
+                    return Poll::Pending;
+                };
+                if let Result::Err(a) = r {
+                    return Poll::Ready(Result::Err(LineError::Read(a)));
+                }
+                if scan.invalid_ {
+                    let e = LineError::Utf8(scan.valid_len_);
+                    return Poll::Ready(Result::Err(e));
+                }
+                let target: &'a mut [u8] = mem::take(&mut this.target_);
+                let r = match core::str::from_utf8(&target[..line_len]) {
+                    Result::Ok(line) => Result::Ok(Line {
+                        line_: line,
+                        truncated_: truncated,
+                    }),
+                    Result::Err(e) => {
+                        Result::Err(LineError::Utf8(e.valid_up_to()))
+                    },
+                };
+                return Poll::Ready(r);
+            }
+            if let Option::Some(consume_to) = this.consume_to_ {
+                let r = poll_consume(
+                    buffer,
+                    &mut scan.consume_len_,
+                    consume_to,
+                    this.target_,
+                    classify_read,
+                    this.cancel_.as_mut(),
+                    cx,
+                );
+                let Poll::Ready(r) = r else {
+                    return Poll::Pending;
+                };
+                if let Result::Err(a) = r {
+                    return Poll::Ready(Result::Err(LineError::Read(a)));
+                }
+                this.consume_to_ = Option::None;
+            }
+            let r = scan.poll_scan(buffer, this.cancel_.as_mut(), cx);
+            let Poll::Ready(r) = r else {
+                return Poll::Pending;
+            };
+            let step = match r {
+                Result::Ok(step) => step,
+                Result::Err(last_error) => {
+                    let consume_len = scan.consume_len_;
+                    let abort = if this.cancel_.is_cancelled() {
+                        ChunkIoAbort::cancelled(consume_len, last_error)
+                    } else {
+                        let kind = classify_peek(buffer, &last_error);
+                        ChunkIoAbort::with_kind(consume_len, last_error, kind)
+                    };
+                    return Poll::Ready(Result::Err(LineError::Peek(abort)));
+                },
+            };
+            let target_len = this.target_.len();
+            match step {
+                ScanStep::Found { frame_len, line_len } => {
+                    this.line_ = Option::Some((frame_len, line_len, false));
+                },
+                ScanStep::Truncated(line_len) => {
+                    this.line_ = Option::Some((line_len, line_len, true));
+                },
+                ScanStep::Scanned => (),
+                ScanStep::Stalled if scan.scan_len_ == scan.consume_len_ => {
+                    if scan.scan_len_ == 0 {
+                        return Poll::Ready(Result::Err(LineError::EndOfStream));
+                    }
+                    // The last line is not ended, and is already consumed.
+                    scan.invalid_ |= !scan.utf8_.is_complete();
+                    let line_len = cmp::min(scan.scan_len_, target_len);
+                    let truncated = scan.scan_len_ > target_len;
+                    let line = (scan.scan_len_, line_len, truncated);
+                    this.line_ = Option::Some(line);
+                },
+                ScanStep::Stalled => {
+                    this.consume_to_ = Option::Some(scan.scan_len_);
+                },
+            }
+        }
+    }
+}
+
+/// The result of a single peek in search of the line end.
+enum ScanStep {
+    /// The line end is found, `frame_len` includes the line end.
+    Found {
+        frame_len: usize,
+        line_len: usize,
+    },
+    /// The line does not fit in the target, with the length to take.
+    Truncated(usize),
+    /// More units are scanned, but the line end is not found yet.
+    Scanned,
+    /// The peek shows no more units than those already scanned.
+    Stalled,
+}
+
+/// Validates UTF-8 one byte at a time, according to the table 3-7 of the
+/// Unicode Standard.
+struct Utf8Validator {
+    need_: u8,
+    lower_: u8,
+    upper_: u8,
+}
+
+impl Utf8Validator {
+    const fn new() -> Self {
+        Utf8Validator {
+            need_: 0,
+            lower_: 0x80,
+            upper_: 0xBF,
+        }
+    }
+
+    /// Whether the bytes so far end on a character boundary.
+    const fn is_complete(&self) -> bool {
+        self.need_ == 0
+    }
+
+    /// Number of bytes still missing from the current character.
+    const fn need(&self) -> usize {
+        self.need_ as usize
+    }
+
+    /// Returns false if `byte` makes the sequence invalid.
+    fn advance(&mut self, byte: u8) -> bool {
+        if self.need_ > 0 {
+            if byte < self.lower_ || byte > self.upper_ {
+                return false;
+            }
+            self.need_ -= 1;
+            self.lower_ = 0x80;
+            self.upper_ = 0xBF;
+            return true;
+        }
+        let (need, lower, upper) = match byte {
+            0x00..=0x7F => return true,
+            0xC2..=0xDF => (1, 0x80, 0xBF),
+            0xE0 => (2, 0xA0, 0xBF),
+            0xE1..=0xEC | 0xEE..=0xEF => (2, 0x80, 0xBF),
+            0xED => (2, 0x80, 0x9F),
+            0xF0 => (3, 0x90, 0xBF),
+            0xF1..=0xF3 => (3, 0x80, 0xBF),
+            0xF4 => (3, 0x80, 0x8F),
+            _ => return false,
+        };
+        self.need_ = need;
+        self.lower_ = lower;
+        self.upper_ = upper;
+        true
+    }
+}
+
+/// The progress of the search for the line end across peeks, validating
+/// UTF-8 along the way.
+///
+/// A `\r` right at the capacity is scanned as well, since it may be the
+/// beginning of a `\r\n` line end. Once the line is found invalid, it is
+/// scanned up to the line end regardless of the capacity, so that the whole
+/// line is consumed.
+struct LineScan {
+    capacity_: usize,
+    utf8_: Utf8Validator,
+    /// Number of units of the line scanned.
+    scan_len_: usize,
+    /// Number of units of the line consumed from the buffer, which are not
+    /// shown by the peek any more.
+    consume_len_: usize,
+    /// Length of the valid prefix that ends on a character boundary.
+    valid_len_: usize,
+    prev_cr_: bool,
+    invalid_: bool,
+}
+
+impl LineScan {
+    const fn new(capacity: usize) -> Self {
+        LineScan {
+            capacity_: capacity,
+            utf8_: Utf8Validator::new(),
+            scan_len_: 0,
+            consume_len_: 0,
+            valid_len_: 0,
+            prev_cr_: false,
+            invalid_: false,
+        }
+    }
+
+    /// Peeks the buffer once, and scans the units not yet scanned.
+    ///
+    /// Before reporting `ScanStep::Stalled`, which lets the caller consume
+    /// the units scanned, a character that cannot fit in the capacity any
+    /// more is reported as `ScanStep::Truncated`, so that the units after
+    /// the cut stay in the buffer. A `\r` right at the capacity is consumed
+    /// along with the others, and is lost if the line turns out to be cut
+    /// there.
+    fn poll_scan<P, C>(
+        &mut self,
+        buffer: &mut P,
+        cancel: Pin<&mut C>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<ScanStep, <P as TrBuffIterPeek<u8>>::Err>>
+    where
+        P: TrBuffIterPeek<u8>,
+        C: TrCancellationToken,
+    {
+        let capacity = self.capacity_;
+        let peek = buffer.peek_async().may_cancel_with(cancel);
+        pin_mut!(peek);
+        let Poll::Ready(r) = peek.poll(cx) else {
+            return Poll::Pending;
+        };
+        let src_iter = match r {
+            Result::Ok(src_iter) => src_iter,
+            Result::Err(e) => return Poll::Ready(Result::Err(e)),
+        };
+        let prev_len = self.scan_len_;
+        let mut skip_len = self.scan_len_ - self.consume_len_;
+        for src in src_iter.into_iter() {
+            let src_len = src.len();
+            if skip_len >= src_len {
+                skip_len -= src_len;
+                continue;
+            }
+            for &byte in src[skip_len..].iter() {
+                let scan_len = self.scan_len_;
+                if byte == b'\n' {
+                    if !self.utf8_.is_complete() {
+                        self.invalid_ = true;
+                    }
+                    let line_len = if self.prev_cr_ {
+                        scan_len - 1
+                    } else {
+                        scan_len
+                    };
+                    let frame_len = scan_len + 1;
+                    let step = ScanStep::Found { frame_len, line_len };
+                    return Poll::Ready(Result::Ok(step));
+                }
+                if !self.invalid_ {
+                    if scan_len > capacity
+                        || (scan_len == capacity && byte != b'\r')
+                    {
+                        let step = ScanStep::Truncated(self.valid_len_);
+                        return Poll::Ready(Result::Ok(step));
+                    }
+                    if !self.utf8_.advance(byte) {
+                        self.invalid_ = true;
+                    }
+                }
+                self.scan_len_ += 1;
+                self.prev_cr_ = byte == b'\r';
+                if !self.invalid_
+                    && self.utf8_.is_complete()
+                    && self.scan_len_ <= capacity
+                {
+                    self.valid_len_ = self.scan_len_;
+                }
+            }
+            skip_len = 0;
+        }
+        if self.scan_len_ != prev_len {
+            return Poll::Ready(Result::Ok(ScanStep::Scanned));
+        }
+        let char_end = self.scan_len_ + self.utf8_.need();
+        if !self.invalid_ && !self.utf8_.is_complete() && char_end > capacity {
+            let step = ScanStep::Truncated(self.valid_len_);
+            return Poll::Ready(Result::Ok(step));
+        }
+        Poll::Ready(Result::Ok(ScanStep::Stalled))
+    }
+}
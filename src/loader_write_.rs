@@ -0,0 +1,441 @@
+use core::{
+    borrow::BorrowMut,
+    cmp,
+    error::Error,
+    future::{Future, IntoFuture},
+    marker::PhantomData,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use abs_buff::{x_deps::abs_sync, TrBuffIterWrite};
+use abs_sync::cancellation::*;
+
+use crate::{
+    chunk_async_::poll_load_some, filler_read_::EmptyStagingError,
+    TrChunkIoAbort, TrChunkLoader,
+};
+
+/// Exposes a chunk loader as a buffer writer.
+///
+/// The segments handed out are taken from the staging buffer `S`, and are
+/// committed as soon as they are handed out. The committed units are loaded
+/// into the chunk loader when the staging buffer is full, or explicitly by
+/// `flush_async`.
+///
+/// Since there is no async drop, the staged units are not loaded when this
+/// writer is dropped or taken apart by `into_inner`. So `flush_async` must be
+/// awaited after the last write, otherwise the staged units are lost.
+pub struct ChunkLoaderAsBuffWrite<BL, L, S, T>
+where
+    BL: BorrowMut<L>,
+    L: TrChunkLoader<T>,
+    S: BorrowMut<[T]>,
+    T: Clone,
+{
+    _use_l_: PhantomData<L>,
+    _use_t_: PhantomData<[T]>,
+    loader_: BL,
+    staging_: S,
+    staged_len_: usize,
+}
+
+impl<BL, L, S, T> ChunkLoaderAsBuffWrite<BL, L, S, T>
+where
+    BL: BorrowMut<L>,
+    L: TrChunkLoader<T>,
+    S: BorrowMut<[T]>,
+    T: Clone,
+{
+    /// Fails if the staging buffer is empty, which could never hand out any
+    /// segment.
+    pub fn new(loader: BL, staging: S) -> Result<Self, EmptyStagingError> {
+        if staging.borrow().is_empty() {
+            return Result::Err(EmptyStagingError);
+        }
+        Result::Ok(ChunkLoaderAsBuffWrite {
+            _use_l_: PhantomData,
+            _use_t_: PhantomData,
+            loader_: loader,
+            staging_: staging,
+            staged_len_: 0usize,
+        })
+    }
+
+    /// Number of units committed into the staging buffer but not yet loaded.
+    pub const fn staged_len(&self) -> usize {
+        self.staged_len_
+    }
+
+    pub fn write_async(
+        &mut self,
+        length: usize,
+    ) -> ChunkLoaderWriteAsync<'_, BL, L, S, T> {
+        ChunkLoaderWriteAsync::new(self, length)
+    }
+
+    /// Load all the staged units into the chunk loader.
+    ///
+    /// Must be awaited after the last write, since dropping the writer does
+    /// not load the staged units.
+    pub fn flush_async(&mut self) -> ChunkLoaderFlushAsync<'_, BL, L, S, T> {
+        ChunkLoaderFlushAsync::new(self)
+    }
+
+    /// Returns the loader and the staging buffer, discarding staged units.
+    pub fn into_inner(self) -> (BL, S) {
+        (self.loader_, self.staging_)
+    }
+
+    /// Polls the loading of the staged units, adding the units loaded to
+    /// `load_len`.
+    fn poll_flush_<C>(
+        &mut self,
+        load_len: &mut usize,
+        mut cancel: Pin<&mut C>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<usize, L::IoAbort>>
+    where
+        C: TrCancellationToken,
+    {
+        let loader = self.loader_.borrow_mut();
+        let staging = self.staging_.borrow_mut();
+        while self.staged_len_ > 0 {
+            let staged_len = self.staged_len_;
+            let source = &staging[..staged_len];
+            let r = poll_load_some(loader, source, cancel.as_mut(), cx);
+            let Poll::Ready(r) = r else {
+                return Poll::Pending;
+            };
+            let n = match &r {
+                Result::Ok(n) => *n,
+                Result::Err(a) => a.perform_len(),
+            };
+            let n = cmp::min(n, staged_len);
+            staging[..staged_len].rotate_left(n);
+            self.staged_len_ = staged_len - n;
+            let done_len = *load_len;
+            *load_len += n;
+            match r {
+                Result::Ok(0) => break,
+                Result::Ok(_) => (),
+                Result::Err(a) => {
+                    let a = a.add_perform_len(done_len);
+                    return Poll::Ready(Result::Err(a));
+                },
+            }
+        }
+        Poll::Ready(Result::Ok(*load_len))
+    }
+}
+
+impl<BL, L, S, T> TrBuffIterWrite<T> for ChunkLoaderAsBuffWrite<BL, L, S, T>
+where
+    BL: BorrowMut<L>,
+    L: TrChunkLoader<T>,
+    L::IoAbort: Error,
+    S: BorrowMut<[T]>,
+    T: Clone,
+{
+    type Err = L::IoAbort;
+    type SliceMut<'a> = &'a mut [T] where Self: 'a;
+    type BuffIter<'a> = core::option::IntoIter<&'a mut [T]> where Self: 'a;
+    type WriteAsync<'a> = ChunkLoaderWriteAsync<'a, BL, L, S, T> where Self: 'a;
+
+    #[inline(always)]
+    fn write_async(&mut self, length: usize) -> Self::WriteAsync<'_> {
+        ChunkLoaderAsBuffWrite::write_async(self, length)
+    }
+}
+
+pub struct ChunkLoaderWriteAsync<'a, BL, L, S, T>
+where
+    BL: BorrowMut<L>,
+    L: TrChunkLoader<T>,
+    S: BorrowMut<[T]>,
+    T: Clone,
+{
+    writer_: &'a mut ChunkLoaderAsBuffWrite<BL, L, S, T>,
+    length_: usize,
+}
+
+impl<'a, BL, L, S, T> ChunkLoaderWriteAsync<'a, BL, L, S, T>
+where
+    BL: BorrowMut<L>,
+    L: TrChunkLoader<T>,
+    S: BorrowMut<[T]>,
+    T: Clone,
+{
+    pub fn new(
+        writer: &'a mut ChunkLoaderAsBuffWrite<BL, L, S, T>,
+        length: usize,
+    ) -> Self {
+        ChunkLoaderWriteAsync {
+            writer_: writer,
+            length_: length,
+        }
+    }
+
+    pub fn may_cancel_with<C>(
+        self,
+        cancel: Pin<&'a mut C>,
+    ) -> ChunkLoaderWriteFuture<'a, C, BL, L, S, T>
+    where
+        C: TrCancellationToken,
+    {
+        ChunkLoaderWriteFuture::new(self, cancel)
+    }
+}
+
+impl<'a, BL, L, S, T> IntoFuture for ChunkLoaderWriteAsync<'a, BL, L, S, T>
+where
+    BL: BorrowMut<L>,
+    L: TrChunkLoader<T>,
+    S: BorrowMut<[T]>,
+    T: Clone,
+{
+    type IntoFuture =
+        ChunkLoaderWriteFuture<'a, NonCancellableToken, BL, L, S, T>;
+    type Output = <Self::IntoFuture as Future>::Output;
+
+    fn into_future(self) -> Self::IntoFuture {
+        let cancel = NonCancellableToken::pinned();
+        ChunkLoaderWriteFuture::new(self, cancel)
+    }
+}
+
+impl<'a, BL, L, S, T> TrIntoFutureMayCancel<'a>
+for ChunkLoaderWriteAsync<'a, BL, L, S, T>
+where
+    BL: BorrowMut<L>,
+    L: TrChunkLoader<T>,
+    S: BorrowMut<[T]>,
+    T: Clone,
+{
+    type MayCancelOutput = <Self as IntoFuture>::Output;
+
+    #[inline(always)]
+    fn may_cancel_with<C>(
+        self,
+        cancel: Pin<&'a mut C>,
+    ) -> impl Future<Output = Self::MayCancelOutput>
+    where
+        C: TrCancellationToken,
+    {
+        ChunkLoaderWriteAsync::may_cancel_with(self, cancel)
+    }
+}
+
+/// The future that hands out a segment of the staging buffer of a
+/// `ChunkLoaderAsBuffWrite`, flushing it first if it is full.
+///
+/// If the loader accepts none of the staged units, the segment handed out
+/// is empty.
+pub struct ChunkLoaderWriteFuture<'a, C, BL, L, S, T>
+where
+    C: TrCancellationToken,
+    BL: BorrowMut<L>,
+    L: TrChunkLoader<T>,
+    S: BorrowMut<[T]>,
+    T: Clone,
+{
+    writer_: Option<&'a mut ChunkLoaderAsBuffWrite<BL, L, S, T>>,
+    length_: usize,
+    load_len_: usize,
+    cancel_: Pin<&'a mut C>,
+}
+
+impl<'a, C, BL, L, S, T> ChunkLoaderWriteFuture<'a, C, BL, L, S, T>
+where
+    C: TrCancellationToken,
+    BL: BorrowMut<L>,
+    L: TrChunkLoader<T>,
+    S: BorrowMut<[T]>,
+    T: Clone,
+{
+    pub fn new(
+        write: ChunkLoaderWriteAsync<'a, BL, L, S, T>,
+        cancel: Pin<&'a mut C>,
+    ) -> Self {
+        ChunkLoaderWriteFuture {
+            writer_: Option::Some(write.writer_),
+            length_: write.length_,
+            load_len_: 0usize,
+            cancel_: cancel,
+        }
+    }
+}
+
+impl<'a, C, BL, L, S, T> Future for ChunkLoaderWriteFuture<'a, C, BL, L, S, T>
+where
+    C: TrCancellationToken,
+    BL: BorrowMut<L>,
+    L: TrChunkLoader<T>,
+    S: BorrowMut<[T]>,
+    T: Clone,
+{
+    type Output = Result<core::option::IntoIter<&'a mut [T]>, L::IoAbort>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        let Option::Some(writer) = this.writer_.as_mut() else {
+            panic!("ChunkLoaderWriteFuture polled after completion");
+        };
+        if this.length_ == 0 {
+            return Poll::Ready(Result::Ok(Option::None.into_iter()));
+        }
+        if writer.staged_len_ >= writer.staging_.borrow().len() {
+            let load_len = &mut this.load_len_;
+            let r = writer.poll_flush_(load_len, this.cancel_.as_mut(), cx);
+            let Poll::Ready(r) = r else {
+                return Poll::Pending;
+            };
+            if let Result::Err(a) = r {
+                return Poll::Ready(Result::Err(a));
+            }
+        }
+        let Option::Some(writer) = this.writer_.take() else {
+            unreachable!()
+        };
+        let staged_len = writer.staged_len_;
+        let staging: &'a mut [T] = writer.staging_.borrow_mut();
+        let opr_len = cmp::min(this.length_, staging.len() - staged_len);
+        writer.staged_len_ += opr_len;
+        let dst = &mut staging[staged_len..staged_len + opr_len];
+        Poll::Ready(Result::Ok(Option::Some(dst).into_iter()))
+    }
+}
+
+pub struct ChunkLoaderFlushAsync<'a, BL, L, S, T>
+where
+    BL: BorrowMut<L>,
+    L: TrChunkLoader<T>,
+    S: BorrowMut<[T]>,
+    T: Clone,
+{
+    writer_: &'a mut ChunkLoaderAsBuffWrite<BL, L, S, T>,
+}
+
+impl<'a, BL, L, S, T> ChunkLoaderFlushAsync<'a, BL, L, S, T>
+where
+    BL: BorrowMut<L>,
+    L: TrChunkLoader<T>,
+    S: BorrowMut<[T]>,
+    T: Clone,
+{
+    pub fn new(writer: &'a mut ChunkLoaderAsBuffWrite<BL, L, S, T>) -> Self {
+        ChunkLoaderFlushAsync { writer_: writer }
+    }
+}
+
+impl<'a, BL, L, S, T> ChunkLoaderFlushAsync<'a, BL, L, S, T>
+where
+    BL: BorrowMut<L>,
+    L: TrChunkLoader<T>,
+    S: BorrowMut<[T]>,
+    T: Clone,
+{
+    pub fn may_cancel_with<C>(
+        self,
+        cancel: Pin<&'a mut C>,
+    ) -> ChunkLoaderFlushFuture<'a, C, BL, L, S, T>
+    where
+        C: TrCancellationToken,
+    {
+        ChunkLoaderFlushFuture::new(self, cancel)
+    }
+}
+
+impl<'a, BL, L, S, T> IntoFuture for ChunkLoaderFlushAsync<'a, BL, L, S, T>
+where
+    BL: BorrowMut<L>,
+    L: TrChunkLoader<T>,
+    S: BorrowMut<[T]>,
+    T: Clone,
+{
+    type IntoFuture =
+        ChunkLoaderFlushFuture<'a, NonCancellableToken, BL, L, S, T>;
+    type Output = <Self::IntoFuture as Future>::Output;
+
+    fn into_future(self) -> Self::IntoFuture {
+        let cancel = NonCancellableToken::pinned();
+        ChunkLoaderFlushFuture::new(self, cancel)
+    }
+}
+
+impl<'a, BL, L, S, T> TrIntoFutureMayCancel<'a>
+for ChunkLoaderFlushAsync<'a, BL, L, S, T>
+where
+    BL: BorrowMut<L>,
+    L: TrChunkLoader<T>,
+    S: BorrowMut<[T]>,
+    T: Clone,
+{
+    type MayCancelOutput = <Self as IntoFuture>::Output;
+
+    #[inline(always)]
+    fn may_cancel_with<C>(
+        self,
+        cancel: Pin<&'a mut C>,
+    ) -> impl Future<Output = Self::MayCancelOutput>
+    where
+        C: TrCancellationToken,
+    {
+        ChunkLoaderFlushAsync::may_cancel_with(self, cancel)
+    }
+}
+
+/// The future that loads the staged units of a `ChunkLoaderAsBuffWrite`.
+///
+/// The number of units loaded so far is kept in the future, and the units
+/// loaded are removed from the staging buffer as they go, so that a flush
+/// that spans several wakeups resumes from where it stops.
+pub struct ChunkLoaderFlushFuture<'a, C, BL, L, S, T>
+where
+    C: TrCancellationToken,
+    BL: BorrowMut<L>,
+    L: TrChunkLoader<T>,
+    S: BorrowMut<[T]>,
+    T: Clone,
+{
+    writer_: &'a mut ChunkLoaderAsBuffWrite<BL, L, S, T>,
+    load_len_: usize,
+    cancel_: Pin<&'a mut C>,
+}
+
+impl<'a, C, BL, L, S, T> ChunkLoaderFlushFuture<'a, C, BL, L, S, T>
+where
+    C: TrCancellationToken,
+    BL: BorrowMut<L>,
+    L: TrChunkLoader<T>,
+    S: BorrowMut<[T]>,
+    T: Clone,
+{
+    pub fn new(
+        flush: ChunkLoaderFlushAsync<'a, BL, L, S, T>,
+        cancel: Pin<&'a mut C>,
+    ) -> Self {
+        ChunkLoaderFlushFuture {
+            writer_: flush.writer_,
+            load_len_: 0usize,
+            cancel_: cancel,
+        }
+    }
+}
+
+impl<C, BL, L, S, T> Future for ChunkLoaderFlushFuture<'_, C, BL, L, S, T>
+where
+    C: TrCancellationToken,
+    BL: BorrowMut<L>,
+    L: TrChunkLoader<T>,
+    S: BorrowMut<[T]>,
+    T: Clone,
+{
+    type Output = Result<usize, L::IoAbort>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        let load_len = &mut this.load_len_;
+        this.writer_.poll_flush_(load_len, this.cancel_.as_mut(), cx)
+    }
+}
@@ -1,20 +1,59 @@
 ﻿use core::{
     borrow::BorrowMut,
     cmp,
+    error::Error,
+    fmt,
     future::{Future, IntoFuture},
     marker::PhantomData,
     pin::Pin,
     task::{Context, Poll},
 };
 
-use pin_project::pin_project;
 use pin_utils::pin_mut;
 
 use abs_buff::{x_deps::abs_sync, TrBuffIterPeek};
 use abs_sync::{cancellation::*, x_deps::pin_utils};
 
-use crate::{ChunkIoAbort, TrChunkFiller};
+use crate::{ChunkIoAbort, ChunkIoAbortKind, TrBuffClosed, TrChunkFiller};
 
+#[derive(Debug)]
+pub enum PeekError<E> {
+    /// The buffer holds no unit beyond those already copied, and the fill
+    /// needs more.
+    Exhausted,
+    /// The peek of the buffer fails.
+    Buff(E),
+}
+
+impl<E> fmt::Display for PeekError<E>
+where
+    E: Error,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PeekError::Exhausted => write!(f, "no more unit to peek"),
+            PeekError::Buff(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl<E> Error for PeekError<E>
+where
+    E: Error,
+{}
+
+/// A chunk filler that copies the units from the buffer without consuming
+/// them.
+///
+/// Every fill copies from the beginning of the buffer contents. When a fill
+/// has to peek more than once, the units it has already copied are skipped,
+/// so that the target holds a contiguous snapshot of the buffer contents.
+///
+/// A peek does not wait for the buffer to grow, so when the buffer holds
+/// nothing beyond the units already copied before the minimum is copied, the
+/// fill is aborted with `PeekError::Exhausted` and the units copied so far.
+/// Like an error from the buffer, it is classified as the end of stream once
+/// the buffer is closed with `new_with_closed_state`.
 pub struct BuffPeekAsChunkFiller<B, P, T>
 where
     B: BorrowMut<P>,
@@ -24,6 +63,21 @@ where
     _use_p_: PhantomData<P>,
     _use_t_: PhantomData<[T]>,
     buffer_: B,
+    classify_: fn(&P, &PeekError<P::Err>) -> ChunkIoAbortKind,
+}
+
+impl<B, P, T> BuffPeekAsChunkFiller<B, P, T>
+where
+    B: BorrowMut<P>,
+    P: TrBuffIterPeek<T> + TrBuffClosed,
+    T: Clone,
+{
+    /// An error from the buffer is classified as the end of stream once the
+    /// buffer is closed, and as a fault otherwise.
+    pub const fn new_with_closed_state(buffer: B) -> Self {
+        let classify = ChunkIoAbortKind::from_closed_state;
+        BuffPeekAsChunkFiller::new_with_classify(buffer, classify)
+    }
 }
 
 impl<B, P, T> BuffPeekAsChunkFiller<B, P, T>
@@ -32,11 +86,28 @@ where
     P: TrBuffIterPeek<T>,
     T: Clone,
 {
+    /// Every error from the buffer is classified as a fault, see
+    /// `new_with_closed_state` and `new_with_classify` to tell the end of
+    /// stream.
     pub const fn new(buffer: B) -> Self {
+        let classify = ChunkIoAbortKind::always_fault;
+        BuffPeekAsChunkFiller::new_with_classify(buffer, classify)
+    }
+
+    /// Create with how an error from the buffer is classified in the abort,
+    /// for example for a buffer that does not tell whether it is closed.
+    ///
+    /// An error while the cancellation token is signalled is always
+    /// classified as `ChunkIoAbortKind::Cancelled`.
+    pub const fn new_with_classify(
+        buffer: B,
+        classify: fn(&P, &PeekError<P::Err>) -> ChunkIoAbortKind,
+    ) -> Self {
         BuffPeekAsChunkFiller {
             _use_p_: PhantomData,
             _use_t_: PhantomData,
             buffer_: buffer,
+            classify_: classify,
         }
     }
 
@@ -46,6 +117,83 @@ where
     ) -> BuffPeekChunkFillAsync<'a, B, P, T> {
         BuffPeekChunkFillAsync::new(self, target)
     }
+
+    pub fn fill_at_least_async<'a>(
+        &'a mut self,
+        target: &'a mut [T],
+        min_len: usize,
+    ) -> BuffPeekChunkFillAsync<'a, B, P, T> {
+        BuffPeekChunkFillAsync::new_at_least(self, target, min_len)
+    }
+
+    /// Polls the fill of `target`, of which `perform_len` units are already
+    /// copied, until at least `min_len` units are copied.
+    fn poll_fill_<C>(
+        &mut self,
+        target: &mut [T],
+        min_len: usize,
+        perform_len: &mut usize,
+        mut cancel: Pin<&mut C>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<usize, ChunkIoAbort<PeekError<P::Err>>>>
+    where
+        C: TrCancellationToken,
+    {
+        let classify = self.classify_;
+        let buffer = self.buffer_.borrow_mut();
+        let target_len = target.len();
+        let min_len = cmp::min(min_len, target_len);
+        let last_error = loop {
+            if *perform_len >= min_len {
+                return Poll::Ready(Result::Ok(*perform_len));
+            }
+            let r = buffer.peek_async().may_cancel_with(cancel.as_mut());
+            pin_mut!(r);
+            let src_iter = match r.poll(cx) {
+                Poll::Pending => return Poll::Pending,
+                Poll::Ready(Result::Ok(src_iter)) => src_iter,
+                Poll::Ready(Result::Err(e)) => break PeekError::Buff(e),
+            };
+            let peek_len = *perform_len;
+            let mut skip_len = peek_len;
+            for src in src_iter.into_iter() {
+                let src_len = src.len();
+                if skip_len >= src_len {
+                    skip_len -= src_len;
+                    continue;
+                }
+                let src = &src[skip_len..];
+                skip_len = 0;
+                let opr_len = cmp::min(src.len(), target_len - *perform_len);
+                if opr_len == 0 {
+                    break;
+                } else {
+                    #[cfg(test)]
+                    log::trace!(
+                        "[BuffPeekAsChunkFiller::poll_fill_] \
+                        src_len({src_len}) opr_len({opr_len})"
+                    );
+                }
+                let dst = &mut target[*perform_len..*perform_len + opr_len];
+                dst.clone_from_slice(&src[..opr_len]);
+                *perform_len += opr_len;
+            }
+            if *perform_len == peek_len {
+                // Nothing beyond the units already copied.
+                break PeekError::Exhausted;
+            }
+        };
+        // The units copied so far are kept in the target, so that a cancelled
+        // IO can be resumed from `perform_len`.
+        let perform_len = *perform_len;
+        let abort = if cancel.is_cancelled() {
+            ChunkIoAbort::cancelled(perform_len, last_error)
+        } else {
+            let kind = classify(buffer, &last_error);
+            ChunkIoAbort::with_kind(perform_len, last_error, kind)
+        };
+        Poll::Ready(Result::Err(abort))
+    }
 }
 
 impl<'a, P, T> From<&'a mut P> for BuffPeekAsChunkFiller<&'a mut P, P, T>
@@ -64,7 +212,7 @@ where
     P: TrBuffIterPeek<T>,
     T: Clone,
 {
-    type IoAbort = ChunkIoAbort<<P as TrBuffIterPeek<T>>::Err>;
+    type IoAbort = ChunkIoAbort<PeekError<P::Err>>;
     type FillAsync<'a> = BuffPeekChunkFillAsync<'a, B, P, T> where Self: 'a;
 
     #[inline(always)]
@@ -74,6 +222,15 @@ where
     ) -> Self::FillAsync<'a> {
         BuffPeekAsChunkFiller::fill_async(self, target)
     }
+
+    #[inline(always)]
+    fn fill_at_least_async<'a>(
+        &'a mut self,
+        target: &'a mut [T],
+        min_len: usize,
+    ) -> Self::FillAsync<'a> {
+        BuffPeekAsChunkFiller::fill_at_least_async(self, target, min_len)
+    }
 }
 
 pub struct BuffPeekChunkFillAsync<'a, B, P, T>
@@ -84,6 +241,7 @@ where
 {
     filler_: &'a mut BuffPeekAsChunkFiller<B, P, T>,
     target_: &'a mut [T],
+    min_len_: usize,
 }
 
 impl<'a, B, P, T> BuffPeekChunkFillAsync<'a, B, P, T>
@@ -95,21 +253,31 @@ where
     pub fn new(
         filler: &'a mut BuffPeekAsChunkFiller<B, P, T>,
         target: &'a mut [T],
+    ) -> Self {
+        let min_len = target.len();
+        BuffPeekChunkFillAsync::new_at_least(filler, target, min_len)
+    }
+
+    pub fn new_at_least(
+        filler: &'a mut BuffPeekAsChunkFiller<B, P, T>,
+        target: &'a mut [T],
+        min_len: usize,
     ) -> Self {
         BuffPeekChunkFillAsync {
             filler_: filler,
             target_: target,
+            min_len_: min_len,
         }
     }
 
-    fn may_cancel_with<C>(
+    pub fn may_cancel_with<C>(
         self,
         cancel: Pin<&'a mut C>,
     ) -> BuffPeekChunkFillFuture<'a, C, B, P, T>
     where
         C: TrCancellationToken,
     {
-        BuffPeekChunkFillFuture::new(self.filler_, self.target_, cancel)
+        BuffPeekChunkFillFuture::new(self, cancel)
     }
 }
 
@@ -124,7 +292,7 @@ where
 
     fn into_future(self) -> Self::IntoFuture {
         let cancel = NonCancellableToken::pinned();
-        BuffPeekChunkFillFuture::new(self.filler_, self.target_, cancel)
+        BuffPeekChunkFillFuture::new(self, cancel)
     }
 }
 
@@ -148,7 +316,13 @@ where
     }
 }
 
-#[pin_project]
+/// The future that fills the target with the units peeked from the buffer.
+///
+/// The number of units copied into the target, which is also the offset of
+/// the next peek to copy from, is kept in the future, so a fill that spans
+/// several wakeups resumes from where it stops. The pending `peek_async` is
+/// issued again on the next poll, as it borrows the buffer that the future
+/// borrows as well.
 pub struct BuffPeekChunkFillFuture<'a, C, B, P, T>
 where
     C: TrCancellationToken,
@@ -156,88 +330,112 @@ where
     P: TrBuffIterPeek<T>,
     T: Clone,
 {
-    #[pin]filler_: &'a mut BuffPeekAsChunkFiller<B, P, T>,
-    #[pin]target_: &'a mut [T],
+    filler_: &'a mut BuffPeekAsChunkFiller<B, P, T>,
+    target_: &'a mut [T],
+    min_len_: usize,
+    perform_len_: usize,
     cancel_: Pin<&'a mut C>,
 }
 
-impl<C, B, P, T> Future for BuffPeekChunkFillFuture<'_, C, B, P, T>
+impl<'a, C, B, P, T> BuffPeekChunkFillFuture<'a, C, B, P, T>
 where
     C: TrCancellationToken,
     B: BorrowMut<P>,
     P: TrBuffIterPeek<T>,
     T: Clone,
 {
-    type Output = Result<
-        usize,
-        ChunkIoAbort<<P as TrBuffIterPeek<T>>::Err>,
-    >;
+    pub fn new(
+        fill: BuffPeekChunkFillAsync<'a, B, P, T>,
+        cancel: Pin<&'a mut C>,
+    ) -> Self {
+        BuffPeekChunkFillFuture {
+            filler_: fill.filler_,
+            target_: fill.target_,
+            min_len_: fill.min_len_,
+            perform_len_: 0usize,
+            cancel_: cancel,
+        }
+    }
 
-    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
-        let f = self.fill_async_();
-        pin_mut!(f);
-        f.poll(cx)
+    /// Number of units that has been copied into the target so far.
+    pub fn perform_len(&self) -> usize {
+        self.perform_len_
     }
 }
 
-impl<'a, C, B, P, T> BuffPeekChunkFillFuture<'a, C, B, P, T>
+impl<C, B, P, T> Future for BuffPeekChunkFillFuture<'_, C, B, P, T>
 where
     C: TrCancellationToken,
     B: BorrowMut<P>,
     P: TrBuffIterPeek<T>,
     T: Clone,
 {
-    pub fn new(
-        filler: &'a mut BuffPeekAsChunkFiller<B, P, T>,
-        target: &'a mut [T],
-        cancel: Pin<&'a mut C>,
-    ) -> Self {
-        BuffPeekChunkFillFuture {
-            filler_: filler,
-            target_: target,
-            cancel_: cancel,
-        }
+    type Output = Result<usize, ChunkIoAbort<PeekError<P::Err>>>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        this.filler_.poll_fill_(
+            this.target_,
+            this.min_len_,
+            &mut this.perform_len_,
+            this.cancel_.as_mut(),
+            cx,
+        )
     }
+}
 
-    async fn fill_async_(
-        self: Pin<&mut Self>,
-    ) -> Result<usize, ChunkIoAbort<<P as TrBuffIterPeek<T>>::Err>> {
-        let mut this = self.project();
-        let mut filler = this.filler_.as_mut();
-        let buffer = filler.buffer_.borrow_mut();
-        let mut target = this.target_.as_mut();
-        let target_len = target.len();
-        let mut perform_len = 0usize;
-        loop {
-            if perform_len >= target_len {
-                break Result::Ok(perform_len);
-            }
-            let r = buffer
-                .peek_async()
-                .may_cancel_with(this.cancel_.as_mut())
-                .await;
-            let Result::Ok(src_iter) = r else {
-                let Result::Err(last_error) = r else {
-                    unreachable!("[BuffPeekChunkFillFuture::fill_async_]")
-                };
-                break Result::Err(ChunkIoAbort::new(perform_len, last_error));
-            };
-            for src in src_iter.into_iter() {
-                let src_len = src.len();
-                let opr_len = cmp::min(src_len, target_len - perform_len);
-                if opr_len == 0 {
-                    break;
-                } else {
-                    #[cfg(test)]
-                    log::trace!(
-                        "[BuffPeekChunkFillFuture::fill_async_] \
-                        src_len({src_len}) opr_len({opr_len})"
-                    );
-                }
-                let dst = &mut target[perform_len..perform_len + opr_len];
-                dst.clone_from_slice(&src[..opr_len]);
-                perform_len += opr_len;
-            }
-        }
+#[cfg(test)]
+mod tests {
+    use std::vec::Vec;
+
+    use super::*;
+    use crate::test_utils_::*;
+
+    #[test]
+    fn fill_resumes_across_pending_peeks() {
+        let source: Vec<u8> = (0..20).collect();
+        let mut buff = MockBuff::new(&source, 3, 2);
+        let mut filler = BuffPeekAsChunkFiller::from(&mut buff);
+        let mut target = [0u8; 10];
+        let r = block_on(
+            filler.fill_async(&mut target).may_cancel_with(no_cancel()),
+        );
+        assert_eq!(r.unwrap(), 10);
+        assert_eq!(&target[..], &source[..10]);
+
+        // The peeked units are not consumed.
+        let mut target = [0u8; 16];
+        let r = block_on(
+            filler.fill_async(&mut target).may_cancel_with(no_cancel()),
+        );
+        assert_eq!(r.unwrap(), 16);
+        assert_eq!(&target[..], &source[..16]);
+        assert!(buff.pending_count() >= 2 * 4);
+    }
+
+    #[test]
+    fn fill_aborts_when_peek_shows_nothing_new() {
+        let source: Vec<u8> = (0..5).collect();
+        let mut buff = MockBuff::new(&source, 3, 1);
+        let mut target = [0u8; 8];
+        let mut filler = BuffPeekAsChunkFiller::from(&mut buff);
+        let a = block_on(
+            filler.fill_async(&mut target).may_cancel_with(no_cancel()),
+        )
+        .unwrap_err();
+        assert!(matches!(a.last_error(), PeekError::Exhausted));
+        assert_eq!(a.kind(), ChunkIoAbortKind::Fault);
+        assert_eq!(a.perform_len(), 5);
+        assert_eq!(&target[..5], &source[..]);
+
+        buff.close();
+        let mut filler: BuffPeekAsChunkFiller<_, MockBuff, _> =
+            BuffPeekAsChunkFiller::new_with_closed_state(&mut buff);
+        let a = block_on(
+            filler.fill_async(&mut target).may_cancel_with(no_cancel()),
+        )
+        .unwrap_err();
+        assert!(a.is_end_of_stream());
+        assert_eq!(a.perform_len(), 5);
     }
 }
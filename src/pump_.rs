@@ -0,0 +1,466 @@
+use core::{
+    borrow::BorrowMut,
+    cmp,
+    error::Error,
+    fmt,
+    future::{Future, IntoFuture},
+    marker::PhantomData,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use abs_buff::x_deps::abs_sync;
+use abs_sync::cancellation::*;
+
+use crate::{
+    chunk_async_::{poll_fill_some, poll_load_some},
+    ChunkIoAbortKind, TrChunkFiller, TrChunkIoAbort, TrChunkLoader,
+};
+
+/// The side of a chunk pump that causes the abortion.
+#[derive(Debug)]
+pub enum ChunkPumpError<FA, LA> {
+    /// The filler is aborted.
+    Fill(FA),
+    /// The loader is aborted.
+    Load(LA),
+    /// The loading side accepts no unit at all while there are units to
+    /// load, so that the copy cannot make any progress.
+    LoadStalled,
+    /// The filling side hands out no unit at all while more units are
+    /// wanted, which is classified as the end of stream.
+    FillExhausted,
+}
+
+impl<FA, LA> fmt::Display for ChunkPumpError<FA, LA>
+where
+    FA: Error,
+    LA: Error,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChunkPumpError::Fill(a) => write!(f, "filling side: {a}"),
+            ChunkPumpError::Load(a) => write!(f, "loading side: {a}"),
+            ChunkPumpError::LoadStalled => write!(f, "loading side stalled"),
+            ChunkPumpError::FillExhausted => {
+                write!(f, "filling side exhausted")
+            },
+        }
+    }
+}
+
+impl<FA, LA> Error for ChunkPumpError<FA, LA>
+where
+    FA: Error,
+    LA: Error,
+{}
+
+/// To report the detail of an aborted chunk pump.
+///
+/// The units filled from the filler but not yet loaded into the loader are
+/// the difference between `fill_len` and `load_len`. The `perform_len` is
+/// the number of units loaded.
+#[derive(Debug)]
+pub struct ChunkPumpAbort<FA, LA> {
+    fill_len_: usize,
+    load_len_: usize,
+    error_: ChunkPumpError<FA, LA>,
+    kind_: ChunkIoAbortKind,
+}
+
+impl<FA, LA> ChunkPumpAbort<FA, LA> {
+    pub const fn new(
+        fill_len: usize,
+        load_len: usize,
+        error: ChunkPumpError<FA, LA>,
+    ) -> Self {
+        let kind = ChunkIoAbortKind::Fault;
+        ChunkPumpAbort::with_kind(fill_len, load_len, error, kind)
+    }
+
+    pub const fn with_kind(
+        fill_len: usize,
+        load_len: usize,
+        error: ChunkPumpError<FA, LA>,
+        kind: ChunkIoAbortKind,
+    ) -> Self {
+        ChunkPumpAbort {
+            fill_len_: fill_len,
+            load_len_: load_len,
+            error_: error,
+            kind_: kind,
+        }
+    }
+
+    /// Number of units that has been filled from the filler.
+    pub const fn fill_len(&self) -> usize {
+        self.fill_len_
+    }
+
+    /// Number of units that has been loaded into the loader.
+    pub const fn load_len(&self) -> usize {
+        self.load_len_
+    }
+
+    /// The abortion from either side.
+    pub const fn error(&self) -> &ChunkPumpError<FA, LA> {
+        &self.error_
+    }
+
+    /// The classification of the abort, which is `Cancelled` if the
+    /// cancellation token is signalled, whatever the side reports.
+    pub const fn kind(&self) -> ChunkIoAbortKind {
+        self.kind_
+    }
+
+    pub const fn is_cancelled(&self) -> bool {
+        matches!(self.kind_, ChunkIoAbortKind::Cancelled)
+    }
+}
+
+impl<FA, LA> TrChunkIoAbort for ChunkPumpAbort<FA, LA>
+where
+    FA: Error,
+    LA: Error,
+{
+    type LastErr = ChunkPumpError<FA, LA>;
+
+    #[inline]
+    fn perform_len(&self) -> usize {
+        self.load_len_
+    }
+
+    #[inline]
+    fn last_error(&self) -> &Self::LastErr {
+        &self.error_
+    }
+
+    #[inline]
+    fn add_perform_len(self, len: usize) -> Self {
+        ChunkPumpAbort {
+            fill_len_: self.fill_len_ + len,
+            load_len_: self.load_len_ + len,
+            ..self
+        }
+    }
+
+    #[inline]
+    fn kind(&self) -> ChunkIoAbortKind {
+        self.kind_
+    }
+}
+
+impl<FA, LA> fmt::Display for ChunkPumpAbort<FA, LA>
+where
+    FA: Error,
+    LA: Error,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let action = if self.is_cancelled() { "cancelled" } else { "aborted" };
+        write!(
+            f,
+            "chunk pump {action} after {} units filled and {} loaded: {}",
+            self.fill_len_, self.load_len_, self.error_,
+        )
+    }
+}
+
+impl<FA, LA> Error for ChunkPumpAbort<FA, LA>
+where
+    FA: Error,
+    LA: Error,
+{}
+
+/// Copies units from a chunk filler into a chunk loader, through an
+/// intermediate buffer.
+///
+/// The intermediate buffer `S` can be a slice provided by the caller, or an
+/// array owned by the pump (see `ChunkPumpAsync::new_array`). The pump
+/// completes with the number of units copied once the limit (if any) is
+/// reached, or once the filler reaches the end of stream or fills nothing;
+/// otherwise it keeps copying until either side aborts.
+pub struct ChunkPumpAsync<'a, F, L, S, T>
+where
+    F: TrChunkFiller<T>,
+    L: TrChunkLoader<T>,
+    S: BorrowMut<[T]>,
+    T: Clone,
+{
+    _use_t_: PhantomData<[T]>,
+    filler_: &'a mut F,
+    loader_: &'a mut L,
+    buffer_: S,
+    limit_: Option<usize>,
+}
+
+impl<'a, F, L, S, T> ChunkPumpAsync<'a, F, L, S, T>
+where
+    F: TrChunkFiller<T>,
+    L: TrChunkLoader<T>,
+    S: BorrowMut<[T]>,
+    T: Clone,
+{
+    pub fn new(filler: &'a mut F, loader: &'a mut L, buffer: S) -> Self {
+        ChunkPumpAsync {
+            _use_t_: PhantomData,
+            filler_: filler,
+            loader_: loader,
+            buffer_: buffer,
+            limit_: Option::None,
+        }
+    }
+
+    /// Stop the pump after `limit` units are copied.
+    pub fn with_limit(self, limit: usize) -> Self {
+        ChunkPumpAsync {
+            limit_: Option::Some(limit),
+            ..self
+        }
+    }
+}
+
+fn classify<A, C>(abort: &A, cancel: &C) -> ChunkIoAbortKind
+where
+    A: TrChunkIoAbort,
+    C: TrCancellationToken,
+{
+    if cancel.is_cancelled() {
+        ChunkIoAbortKind::Cancelled
+    } else {
+        abort.kind()
+    }
+}
+
+impl<'a, F, L, T, const N: usize> ChunkPumpAsync<'a, F, L, [T; N], T>
+where
+    F: TrChunkFiller<T>,
+    L: TrChunkLoader<T>,
+    T: Clone + Default,
+{
+    /// Create a pump with an intermediate buffer of `N` units on its own.
+    pub fn new_array(filler: &'a mut F, loader: &'a mut L) -> Self {
+        let buffer = core::array::from_fn(|_| T::default());
+        ChunkPumpAsync::new(filler, loader, buffer)
+    }
+}
+
+impl<'a, F, L, S, T> ChunkPumpAsync<'a, F, L, S, T>
+where
+    F: TrChunkFiller<T>,
+    L: TrChunkLoader<T>,
+    S: 'a + BorrowMut<[T]>,
+    T: 'a + Clone,
+{
+    pub fn may_cancel_with<C>(
+        self,
+        cancel: Pin<&'a mut C>,
+    ) -> ChunkPumpFuture<'a, C, F, L, S, T>
+    where
+        C: TrCancellationToken,
+    {
+        ChunkPumpFuture::new(self, cancel)
+    }
+}
+
+impl<'a, F, L, S, T> IntoFuture for ChunkPumpAsync<'a, F, L, S, T>
+where
+    F: TrChunkFiller<T>,
+    L: TrChunkLoader<T>,
+    S: 'a + BorrowMut<[T]>,
+    T: 'a + Clone,
+{
+    type IntoFuture = ChunkPumpFuture<'a, NonCancellableToken, F, L, S, T>;
+    type Output = <Self::IntoFuture as Future>::Output;
+
+    fn into_future(self) -> Self::IntoFuture {
+        let cancel = NonCancellableToken::pinned();
+        ChunkPumpFuture::new(self, cancel)
+    }
+}
+
+impl<'a, F, L, S, T> TrIntoFutureMayCancel<'a> for ChunkPumpAsync<'a, F, L, S, T>
+where
+    F: TrChunkFiller<T>,
+    L: TrChunkLoader<T>,
+    S: 'a + BorrowMut<[T]>,
+    T: 'a + Clone,
+{
+    type MayCancelOutput = <Self as IntoFuture>::Output;
+
+    #[inline(always)]
+    fn may_cancel_with<C>(
+        self,
+        cancel: Pin<&'a mut C>,
+    ) -> impl Future<Output = Self::MayCancelOutput>
+    where
+        C: TrCancellationToken,
+    {
+        ChunkPumpAsync::may_cancel_with(self, cancel)
+    }
+}
+
+/// The future that copies units from the filler into the loader.
+///
+/// The units filled into the intermediate buffer and not yet loaded are kept
+/// in the future, together with the numbers of units filled and loaded, so a
+/// pump that spans several wakeups resumes from where it stops. Each poll
+/// issues a single `fill_some_async` or `load_some_async` at a time, which
+/// has not copied any unit while it is pending, so it is issued again on the
+/// next poll.
+pub struct ChunkPumpFuture<'a, C, F, L, S, T>
+where
+    C: TrCancellationToken,
+    F: TrChunkFiller<T>,
+    L: TrChunkLoader<T>,
+    S: BorrowMut<[T]>,
+    T: Clone,
+{
+    _use_t_: PhantomData<[T]>,
+    filler_: &'a mut F,
+    loader_: &'a mut L,
+    buffer_: S,
+    limit_: Option<usize>,
+    fill_len_: usize,
+    load_len_: usize,
+    /// Range of the intermediate buffer filled but not yet loaded.
+    stage_: (usize, usize),
+    /// The abort of the filler, reported once the units filled before it are
+    /// loaded.
+    fill_abort_: Option<F::IoAbort>,
+    cancel_: Pin<&'a mut C>,
+}
+
+// None of the fields is pinned.
+impl<C, F, L, S, T> Unpin for ChunkPumpFuture<'_, C, F, L, S, T>
+where
+    C: TrCancellationToken,
+    F: TrChunkFiller<T>,
+    L: TrChunkLoader<T>,
+    S: BorrowMut<[T]>,
+    T: Clone,
+{}
+
+impl<'a, C, F, L, S, T> ChunkPumpFuture<'a, C, F, L, S, T>
+where
+    C: TrCancellationToken,
+    F: TrChunkFiller<T>,
+    L: TrChunkLoader<T>,
+    S: BorrowMut<[T]>,
+    T: Clone,
+{
+    pub fn new(
+        pump: ChunkPumpAsync<'a, F, L, S, T>,
+        cancel: Pin<&'a mut C>,
+    ) -> Self {
+        ChunkPumpFuture {
+            _use_t_: PhantomData,
+            filler_: pump.filler_,
+            loader_: pump.loader_,
+            buffer_: pump.buffer_,
+            limit_: pump.limit_,
+            fill_len_: 0usize,
+            load_len_: 0usize,
+            stage_: (0usize, 0usize),
+            fill_abort_: Option::None,
+            cancel_: cancel,
+        }
+    }
+
+    /// Number of units that has been loaded into the loader so far.
+    pub fn load_len(&self) -> usize {
+        self.load_len_
+    }
+}
+
+impl<C, F, L, S, T> Future for ChunkPumpFuture<'_, C, F, L, S, T>
+where
+    C: TrCancellationToken,
+    F: TrChunkFiller<T>,
+    L: TrChunkLoader<T>,
+    S: BorrowMut<[T]>,
+    T: Clone,
+{
+    type Output = Result<usize, ChunkPumpAbort<F::IoAbort, L::IoAbort>>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        let buffer = this.buffer_.borrow_mut();
+        loop {
+            let (load_pos, stage_len) = this.stage_;
+            if load_pos < stage_len {
+                let r = poll_load_some(
+                    this.loader_,
+                    &buffer[load_pos..stage_len],
+                    this.cancel_.as_mut(),
+                    cx,
+                );
+                let Poll::Ready(r) = r else {
+                    return Poll::Pending;
+                };
+                match r {
+                    Result::Ok(m) if m > 0 => {
+                        this.load_len_ += m;
+                        this.stage_.0 += m;
+                        continue;
+                    },
+                    Result::Ok(_) => {
+                        let e = ChunkPumpError::LoadStalled;
+                        return Poll::Ready(Result::Err(ChunkPumpAbort::new(
+                            this.fill_len_, this.load_len_, e)));
+                    },
+                    Result::Err(a) => {
+                        this.load_len_ += a.perform_len();
+                        let kind = classify(&a, &*this.cancel_);
+                        let e = ChunkPumpError::Load(a);
+                        let a = ChunkPumpAbort::with_kind(
+                            this.fill_len_, this.load_len_, e, kind);
+                        return Poll::Ready(Result::Err(a));
+                    },
+                }
+            }
+            match this.fill_abort_.take() {
+                Option::None => (),
+                Option::Some(a) if a.is_end_of_stream() => {
+                    return Poll::Ready(Result::Ok(this.load_len_));
+                },
+                Option::Some(a) => {
+                    let kind = classify(&a, &*this.cancel_);
+                    let e = ChunkPumpError::Fill(a);
+                    return Poll::Ready(Result::Err(ChunkPumpAbort::with_kind(
+                        this.fill_len_, this.load_len_, e, kind)));
+                },
+            }
+            let opr_len = match this.limit_ {
+                Option::Some(limit) => {
+                    cmp::min(buffer.len(), limit - this.fill_len_)
+                },
+                Option::None => buffer.len(),
+            };
+            if opr_len == 0 {
+                return Poll::Ready(Result::Ok(this.load_len_));
+            }
+            let r = poll_fill_some(
+                this.filler_,
+                &mut buffer[..opr_len],
+                this.cancel_.as_mut(),
+                cx,
+            );
+            let Poll::Ready(r) = r else {
+                return Poll::Pending;
+            };
+            let n = match r {
+                Result::Ok(n) => n,
+                Result::Err(a) => {
+                    let n = a.perform_len();
+                    this.fill_abort_ = Option::Some(a);
+                    n
+                },
+            };
+            if n == 0 && this.fill_abort_.is_none() {
+                return Poll::Ready(Result::Ok(this.load_len_));
+            }
+            this.fill_len_ += n;
+            this.stage_ = (0usize, n);
+        }
+    }
+}
@@ -7,13 +7,12 @@
     task::{Context, Poll},
 };
 
-use pin_project::pin_project;
 use pin_utils::pin_mut;
 
 use abs_buff::{x_deps::abs_sync, TrBuffIterRead};
 use abs_sync::{cancellation::*, x_deps::pin_utils};
 
-use crate::{ChunkIoAbort, TrChunkFiller};
+use crate::{ChunkIoAbort, ChunkIoAbortKind, TrBuffClosed, TrChunkFiller};
 
 pub struct BuffReadAsChunkFiller<B, R, T>
 where
@@ -24,6 +23,21 @@ where
     _use_r_: PhantomData<R>,
     _use_t_: PhantomData<[T]>,
     buffer_: B,
+    classify_: fn(&R, &<R as TrBuffIterRead<T>>::Err) -> ChunkIoAbortKind,
+}
+
+impl<B, R, T> BuffReadAsChunkFiller<B, R, T>
+where
+    B: BorrowMut<R>,
+    R: TrBuffIterRead<T> + TrBuffClosed,
+    T: Clone,
+{
+    /// An error from the buffer is classified as the end of stream once the
+    /// buffer is closed, and as a fault otherwise.
+    pub const fn new_with_closed_state(read: B) -> Self {
+        let classify = ChunkIoAbortKind::from_closed_state;
+        BuffReadAsChunkFiller::new_with_classify(read, classify)
+    }
 }
 
 impl<B, R, T> BuffReadAsChunkFiller<B, R, T>
@@ -32,11 +46,28 @@ where
     R: TrBuffIterRead<T>,
     T: Clone,
 {
+    /// Every error from the buffer is classified as a fault, see
+    /// `new_with_closed_state` and `new_with_classify` to tell the end of
+    /// stream.
     pub const fn new(read: B) -> Self {
+        let classify = ChunkIoAbortKind::always_fault;
+        BuffReadAsChunkFiller::new_with_classify(read, classify)
+    }
+
+    /// Create with how an error from the buffer is classified in the abort,
+    /// for example for a buffer that does not tell whether it is closed.
+    ///
+    /// An error while the cancellation token is signalled is always
+    /// classified as `ChunkIoAbortKind::Cancelled`.
+    pub const fn new_with_classify(
+        read: B,
+        classify: fn(&R, &<R as TrBuffIterRead<T>>::Err) -> ChunkIoAbortKind,
+    ) -> Self {
         BuffReadAsChunkFiller {
             _use_r_: PhantomData,
             _use_t_: PhantomData,
             buffer_: read,
+            classify_: classify,
         }
     }
 
@@ -46,6 +77,79 @@ where
     ) -> BuffReadChunkFillAsync<'a, B, R, T> {
         BuffReadChunkFillAsync::new(self, target)
     }
+
+    pub fn fill_at_least_async<'a>(
+        &'a mut self,
+        target: &'a mut [T],
+        min_len: usize,
+    ) -> BuffReadChunkFillAsync<'a, B, R, T> {
+        BuffReadChunkFillAsync::new_at_least(self, target, min_len)
+    }
+
+    /// Polls the fill of `target`, of which `perform_len` units are already
+    /// copied, until at least `min_len` units are copied.
+    fn poll_fill_<C>(
+        &mut self,
+        target: &mut [T],
+        min_len: usize,
+        perform_len: &mut usize,
+        mut cancel: Pin<&mut C>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<usize, ChunkIoAbort<<R as TrBuffIterRead<T>>::Err>>>
+    where
+        C: TrCancellationToken,
+    {
+        let classify = self.classify_;
+        let buffer = self.buffer_.borrow_mut();
+        let target_len = target.len();
+        let min_len = cmp::min(min_len, target_len);
+        let last_error = loop {
+            if *perform_len >= min_len {
+                return Poll::Ready(Result::Ok(*perform_len));
+            }
+            #[cfg(test)]
+            log::trace!(
+                "[BuffReadAsChunkFiller::poll_fill_] \
+                target_len({target_len}), perform_len({perform_len})"
+            );
+            let r = buffer
+                .read_async(target_len - *perform_len)
+                .may_cancel_with(cancel.as_mut());
+            pin_mut!(r);
+            let src_iter = match r.poll(cx) {
+                Poll::Pending => return Poll::Pending,
+                Poll::Ready(Result::Ok(src_iter)) => src_iter,
+                Poll::Ready(Result::Err(last_error)) => break last_error,
+            };
+            for src in src_iter.into_iter() {
+                let src_len = src.len();
+                let opr_len = cmp::min(src_len, target_len - *perform_len);
+                if opr_len == 0 {
+                    break;
+                } else {
+                    #[cfg(test)]
+                    log::trace!(
+                        "[BuffReadAsChunkFiller::poll_fill_] \
+                        read_async src_len({src_len}), opr_len({opr_len})"
+                    );
+                }
+                debug_assert!(opr_len + *perform_len <= target_len);
+                let dst = &mut target[*perform_len..*perform_len + opr_len];
+                dst.clone_from_slice(&src[..opr_len]);
+                *perform_len += opr_len;
+            }
+        };
+        // The units copied so far are kept in the target, so that a cancelled
+        // IO can be resumed from `perform_len`.
+        let perform_len = *perform_len;
+        let abort = if cancel.is_cancelled() {
+            ChunkIoAbort::cancelled(perform_len, last_error)
+        } else {
+            let kind = classify(buffer, &last_error);
+            ChunkIoAbort::with_kind(perform_len, last_error, kind)
+        };
+        Poll::Ready(Result::Err(abort))
+    }
 }
 
 impl<'a, R, T> From<&'a mut R> for BuffReadAsChunkFiller<&'a mut R, R, T>
@@ -74,6 +178,15 @@ where
     ) -> Self::FillAsync<'a> {
         BuffReadAsChunkFiller::fill_async(self, target)
     }
+
+    #[inline(always)]
+    fn fill_at_least_async<'a>(
+        &'a mut self,
+        target: &'a mut [T],
+        min_len: usize,
+    ) -> Self::FillAsync<'a> {
+        BuffReadAsChunkFiller::fill_at_least_async(self, target, min_len)
+    }
 }
 
 pub struct BuffReadChunkFillAsync<'a, B, R, T>
@@ -84,6 +197,7 @@ where
 {
     filler_: &'a mut BuffReadAsChunkFiller<B, R, T>,
     target_: &'a mut [T],
+    min_len_: usize,
 }
 
 impl<'a, B, R, T> BuffReadChunkFillAsync<'a, B, R, T>
@@ -95,10 +209,20 @@ where
     pub fn new(
         filler: &'a mut BuffReadAsChunkFiller<B, R, T>,
         target: &'a mut [T],
+    ) -> Self {
+        let min_len = target.len();
+        BuffReadChunkFillAsync::new_at_least(filler, target, min_len)
+    }
+
+    pub fn new_at_least(
+        filler: &'a mut BuffReadAsChunkFiller<B, R, T>,
+        target: &'a mut [T],
+        min_len: usize,
     ) -> Self {
         BuffReadChunkFillAsync {
             filler_: filler,
             target_: target,
+            min_len_: min_len,
         }
     }
 
@@ -109,7 +233,7 @@ where
     where
         C: TrCancellationToken,
     {
-        BuffReadChunkFillFuture::new(self.filler_, self.target_, cancel)
+        BuffReadChunkFillFuture::new(self, cancel)
     }
 }
 
@@ -124,7 +248,7 @@ where
 
     fn into_future(self) -> Self::IntoFuture {
         let cancel = NonCancellableToken::pinned();
-        BuffReadChunkFillFuture::new(self.filler_, self.target_, cancel)
+        BuffReadChunkFillFuture::new(self, cancel)
     }
 }
 
@@ -148,7 +272,13 @@ where
     }
 }
 
-#[pin_project]
+/// The future that fills the target with the units read from the buffer.
+///
+/// The number of units copied into the target is kept in the future, so a
+/// fill that spans several wakeups resumes from where it stops. The pending
+/// `read_async` borrows the buffer that the future borrows as well, so it is
+/// not kept across polls, but issued again on the next poll. A pending read
+/// has not handed out any segment, so no unit is lost or duplicated.
 pub struct BuffReadChunkFillFuture<'a, C, B, R, T>
 where
     C: TrCancellationToken,
@@ -156,94 +286,90 @@ where
     R: TrBuffIterRead<T>,
     T: Clone,
 {
-    #[pin]filler_: &'a mut BuffReadAsChunkFiller<B, R, T>,
-    #[pin]target_: &'a mut [T],
+    filler_: &'a mut BuffReadAsChunkFiller<B, R, T>,
+    target_: &'a mut [T],
+    min_len_: usize,
+    perform_len_: usize,
     cancel_: Pin<&'a mut C>,
 }
 
-impl<C, B, R, T> Future for BuffReadChunkFillFuture<'_, C, B, R, T>
+impl<'a, C, B, R, T> BuffReadChunkFillFuture<'a, C, B, R, T>
 where
     C: TrCancellationToken,
     B: BorrowMut<R>,
     R: TrBuffIterRead<T>,
     T: Clone,
 {
-    type Output = Result<
-        usize,
-        ChunkIoAbort<<R as TrBuffIterRead<T>>::Err>,
-    >;
+    pub fn new(
+        fill: BuffReadChunkFillAsync<'a, B, R, T>,
+        cancel: Pin<&'a mut C>,
+    ) -> Self {
+        BuffReadChunkFillFuture {
+            filler_: fill.filler_,
+            target_: fill.target_,
+            min_len_: fill.min_len_,
+            perform_len_: 0usize,
+            cancel_: cancel,
+        }
+    }
 
-    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
-        let f = self.fill_async_();
-        pin_mut!(f);
-        f.poll(cx)
+    /// Number of units that has been copied into the target so far.
+    pub fn perform_len(&self) -> usize {
+        self.perform_len_
     }
 }
 
-impl<'a, C, B, R, T> BuffReadChunkFillFuture<'a, C, B, R, T>
+impl<C, B, R, T> Future for BuffReadChunkFillFuture<'_, C, B, R, T>
 where
     C: TrCancellationToken,
     B: BorrowMut<R>,
     R: TrBuffIterRead<T>,
     T: Clone,
 {
-    pub fn new(
-        filler: &'a mut BuffReadAsChunkFiller<B, R, T>,
-        target: &'a mut [T],
-        cancel: Pin<&'a mut C>,
-    ) -> Self {
-        BuffReadChunkFillFuture {
-            filler_: filler,
-            target_: target,
-            cancel_: cancel,
-        }
+    type Output = Result<usize, ChunkIoAbort<<R as TrBuffIterRead<T>>::Err>>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        this.filler_.poll_fill_(
+            this.target_,
+            this.min_len_,
+            &mut this.perform_len_,
+            this.cancel_.as_mut(),
+            cx,
+        )
     }
+}
 
-    async fn fill_async_(
-        self: Pin<&mut Self>,
-    ) -> Result<usize, ChunkIoAbort<<R as TrBuffIterRead<T>>::Err>> {
-        let mut this = self.project();
-        let mut filler = this.filler_.as_mut();
-        let buffer = filler.buffer_.borrow_mut();
-        let mut target = this.target_.as_mut();
-        let target_len = target.len();
-        let mut perform_len = 0usize;
-        loop {
-            if perform_len >= target_len {
-                break Result::Ok(perform_len);
-            }
-            #[cfg(test)]
-            log::trace!(
-                "[BuffReadChunkFillFuture::fill_async_] \
-                target_len({target_len}), perform_len({perform_len})"
-            );
-            let r = buffer
-                .read_async(target_len - perform_len)
-                .may_cancel_with(this.cancel_.as_mut())
-                .await;
-            let Result::Ok(src_iter) = r else {
-                let Result::Err(last_error) = r else {
-                    unreachable!("[ReaderFillFuture::fill_async_]")
-                };
-                break Result::Err(ChunkIoAbort::new(perform_len, last_error));
-            };
-            for src in src_iter.into_iter() {
-                let src_len = src.len();
-                let opr_len = cmp::min(src_len, target_len - perform_len);
-                if opr_len == 0 {
-                    break;
-                } else {
-                    #[cfg(test)]
-                    log::trace!(
-                        "[BuffReadChunkFillFuture::fill_async_] \
-                        read_async src_len({src_len}), opr_len({opr_len})"
-                    );
-                }
-                debug_assert!(opr_len + perform_len <= target_len);
-                let dst = &mut target[perform_len..perform_len + opr_len];
-                dst.clone_from_slice(&src);
-                perform_len += opr_len;
-            }
-        }
+#[cfg(test)]
+mod tests {
+    use std::vec::Vec;
+
+    use super::*;
+    use crate::test_utils_::*;
+
+    #[test]
+    fn fill_resumes_across_pending_reads() {
+        let source: Vec<u8> = (0..40).collect();
+        let mut buff = MockBuff::new(&source, 3, 2);
+        buff.close();
+        let mut filler: BuffReadAsChunkFiller<_, MockBuff, _> =
+            BuffReadAsChunkFiller::new_with_closed_state(&mut buff);
+        let mut target = [0u8; 25];
+        let r = block_on(
+            filler.fill_async(&mut target).may_cancel_with(no_cancel()),
+        );
+        assert_eq!(r.unwrap(), 25);
+        assert_eq!(&target[..], &source[..25]);
+
+        let mut target = [0u8; 25];
+        let a = block_on(
+            filler.fill_async(&mut target).may_cancel_with(no_cancel()),
+        )
+        .unwrap_err();
+        assert!(a.is_end_of_stream());
+        assert_eq!(a.perform_len(), 15);
+        assert_eq!(&target[..15], &source[25..]);
+        // Every read of 3 units pended twice.
+        assert!(buff.pending_count() >= 2 * 40 / 3);
     }
 }
@@ -0,0 +1,268 @@
+use core::{
+    borrow::BorrowMut,
+    cmp,
+    future::Future,
+    marker::PhantomData,
+    pin::Pin,
+};
+
+use abs_buff::{x_deps::abs_sync, TrBuffIterRead, TrBuffIterWrite};
+use abs_sync::cancellation::*;
+
+use crate::{ChunkIoAbortKind, ChunkPumpAbort, ChunkPumpError, TrBuffClosed};
+
+/// Relays units from a buffer reader into a buffer writer, by copying the
+/// segments read directly into the segments to write, without any staging
+/// buffer in between.
+///
+/// The abortion is reported as a `ChunkPumpAbort`, with the reader as the
+/// filling side and the writer as the loading side, and is classified as
+/// cancelled if the read is aborted while the cancellation token is
+/// signalled. A read that hands out nothing before `min_len` units are
+/// relayed aborts the relay with `ChunkPumpError::FillExhausted`, classified
+/// as the end of stream, while a write that hands out no room aborts it with
+/// `ChunkPumpError::LoadStalled`.
+///
+/// A relay is awaited through `may_cancel_with`, for example with
+/// `NonCancellableToken::pinned()`. The segments read have to be held while
+/// the writer waits for room, and they borrow the reader, so the relay has
+/// no named future to implement `IntoFuture` with.
+pub struct BuffIterRelay<BR, R, BW, W, T>
+where
+    BR: BorrowMut<R>,
+    R: TrBuffIterRead<T>,
+    BW: BorrowMut<W>,
+    W: TrBuffIterWrite<T>,
+    T: Clone,
+{
+    _use_r_: PhantomData<R>,
+    _use_w_: PhantomData<W>,
+    _use_t_: PhantomData<[T]>,
+    reader_: BR,
+    writer_: BW,
+    classify_: fn(&R, &R::Err) -> ChunkIoAbortKind,
+}
+
+impl<BR, R, BW, W, T> BuffIterRelay<BR, R, BW, W, T>
+where
+    BR: BorrowMut<R>,
+    R: TrBuffIterRead<T> + TrBuffClosed,
+    BW: BorrowMut<W>,
+    W: TrBuffIterWrite<T>,
+    T: Clone,
+{
+    /// An error from the read is classified as the end of stream once the
+    /// reader is closed, and as a fault otherwise.
+    pub const fn new_with_closed_state(reader: BR, writer: BW) -> Self {
+        let classify = ChunkIoAbortKind::from_closed_state;
+        BuffIterRelay::new_with_classify(reader, writer, classify)
+    }
+}
+
+impl<BR, R, BW, W, T> BuffIterRelay<BR, R, BW, W, T>
+where
+    BR: BorrowMut<R>,
+    R: TrBuffIterRead<T>,
+    BW: BorrowMut<W>,
+    W: TrBuffIterWrite<T>,
+    T: Clone,
+{
+    /// Every error from the read is classified as a fault, see
+    /// `new_with_closed_state` and `new_with_classify` to tell the end of
+    /// stream.
+    pub const fn new(reader: BR, writer: BW) -> Self {
+        let classify = ChunkIoAbortKind::always_fault;
+        BuffIterRelay::new_with_classify(reader, writer, classify)
+    }
+
+    /// Create with how an error from the read is classified, for example for
+    /// a reader that does not tell whether it is closed.
+    ///
+    /// An error while the cancellation token is signalled is always
+    /// classified as `ChunkIoAbortKind::Cancelled`.
+    pub const fn new_with_classify(
+        reader: BR,
+        writer: BW,
+        classify: fn(&R, &R::Err) -> ChunkIoAbortKind,
+    ) -> Self {
+        BuffIterRelay {
+            _use_r_: PhantomData,
+            _use_w_: PhantomData,
+            _use_t_: PhantomData,
+            reader_: reader,
+            writer_: writer,
+            classify_: classify,
+        }
+    }
+
+    /// Relay exactly `length` units unless the IO is aborted.
+    pub fn relay_async(
+        &mut self,
+        length: usize,
+    ) -> BuffRelayAsync<'_, BR, R, BW, W, T> {
+        BuffRelayAsync::new(self, length, length)
+    }
+
+    /// Relay at most `length` units, and complete as soon as at least
+    /// `min_len` units are relayed.
+    pub fn relay_at_least_async(
+        &mut self,
+        length: usize,
+        min_len: usize,
+    ) -> BuffRelayAsync<'_, BR, R, BW, W, T> {
+        BuffRelayAsync::new(self, length, min_len)
+    }
+
+    pub fn into_inner(self) -> (BR, BW) {
+        (self.reader_, self.writer_)
+    }
+}
+
+pub struct BuffRelayAsync<'a, BR, R, BW, W, T>
+where
+    BR: BorrowMut<R>,
+    R: TrBuffIterRead<T>,
+    BW: BorrowMut<W>,
+    W: TrBuffIterWrite<T>,
+    T: Clone,
+{
+    relay_: &'a mut BuffIterRelay<BR, R, BW, W, T>,
+    length_: usize,
+    min_len_: usize,
+}
+
+impl<'a, BR, R, BW, W, T> BuffRelayAsync<'a, BR, R, BW, W, T>
+where
+    BR: BorrowMut<R>,
+    R: TrBuffIterRead<T>,
+    BW: BorrowMut<W>,
+    W: TrBuffIterWrite<T>,
+    T: Clone,
+{
+    pub fn new(
+        relay: &'a mut BuffIterRelay<BR, R, BW, W, T>,
+        length: usize,
+        min_len: usize,
+    ) -> Self {
+        BuffRelayAsync {
+            relay_: relay,
+            length_: length,
+            min_len_: cmp::min(min_len, length),
+        }
+    }
+
+    /// Only the reads are cancellable. Once the segments are taken from the
+    /// reader, they are consumed and thus must be written completely, so the
+    /// writes are not interrupted by the cancellation token.
+    async fn relay_async_<C>(
+        self,
+        mut cancel: Pin<&'a mut C>,
+    ) -> Result<usize, ChunkPumpAbort<R::Err, W::Err>>
+    where
+        C: TrCancellationToken,
+    {
+        let relay = self.relay_;
+        let classify = relay.classify_;
+        let reader = relay.reader_.borrow_mut();
+        let writer = relay.writer_.borrow_mut();
+        let length = self.length_;
+        let min_len = self.min_len_;
+        let mut read_len = 0usize;
+        let mut write_len = 0usize;
+        let last_error = loop {
+            if read_len >= min_len {
+                return Result::Ok(write_len);
+            }
+            let r = reader
+                .read_async(length - read_len)
+                .may_cancel_with(cancel.as_mut())
+                .await;
+            let src_iter = match r {
+                Result::Ok(src_iter) => src_iter,
+                Result::Err(last_error) => break last_error,
+            };
+            let last_read_len = read_len;
+            let mut src_iter = src_iter.into_iter();
+            let mut write_error = Option::None;
+            for src in src_iter.by_ref() {
+                let src_len = src.len();
+                read_len += src_len;
+                let mut offset = 0usize;
+                while offset < src_len {
+                    let w = writer
+                        .write_async(src_len - offset)
+                        .may_cancel_with(NonCancellableToken::pinned())
+                        .await;
+                    let dst_iter = match w {
+                        Result::Ok(dst_iter) => dst_iter,
+                        Result::Err(e) => {
+                            write_error = Option::Some(ChunkPumpError::Load(e));
+                            break;
+                        },
+                    };
+                    let last_offset = offset;
+                    for mut dst in dst_iter.into_iter() {
+                        let opr_len = cmp::min(dst.len(), src_len - offset);
+                        if opr_len == 0 {
+                            break;
+                        }
+                        let s = &src[offset..offset + opr_len];
+                        dst[..opr_len].clone_from_slice(s);
+                        offset += opr_len;
+                        write_len += opr_len;
+                    }
+                    if offset == last_offset {
+                        write_error = Option::Some(ChunkPumpError::LoadStalled);
+                        break;
+                    }
+                }
+                if write_error.is_some() {
+                    break;
+                }
+            }
+            if let Option::Some(e) = write_error {
+                // The rest of the segments are consumed along with the iter.
+                read_len += src_iter.map(|s| s.len()).sum::<usize>();
+                return Result::Err(
+                    ChunkPumpAbort::new(read_len, write_len, e));
+            }
+            if read_len == last_read_len {
+                // The reader has nothing more to hand out.
+                let e = ChunkPumpError::FillExhausted;
+                let kind = ChunkIoAbortKind::EndOfStream;
+                return Result::Err(
+                    ChunkPumpAbort::with_kind(read_len, write_len, e, kind));
+            }
+        };
+        let kind = if cancel.is_cancelled() {
+            ChunkIoAbortKind::Cancelled
+        } else {
+            classify(reader, &last_error)
+        };
+        let e = ChunkPumpError::Fill(last_error);
+        Result::Err(ChunkPumpAbort::with_kind(read_len, write_len, e, kind))
+    }
+}
+
+impl<'a, BR, R, BW, W, T> TrIntoFutureMayCancel<'a>
+for BuffRelayAsync<'a, BR, R, BW, W, T>
+where
+    BR: BorrowMut<R>,
+    R: TrBuffIterRead<T>,
+    BW: BorrowMut<W>,
+    W: TrBuffIterWrite<T>,
+    T: 'a + Clone,
+{
+    type MayCancelOutput = Result<usize, ChunkPumpAbort<R::Err, W::Err>>;
+
+    #[inline(always)]
+    fn may_cancel_with<C>(
+        self,
+        cancel: Pin<&'a mut C>,
+    ) -> impl Future<Output = Self::MayCancelOutput>
+    where
+        C: TrCancellationToken,
+    {
+        self.relay_async_(cancel)
+    }
+}
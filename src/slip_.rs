@@ -0,0 +1,563 @@
+use core::{
+    borrow::BorrowMut,
+    error::Error,
+    future::{Future, IntoFuture},
+    marker::PhantomData,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use abs_buff::x_deps::abs_sync;
+use abs_sync::cancellation::*;
+
+use crate::{
+    chunk_async_::{
+        ChunkFillAsync, ChunkLoadAsync, TrChunkFillWith, TrChunkLoadWith,
+    },
+    stuff_::{StuffBuf, StuffStage},
+    ChunkIoAbort, StuffError, TrChunkFiller, TrChunkIoAbort, TrChunkLoader,
+};
+
+const SLIP_END: u8 = 0xC0;
+const SLIP_ESC: u8 = 0xDB;
+const SLIP_ESC_END: u8 = 0xDC;
+const SLIP_ESC_ESC: u8 = 0xDD;
+
+/// SLIP-encodes (RFC 1055) the units loaded through it into the inner loader.
+///
+/// The encoded units are buffered, and are not necessarily passed to the
+/// inner loader until the frame is ended by `finish_frame_async`, which also
+/// emits the `END` unit. If the inner loader is aborted, the units not yet
+/// emitted are kept and emitted by the next load or finish.
+pub struct SlipEncodeLoader<BL, L>
+where
+    BL: BorrowMut<L>,
+    L: TrChunkLoader<u8>,
+{
+    _use_l_: PhantomData<L>,
+    loader_: BL,
+    buf_: StuffBuf,
+    closing_: bool,
+}
+
+impl<BL, L> SlipEncodeLoader<BL, L>
+where
+    BL: BorrowMut<L>,
+    L: TrChunkLoader<u8>,
+{
+    pub const fn new(loader: BL) -> Self {
+        SlipEncodeLoader {
+            _use_l_: PhantomData,
+            loader_: loader,
+            buf_: StuffBuf::new(),
+            closing_: false,
+        }
+    }
+
+    /// Emits the buffered units of the frame and the `END` unit.
+    pub fn finish_frame_async(&mut self) -> SlipFinishAsync<'_, BL, L> {
+        SlipFinishAsync::new(self)
+    }
+
+    /// Returns the inner loader, discarding the unfinished frame.
+    pub fn into_inner(self) -> BL {
+        self.loader_
+    }
+
+    fn poll_finish_<C>(
+        &mut self,
+        mut cancel: Pin<&mut C>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), L::IoAbort>>
+    where
+        C: TrCancellationToken,
+    {
+        if !self.closing_ {
+            if self.buf_.free_len() < 1 {
+                let loader = self.loader_.borrow_mut();
+                let r = self.buf_.poll_flush(loader, cancel.as_mut(), cx);
+                let Poll::Ready(r) = r else {
+                    return Poll::Pending;
+                };
+                if r.is_err() {
+                    return Poll::Ready(r);
+                }
+            }
+            self.buf_.push(SLIP_END);
+            self.closing_ = true;
+        }
+        let loader = self.loader_.borrow_mut();
+        let r = self.buf_.poll_flush(loader, cancel, cx);
+        let Poll::Ready(r) = r else {
+            return Poll::Pending;
+        };
+        if r.is_ok() {
+            self.closing_ = false;
+        }
+        Poll::Ready(r)
+    }
+}
+
+impl<BL, L> TrChunkLoader<u8> for SlipEncodeLoader<BL, L>
+where
+    BL: BorrowMut<L>,
+    L: TrChunkLoader<u8>,
+    L::IoAbort: Error,
+{
+    type IoAbort = ChunkIoAbort<StuffError<L::IoAbort>>;
+    type LoadAsync<'a> = ChunkLoadAsync<'a, Self, u8> where Self: 'a;
+
+    #[inline(always)]
+    fn load_async<'a>(
+        &'a mut self,
+        source: &'a [u8],
+    ) -> Self::LoadAsync<'a> {
+        ChunkLoadAsync::new(self, source)
+    }
+
+    #[inline(always)]
+    fn load_at_least_async<'a>(
+        &'a mut self,
+        source: &'a [u8],
+        min_len: usize,
+    ) -> Self::LoadAsync<'a> {
+        ChunkLoadAsync::new_at_least(self, source, min_len)
+    }
+}
+
+impl<BL, L> TrChunkLoadWith<u8> for SlipEncodeLoader<BL, L>
+where
+    BL: BorrowMut<L>,
+    L: TrChunkLoader<u8>,
+    L::IoAbort: Error,
+{
+    fn poll_load_with<C>(
+        &mut self,
+        source: &[u8],
+        min_len: usize,
+        perform_len: &mut usize,
+        mut cancel: Pin<&mut C>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<usize, Self::IoAbort>>
+    where
+        C: TrCancellationToken,
+    {
+        // The `END` of the previous frame is already buffered.
+        self.closing_ = false;
+        while *perform_len < source.len() {
+            if self.buf_.free_len() < 2 {
+                if *perform_len >= min_len {
+                    break;
+                }
+                let loader = self.loader_.borrow_mut();
+                let r = self.buf_.poll_flush(loader, cancel.as_mut(), cx);
+                let Poll::Ready(r) = r else {
+                    return Poll::Pending;
+                };
+                if let Result::Err(a) = r {
+                    let kind = a.kind();
+                    let e = StuffError::Io(a);
+                    return Poll::Ready(Result::Err(
+                        ChunkIoAbort::with_kind(*perform_len, e, kind),
+                    ));
+                }
+            }
+            match source[*perform_len] {
+                SLIP_END => {
+                    self.buf_.push(SLIP_ESC);
+                    self.buf_.push(SLIP_ESC_END);
+                },
+                SLIP_ESC => {
+                    self.buf_.push(SLIP_ESC);
+                    self.buf_.push(SLIP_ESC_ESC);
+                },
+                byte => self.buf_.push(byte),
+            }
+            *perform_len += 1;
+        }
+        Poll::Ready(Result::Ok(*perform_len))
+    }
+}
+
+pub struct SlipFinishAsync<'a, BL, L>
+where
+    BL: BorrowMut<L>,
+    L: TrChunkLoader<u8>,
+{
+    loader_: &'a mut SlipEncodeLoader<BL, L>,
+}
+
+impl<'a, BL, L> SlipFinishAsync<'a, BL, L>
+where
+    BL: BorrowMut<L>,
+    L: TrChunkLoader<u8>,
+{
+    pub fn new(loader: &'a mut SlipEncodeLoader<BL, L>) -> Self {
+        SlipFinishAsync { loader_: loader }
+    }
+
+    pub fn may_cancel_with<C>(
+        self,
+        cancel: Pin<&'a mut C>,
+    ) -> SlipFinishFuture<'a, C, BL, L>
+    where
+        C: TrCancellationToken,
+    {
+        SlipFinishFuture::new(self, cancel)
+    }
+}
+
+impl<'a, BL, L> IntoFuture for SlipFinishAsync<'a, BL, L>
+where
+    BL: BorrowMut<L>,
+    L: TrChunkLoader<u8>,
+{
+    type IntoFuture = SlipFinishFuture<'a, NonCancellableToken, BL, L>;
+    type Output = <Self::IntoFuture as Future>::Output;
+
+    fn into_future(self) -> Self::IntoFuture {
+        let cancel = NonCancellableToken::pinned();
+        SlipFinishFuture::new(self, cancel)
+    }
+}
+
+impl<'a, BL, L> TrIntoFutureMayCancel<'a> for SlipFinishAsync<'a, BL, L>
+where
+    BL: BorrowMut<L>,
+    L: TrChunkLoader<u8>,
+{
+    type MayCancelOutput = <Self as IntoFuture>::Output;
+
+    #[inline(always)]
+    fn may_cancel_with<C>(
+        self,
+        cancel: Pin<&'a mut C>,
+    ) -> impl Future<Output = Self::MayCancelOutput>
+    where
+        C: TrCancellationToken,
+    {
+        SlipFinishAsync::may_cancel_with(self, cancel)
+    }
+}
+
+/// The future that finishes the frame of a `SlipEncodeLoader`.
+///
+/// The units of the frame left to emit are kept in the loader, so that a
+/// finish that spans several wakeups resumes from where it stops.
+pub struct SlipFinishFuture<'a, C, BL, L>
+where
+    C: TrCancellationToken,
+    BL: BorrowMut<L>,
+    L: TrChunkLoader<u8>,
+{
+    loader_: &'a mut SlipEncodeLoader<BL, L>,
+    cancel_: Pin<&'a mut C>,
+}
+
+impl<'a, C, BL, L> SlipFinishFuture<'a, C, BL, L>
+where
+    C: TrCancellationToken,
+    BL: BorrowMut<L>,
+    L: TrChunkLoader<u8>,
+{
+    pub fn new(
+        finish: SlipFinishAsync<'a, BL, L>,
+        cancel: Pin<&'a mut C>,
+    ) -> Self {
+        SlipFinishFuture {
+            loader_: finish.loader_,
+            cancel_: cancel,
+        }
+    }
+}
+
+impl<C, BL, L> Future for SlipFinishFuture<'_, C, BL, L>
+where
+    C: TrCancellationToken,
+    BL: BorrowMut<L>,
+    L: TrChunkLoader<u8>,
+{
+    type Output = Result<(), L::IoAbort>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        this.loader_.poll_finish_(this.cancel_.as_mut(), cx)
+    }
+}
+
+/// Decodes SLIP (RFC 1055) frames from the inner filler.
+///
+/// A fill never crosses the end of a frame. If the frame ends before the fill
+/// is satisfied, the fill is aborted with `StuffError::EndOfFrame`. On an
+/// invalid escape sequence, the fill is aborted with `StuffError::Malformed`,
+/// and the rest of the frame is skipped by the next fill. Empty frames are
+/// skipped.
+///
+/// The encoded units are filled from the inner filler in chunks, and those
+/// after the end of a frame are kept for the next fill. If the inner filler
+/// fills nothing, the fill completes with the units decoded so far.
+pub struct SlipDecodeFiller<BF, F>
+where
+    BF: BorrowMut<F>,
+    F: TrChunkFiller<u8>,
+{
+    _use_f_: PhantomData<F>,
+    filler_: BF,
+    stage_: StuffStage,
+    escape_: bool,
+    in_frame_: bool,
+    resync_: bool,
+}
+
+impl<BF, F> SlipDecodeFiller<BF, F>
+where
+    BF: BorrowMut<F>,
+    F: TrChunkFiller<u8>,
+{
+    pub const fn new(filler: BF) -> Self {
+        SlipDecodeFiller {
+            _use_f_: PhantomData,
+            filler_: filler,
+            stage_: StuffStage::new(),
+            escape_: false,
+            in_frame_: false,
+            resync_: false,
+        }
+    }
+
+    /// Whether the fills have started decoding a frame that is not ended yet.
+    pub const fn is_in_frame(&self) -> bool {
+        self.in_frame_
+    }
+
+    /// Number of units filled from the inner filler but not yet decoded.
+    pub const fn staged_len(&self) -> usize {
+        self.stage_.staged_len()
+    }
+
+    /// Returns the inner filler, dropping the units not yet decoded.
+    pub fn into_inner(self) -> BF {
+        self.filler_
+    }
+}
+
+impl<BF, F> TrChunkFiller<u8> for SlipDecodeFiller<BF, F>
+where
+    BF: BorrowMut<F>,
+    F: TrChunkFiller<u8>,
+    F::IoAbort: Error,
+{
+    type IoAbort = ChunkIoAbort<StuffError<F::IoAbort>>;
+    type FillAsync<'a> = ChunkFillAsync<'a, Self, u8> where Self: 'a;
+
+    #[inline(always)]
+    fn fill_async<'a>(
+        &'a mut self,
+        target: &'a mut [u8],
+    ) -> Self::FillAsync<'a> {
+        ChunkFillAsync::new(self, target)
+    }
+
+    #[inline(always)]
+    fn fill_at_least_async<'a>(
+        &'a mut self,
+        target: &'a mut [u8],
+        min_len: usize,
+    ) -> Self::FillAsync<'a> {
+        ChunkFillAsync::new_at_least(self, target, min_len)
+    }
+}
+
+impl<BF, F> TrChunkFillWith<u8> for SlipDecodeFiller<BF, F>
+where
+    BF: BorrowMut<F>,
+    F: TrChunkFiller<u8>,
+    F::IoAbort: Error,
+{
+    fn poll_fill_with<C>(
+        &mut self,
+        target: &mut [u8],
+        min_len: usize,
+        perform_len: &mut usize,
+        mut cancel: Pin<&mut C>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<usize, Self::IoAbort>>
+    where
+        C: TrCancellationToken,
+    {
+        while *perform_len < min_len {
+            let Option::Some(byte) = self.stage_.pop() else {
+                let filler = self.filler_.borrow_mut();
+                let r = self.stage_.poll_fill(filler, cancel.as_mut(), cx);
+                let Poll::Ready(r) = r else {
+                    return Poll::Pending;
+                };
+                match r {
+                    Result::Ok(0) => break,
+                    Result::Ok(_) => continue,
+                    Result::Err(a) => {
+                        let kind = a.kind();
+                        let e = StuffError::Io(a);
+                        return Poll::Ready(Result::Err(
+                            ChunkIoAbort::with_kind(*perform_len, e, kind),
+                        ));
+                    },
+                }
+            };
+            if byte == SLIP_END {
+                let in_frame = self.in_frame_ && !self.resync_;
+                let escape = self.escape_;
+                self.escape_ = false;
+                self.in_frame_ = false;
+                self.resync_ = false;
+                if escape {
+                    return Poll::Ready(Result::Err(
+                        ChunkIoAbort::new(*perform_len, StuffError::Malformed),
+                    ));
+                }
+                if in_frame {
+                    return Poll::Ready(Result::Err(
+                        ChunkIoAbort::end_of_stream(
+                            *perform_len,
+                            StuffError::EndOfFrame,
+                        ),
+                    ));
+                }
+                continue;
+            }
+            if self.resync_ {
+                continue;
+            }
+            self.in_frame_ = true;
+            if self.escape_ {
+                self.escape_ = false;
+                let decoded = match byte {
+                    SLIP_ESC_END => SLIP_END,
+                    SLIP_ESC_ESC => SLIP_ESC,
+                    _ => {
+                        self.resync_ = true;
+                        return Poll::Ready(Result::Err(
+                            ChunkIoAbort::new(*perform_len, StuffError::Malformed),
+                        ));
+                    },
+                };
+                target[*perform_len] = decoded;
+                *perform_len += 1;
+            } else if byte == SLIP_ESC {
+                self.escape_ = true;
+            } else {
+                target[*perform_len] = byte;
+                *perform_len += 1;
+            }
+        }
+        Poll::Ready(Result::Ok(*perform_len))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::vec::Vec;
+
+    use super::*;
+    use crate::test_utils_::*;
+
+    fn encode(frames: &[&[u8]]) -> Vec<u8> {
+        let mut encoder = SlipEncodeLoader::new(VecLoader::default());
+        for frame in frames {
+            let n = block_on(
+                encoder.load_async(frame).may_cancel_with(no_cancel()),
+            );
+            assert_eq!(n.unwrap(), frame.len());
+            block_on(encoder.finish_frame_async().may_cancel_with(no_cancel()))
+                .unwrap();
+        }
+        encoder.into_inner().0
+    }
+
+    /// Decodes the next frame, which is ended before the target is full.
+    fn decode_frame<F>(
+        decoder: &mut SlipDecodeFiller<F, F>,
+        target: &mut [u8],
+    ) -> Result<usize, ChunkIoAbort<StuffError<F::IoAbort>>>
+    where
+        F: TrChunkFiller<u8>,
+        F::IoAbort: Error,
+    {
+        let r = block_on(
+            decoder.fill_async(target).may_cancel_with(no_cancel()),
+        );
+        let a = r.unwrap_err();
+        match a.last_error() {
+            StuffError::EndOfFrame => Result::Ok(a.perform_len()),
+            _ => Result::Err(a),
+        }
+    }
+
+    #[test]
+    fn encode_escapes() {
+        assert_eq!(
+            encode(&[&[SLIP_END, 0x01, SLIP_ESC, SLIP_ESC_END]]),
+            [
+                SLIP_ESC, SLIP_ESC_END, 0x01, SLIP_ESC, SLIP_ESC_ESC,
+                SLIP_ESC_END, SLIP_END,
+            ],
+        );
+        assert_eq!(encode(&[b"a", b"b"]), [b'a', SLIP_END, b'b', SLIP_END]);
+    }
+
+    #[test]
+    fn round_trip() {
+        let long: Vec<u8> = (0..600usize).map(|i| (i * 31) as u8).collect();
+        let frames: [&[u8]; 4] =
+            [b"hello", &[SLIP_END, SLIP_ESC, SLIP_END], &long, b"x"];
+        let encoded = encode(&frames);
+        for seg_len in [1, 5, 64, 4096] {
+            let mut decoder =
+                SlipDecodeFiller::new(SliceFiller::new(&encoded, seg_len));
+            let mut target = [0u8; 1024];
+            for frame in frames {
+                let n = decode_frame(&mut decoder, &mut target).unwrap();
+                assert_eq!(&target[..n], frame);
+                assert!(!decoder.is_in_frame());
+            }
+            let r = block_on(
+                decoder.fill_async(&mut target).may_cancel_with(no_cancel()),
+            );
+            assert!(r.unwrap_err().is_end_of_stream());
+        }
+    }
+
+    #[test]
+    fn empty_frames_skipped() {
+        let encoded =
+            [SLIP_END, SLIP_END, b'a', SLIP_END, SLIP_END, b'b', SLIP_END];
+        let mut decoder = SlipDecodeFiller::new(SliceFiller::new(&encoded, 64));
+        let mut target = [0u8; 8];
+        let n = decode_frame(&mut decoder, &mut target).unwrap();
+        assert_eq!(&target[..n], b"a");
+        let n = decode_frame(&mut decoder, &mut target).unwrap();
+        assert_eq!(&target[..n], b"b");
+        assert_eq!(decoder.filler_.fill_count(), 1);
+    }
+
+    #[test]
+    fn malformed_escape() {
+        let encoded = [
+            b'a', SLIP_ESC, 0x05, b'b', SLIP_END,
+            b'c', SLIP_ESC, SLIP_END,
+            b'd', SLIP_END,
+        ];
+        let mut decoder = SlipDecodeFiller::new(SliceFiller::new(&encoded, 4));
+        let mut target = [0u8; 8];
+        let a = decode_frame(&mut decoder, &mut target).unwrap_err();
+        assert!(matches!(a.last_error(), StuffError::Malformed));
+        assert_eq!(a.perform_len(), 1);
+        // The rest of the malformed frame is skipped.
+        let a = decode_frame(&mut decoder, &mut target).unwrap_err();
+        assert!(matches!(a.last_error(), StuffError::Malformed));
+        assert_eq!(a.perform_len(), 1);
+        assert_eq!(target[0], b'c');
+        let n = decode_frame(&mut decoder, &mut target).unwrap();
+        assert_eq!(&target[..n], b"d");
+    }
+}
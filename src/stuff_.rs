@@ -0,0 +1,180 @@
+use core::{
+    error::Error,
+    fmt,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use abs_buff::x_deps::abs_sync;
+use abs_sync::cancellation::*;
+
+use crate::{
+    chunk_async_::{poll_fill_some, poll_load_some},
+    TrChunkFiller, TrChunkIoAbort, TrChunkLoader,
+};
+
+/// The size of the buffer that the byte-stuffing encoders encode into, and
+/// that the decoders fill the encoded units into.
+const STUFF_BUF_LEN: usize = 64;
+
+/// The error of byte-stuffing (SLIP or HDLC) encoding or decoding.
+#[derive(Debug)]
+pub enum StuffError<A> {
+    /// An invalid escape sequence or an aborted frame. The rest of the frame
+    /// is discarded, and the next fill starts with the next frame.
+    Malformed,
+    /// The frame check sequence of an HDLC frame does not match. The frame
+    /// is ended, and the units filled should be discarded.
+    FcsMismatch,
+    /// The frame ends before the fill is satisfied. The next fill starts with
+    /// the next frame.
+    EndOfFrame,
+    /// The inner filler or loader is aborted.
+    Io(A),
+}
+
+impl<A> fmt::Display for StuffError<A>
+where
+    A: Error,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StuffError::Malformed => write!(f, "malformed frame"),
+            StuffError::FcsMismatch => write!(f, "frame check sequence mismatch"),
+            StuffError::EndOfFrame => write!(f, "end of frame"),
+            StuffError::Io(a) => write!(f, "{a}"),
+        }
+    }
+}
+
+impl<A> Error for StuffError<A>
+where
+    A: Error,
+{}
+
+/// The encoded units waiting to be emitted into the inner loader.
+pub(crate) struct StuffBuf {
+    buf_: [u8; STUFF_BUF_LEN],
+    len_: usize,
+    emit_len_: usize,
+}
+
+impl StuffBuf {
+    pub(crate) const fn new() -> Self {
+        StuffBuf {
+            buf_: [0u8; STUFF_BUF_LEN],
+            len_: 0usize,
+            emit_len_: 0usize,
+        }
+    }
+
+    pub(crate) const fn free_len(&self) -> usize {
+        STUFF_BUF_LEN - self.len_
+    }
+
+    pub(crate) fn push(&mut self, byte: u8) {
+        self.buf_[self.len_] = byte;
+        self.len_ += 1;
+    }
+
+    /// Polls the emission of the buffered units. On abortion, the units
+    /// emitted are dropped from the buffer, and the rest are emitted by the
+    /// next flush.
+    pub(crate) fn poll_flush<L, C>(
+        &mut self,
+        loader: &mut L,
+        mut cancel: Pin<&mut C>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), L::IoAbort>>
+    where
+        L: TrChunkLoader<u8>,
+        C: TrCancellationToken,
+    {
+        while self.emit_len_ < self.len_ {
+            let source = &self.buf_[self.emit_len_..self.len_];
+            let r = poll_load_some(loader, source, cancel.as_mut(), cx);
+            let Poll::Ready(r) = r else {
+                return Poll::Pending;
+            };
+            match r {
+                Result::Ok(n) => self.emit_len_ += n,
+                Result::Err(a) => {
+                    self.emit_len_ += a.perform_len();
+                    return Poll::Ready(Result::Err(a));
+                },
+            }
+        }
+        self.len_ = 0;
+        self.emit_len_ = 0;
+        Poll::Ready(Result::Ok(()))
+    }
+}
+
+/// The encoded units filled from the inner filler, waiting to be decoded.
+///
+/// The decoders fill as many units as the inner filler has at hand, and
+/// decode them one by one, so that the units after the end of a frame are
+/// kept for the next frame.
+pub(crate) struct StuffStage {
+    buf_: [u8; STUFF_BUF_LEN],
+    head_: usize,
+    len_: usize,
+}
+
+impl StuffStage {
+    pub(crate) const fn new() -> Self {
+        StuffStage {
+            buf_: [0u8; STUFF_BUF_LEN],
+            head_: 0usize,
+            len_: 0usize,
+        }
+    }
+
+    /// Number of units filled but not yet decoded.
+    pub(crate) const fn staged_len(&self) -> usize {
+        self.len_ - self.head_
+    }
+
+    /// Takes the next unit to decode, if any.
+    pub(crate) fn pop(&mut self) -> Option<u8> {
+        if self.head_ == self.len_ {
+            return Option::None;
+        }
+        let byte = self.buf_[self.head_];
+        self.head_ += 1;
+        Option::Some(byte)
+    }
+
+    /// Polls the fill of some units from the inner filler, once the staged
+    /// units are all decoded.
+    ///
+    /// Completes with the number of units filled. If the filler is aborted
+    /// after filling some units, the abortion is dropped in favour of the
+    /// units filled, and is expected to recur on the next fill.
+    pub(crate) fn poll_fill<F, C>(
+        &mut self,
+        filler: &mut F,
+        cancel: Pin<&mut C>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<usize, F::IoAbort>>
+    where
+        F: TrChunkFiller<u8>,
+        C: TrCancellationToken,
+    {
+        debug_assert_eq!(self.staged_len(), 0);
+        let r = poll_fill_some(filler, &mut self.buf_, cancel, cx);
+        let Poll::Ready(r) = r else {
+            return Poll::Pending;
+        };
+        let fill_len = match r {
+            Result::Ok(n) => n,
+            Result::Err(a) if a.perform_len() == 0 => {
+                return Poll::Ready(Result::Err(a));
+            },
+            Result::Err(a) => a.perform_len(),
+        };
+        self.head_ = 0;
+        self.len_ = fill_len;
+        Poll::Ready(Result::Ok(fill_len))
+    }
+}
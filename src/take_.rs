@@ -0,0 +1,291 @@
+use core::{
+    borrow::BorrowMut,
+    cmp,
+    error::Error,
+    fmt,
+    marker::PhantomData,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use abs_buff::x_deps::abs_sync;
+use abs_sync::cancellation::*;
+
+use crate::{
+    chunk_async_::{
+        poll_fill_some, poll_load_some,
+        ChunkFillAsync, ChunkLoadAsync, TrChunkFillWith, TrChunkLoadWith,
+    },
+    ChunkIoAbort, TrChunkFiller, TrChunkIoAbort, TrChunkLoader,
+};
+
+/// The error of a filler or loader with a limit.
+#[derive(Debug)]
+pub enum TakeError<A> {
+    /// The limit is reached before the fill or load is satisfied.
+    LimitReached,
+    /// The inner filler or loader is aborted.
+    Io(A),
+}
+
+impl<A> fmt::Display for TakeError<A>
+where
+    A: Error,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TakeError::LimitReached => write!(f, "limit reached"),
+            TakeError::Io(a) => write!(f, "{a}"),
+        }
+    }
+}
+
+impl<A> Error for TakeError<A>
+where
+    A: Error,
+{}
+
+/// Fills at most a limited number of units from the inner filler.
+///
+/// A fill that cannot be satisfied within the remaining limit fills what is
+/// left, and is then aborted with `TakeError::LimitReached` as the end of
+/// stream, with the `perform_len` reporting the units filled.
+pub struct TakeFiller<BF, F, T>
+where
+    BF: BorrowMut<F>,
+    F: TrChunkFiller<T>,
+    T: Clone,
+{
+    _use_f_: PhantomData<F>,
+    _use_t_: PhantomData<[T]>,
+    filler_: BF,
+    remain_len_: usize,
+}
+
+impl<BF, F, T> TakeFiller<BF, F, T>
+where
+    BF: BorrowMut<F>,
+    F: TrChunkFiller<T>,
+    T: Clone,
+{
+    pub const fn new(filler: BF, limit: usize) -> Self {
+        TakeFiller {
+            _use_f_: PhantomData,
+            _use_t_: PhantomData,
+            filler_: filler,
+            remain_len_: limit,
+        }
+    }
+
+    /// Number of units that can still be filled.
+    pub const fn remain_len(&self) -> usize {
+        self.remain_len_
+    }
+
+    pub fn into_inner(self) -> BF {
+        self.filler_
+    }
+}
+
+impl<BF, F, T> TrChunkFiller<T> for TakeFiller<BF, F, T>
+where
+    BF: BorrowMut<F>,
+    F: TrChunkFiller<T>,
+    F::IoAbort: Error,
+    T: Clone,
+{
+    type IoAbort = ChunkIoAbort<TakeError<F::IoAbort>>;
+    type FillAsync<'a> = ChunkFillAsync<'a, Self, T> where Self: 'a;
+
+    #[inline(always)]
+    fn fill_async<'a>(
+        &'a mut self,
+        target: &'a mut [T],
+    ) -> Self::FillAsync<'a> {
+        ChunkFillAsync::new(self, target)
+    }
+
+    #[inline(always)]
+    fn fill_at_least_async<'a>(
+        &'a mut self,
+        target: &'a mut [T],
+        min_len: usize,
+    ) -> Self::FillAsync<'a> {
+        ChunkFillAsync::new_at_least(self, target, min_len)
+    }
+}
+
+impl<BF, F, T> TrChunkFillWith<T> for TakeFiller<BF, F, T>
+where
+    BF: BorrowMut<F>,
+    F: TrChunkFiller<T>,
+    F::IoAbort: Error,
+    T: Clone,
+{
+    fn poll_fill_with<C>(
+        &mut self,
+        target: &mut [T],
+        min_len: usize,
+        perform_len: &mut usize,
+        mut cancel: Pin<&mut C>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<usize, Self::IoAbort>>
+    where
+        C: TrCancellationToken,
+    {
+        let filler = self.filler_.borrow_mut();
+        while *perform_len < min_len {
+            let cap = cmp::min(target.len(), *perform_len + self.remain_len_);
+            if cap == *perform_len {
+                let e = TakeError::LimitReached;
+                let a = ChunkIoAbort::end_of_stream(*perform_len, e);
+                return Poll::Ready(Result::Err(a));
+            }
+            let rest = &mut target[*perform_len..cap];
+            let r = poll_fill_some(filler, rest, cancel.as_mut(), cx);
+            let Poll::Ready(r) = r else {
+                return Poll::Pending;
+            };
+            match r {
+                Result::Ok(0) => break,
+                Result::Ok(n) => {
+                    self.remain_len_ -= n;
+                    *perform_len += n;
+                },
+                Result::Err(a) => {
+                    let n = a.perform_len();
+                    let kind = a.kind();
+                    self.remain_len_ -= n;
+                    *perform_len += n;
+                    let e = TakeError::Io(a);
+                    let a = ChunkIoAbort::with_kind(*perform_len, e, kind);
+                    return Poll::Ready(Result::Err(a));
+                },
+            }
+        }
+        Poll::Ready(Result::Ok(*perform_len))
+    }
+}
+
+/// Loads at most a limited number of units into the inner loader.
+///
+/// A load that cannot be satisfied within the remaining limit loads what is
+/// left, and is then aborted with `TakeError::LimitReached` as the end of
+/// stream, with the `perform_len` reporting the units loaded.
+pub struct TakeLoader<BL, L, T>
+where
+    BL: BorrowMut<L>,
+    L: TrChunkLoader<T>,
+    T: Clone,
+{
+    _use_l_: PhantomData<L>,
+    _use_t_: PhantomData<[T]>,
+    loader_: BL,
+    remain_len_: usize,
+}
+
+impl<BL, L, T> TakeLoader<BL, L, T>
+where
+    BL: BorrowMut<L>,
+    L: TrChunkLoader<T>,
+    T: Clone,
+{
+    pub const fn new(loader: BL, limit: usize) -> Self {
+        TakeLoader {
+            _use_l_: PhantomData,
+            _use_t_: PhantomData,
+            loader_: loader,
+            remain_len_: limit,
+        }
+    }
+
+    /// Number of units that can still be loaded.
+    pub const fn remain_len(&self) -> usize {
+        self.remain_len_
+    }
+
+    pub fn into_inner(self) -> BL {
+        self.loader_
+    }
+}
+
+impl<BL, L, T> TrChunkLoader<T> for TakeLoader<BL, L, T>
+where
+    BL: BorrowMut<L>,
+    L: TrChunkLoader<T>,
+    L::IoAbort: Error,
+    T: Clone,
+{
+    type IoAbort = ChunkIoAbort<TakeError<L::IoAbort>>;
+    type LoadAsync<'a> = ChunkLoadAsync<'a, Self, T> where Self: 'a;
+
+    #[inline(always)]
+    fn load_async<'a>(
+        &'a mut self,
+        source: &'a [T],
+    ) -> Self::LoadAsync<'a> {
+        ChunkLoadAsync::new(self, source)
+    }
+
+    #[inline(always)]
+    fn load_at_least_async<'a>(
+        &'a mut self,
+        source: &'a [T],
+        min_len: usize,
+    ) -> Self::LoadAsync<'a> {
+        ChunkLoadAsync::new_at_least(self, source, min_len)
+    }
+}
+
+impl<BL, L, T> TrChunkLoadWith<T> for TakeLoader<BL, L, T>
+where
+    BL: BorrowMut<L>,
+    L: TrChunkLoader<T>,
+    L::IoAbort: Error,
+    T: Clone,
+{
+    fn poll_load_with<C>(
+        &mut self,
+        source: &[T],
+        min_len: usize,
+        perform_len: &mut usize,
+        mut cancel: Pin<&mut C>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<usize, Self::IoAbort>>
+    where
+        C: TrCancellationToken,
+    {
+        let loader = self.loader_.borrow_mut();
+        while *perform_len < min_len {
+            let cap = cmp::min(source.len(), *perform_len + self.remain_len_);
+            if cap == *perform_len {
+                let e = TakeError::LimitReached;
+                let a = ChunkIoAbort::end_of_stream(*perform_len, e);
+                return Poll::Ready(Result::Err(a));
+            }
+            let rest = &source[*perform_len..cap];
+            let r = poll_load_some(loader, rest, cancel.as_mut(), cx);
+            let Poll::Ready(r) = r else {
+                return Poll::Pending;
+            };
+            match r {
+                Result::Ok(0) => break,
+                Result::Ok(n) => {
+                    self.remain_len_ -= n;
+                    *perform_len += n;
+                },
+                Result::Err(a) => {
+                    let n = a.perform_len();
+                    let kind = a.kind();
+                    self.remain_len_ -= n;
+                    *perform_len += n;
+                    let e = TakeError::Io(a);
+                    let a = ChunkIoAbort::with_kind(*perform_len, e, kind);
+                    return Poll::Ready(Result::Err(a));
+                },
+            }
+        }
+        Poll::Ready(Result::Ok(*perform_len))
+    }
+}
+
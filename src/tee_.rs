@@ -0,0 +1,290 @@
+use core::{
+    borrow::BorrowMut,
+    cmp,
+    error::Error,
+    fmt,
+    marker::PhantomData,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use abs_buff::x_deps::abs_sync;
+use abs_sync::cancellation::*;
+
+use crate::{
+    chunk_async_::{poll_load_some, ChunkLoadAsync, TrChunkLoadWith},
+    ChunkIoAbortKind, TrChunkIoAbort, TrChunkLoader,
+};
+
+/// The side of a `TeeLoader` that causes the abortion.
+#[derive(Debug)]
+pub enum TeeError<A1, A2> {
+    /// The first loader is aborted.
+    First(A1),
+    /// The second loader is aborted.
+    Second(A2),
+    /// The second loader accepts none of the units the first one accepts.
+    SecondStalled,
+}
+
+impl<A1, A2> fmt::Display for TeeError<A1, A2>
+where
+    A1: Error,
+    A2: Error,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TeeError::First(a) => write!(f, "first loader: {a}"),
+            TeeError::Second(a) => write!(f, "second loader: {a}"),
+            TeeError::SecondStalled => write!(f, "second loader stalled"),
+        }
+    }
+}
+
+impl<A1, A2> Error for TeeError<A1, A2>
+where
+    A1: Error,
+    A2: Error,
+{}
+
+/// To report the detail of an aborted `TeeLoader`.
+///
+/// The `perform_len` is the number of units loaded into both loaders. The
+/// first loader is ahead of the second by the difference between `first_len`
+/// and `second_len`.
+#[derive(Debug)]
+pub struct TeeAbort<A1, A2> {
+    first_len_: usize,
+    second_len_: usize,
+    error_: TeeError<A1, A2>,
+}
+
+impl<A1, A2> TeeAbort<A1, A2> {
+    pub const fn new(
+        first_len: usize,
+        second_len: usize,
+        error: TeeError<A1, A2>,
+    ) -> Self {
+        TeeAbort {
+            first_len_: first_len,
+            second_len_: second_len,
+            error_: error,
+        }
+    }
+
+    /// Number of units that has been loaded into the first loader.
+    pub const fn first_len(&self) -> usize {
+        self.first_len_
+    }
+
+    /// Number of units that has been loaded into the second loader.
+    pub const fn second_len(&self) -> usize {
+        self.second_len_
+    }
+
+    /// The abortion from either side.
+    pub const fn error(&self) -> &TeeError<A1, A2> {
+        &self.error_
+    }
+}
+
+impl<A1, A2> TrChunkIoAbort for TeeAbort<A1, A2>
+where
+    A1: TrChunkIoAbort + Error,
+    A2: TrChunkIoAbort + Error,
+{
+    type LastErr = TeeError<A1, A2>;
+
+    #[inline]
+    fn perform_len(&self) -> usize {
+        cmp::min(self.first_len_, self.second_len_)
+    }
+
+    #[inline]
+    fn last_error(&self) -> &Self::LastErr {
+        &self.error_
+    }
+
+    #[inline]
+    fn add_perform_len(self, len: usize) -> Self {
+        TeeAbort {
+            first_len_: self.first_len_ + len,
+            second_len_: self.second_len_ + len,
+            ..self
+        }
+    }
+
+    #[inline]
+    fn kind(&self) -> ChunkIoAbortKind {
+        match &self.error_ {
+            TeeError::First(a) => a.kind(),
+            TeeError::Second(a) => a.kind(),
+            TeeError::SecondStalled => ChunkIoAbortKind::Fault,
+        }
+    }
+}
+
+/// Loads every unit into two loaders, for example to mirror the traffic into
+/// a capture sink.
+///
+/// Each load goes to the first loader, and then exactly the units the first
+/// loader accepts go to the second. The tee fails fast: once the second
+/// loader cannot accept all of them, the load is aborted with `TeeAbort`
+/// reporting how far each side gets, and nothing is buffered to make up the
+/// difference. The units accepted by the first loader but not yet by the
+/// second are kept in the tee across polls, so the load is to be polled to
+/// completion before the next one.
+pub struct TeeLoader<B1, L1, B2, L2, T>
+where
+    B1: BorrowMut<L1>,
+    L1: TrChunkLoader<T>,
+    B2: BorrowMut<L2>,
+    L2: TrChunkLoader<T>,
+    T: Clone,
+{
+    _use_l_: PhantomData<(L1, L2)>,
+    _use_t_: PhantomData<[T]>,
+    first_: B1,
+    second_: B2,
+    /// Units of the source loaded into the first loader but not yet into the
+    /// second, following the units loaded into both.
+    ahead_len_: usize,
+    /// The abort of the first loader, reported once the second catches up.
+    first_abort_: Option<L1::IoAbort>,
+}
+
+impl<B1, L1, B2, L2, T> TeeLoader<B1, L1, B2, L2, T>
+where
+    B1: BorrowMut<L1>,
+    L1: TrChunkLoader<T>,
+    B2: BorrowMut<L2>,
+    L2: TrChunkLoader<T>,
+    T: Clone,
+{
+    pub const fn new(first: B1, second: B2) -> Self {
+        TeeLoader {
+            _use_l_: PhantomData,
+            _use_t_: PhantomData,
+            first_: first,
+            second_: second,
+            ahead_len_: 0usize,
+            first_abort_: Option::None,
+        }
+    }
+
+    pub fn into_inner(self) -> (B1, B2) {
+        (self.first_, self.second_)
+    }
+}
+
+impl<B1, L1, B2, L2, T> TrChunkLoader<T> for TeeLoader<B1, L1, B2, L2, T>
+where
+    B1: BorrowMut<L1>,
+    L1: TrChunkLoader<T>,
+    L1::IoAbort: Error,
+    B2: BorrowMut<L2>,
+    L2: TrChunkLoader<T>,
+    L2::IoAbort: Error,
+    T: Clone,
+{
+    type IoAbort = TeeAbort<L1::IoAbort, L2::IoAbort>;
+    type LoadAsync<'a> = ChunkLoadAsync<'a, Self, T> where Self: 'a;
+
+    #[inline(always)]
+    fn load_async<'a>(
+        &'a mut self,
+        source: &'a [T],
+    ) -> Self::LoadAsync<'a> {
+        ChunkLoadAsync::new(self, source)
+    }
+
+    #[inline(always)]
+    fn load_at_least_async<'a>(
+        &'a mut self,
+        source: &'a [T],
+        min_len: usize,
+    ) -> Self::LoadAsync<'a> {
+        ChunkLoadAsync::new_at_least(self, source, min_len)
+    }
+}
+
+impl<B1, L1, B2, L2, T> TrChunkLoadWith<T> for TeeLoader<B1, L1, B2, L2, T>
+where
+    B1: BorrowMut<L1>,
+    L1: TrChunkLoader<T>,
+    L1::IoAbort: Error,
+    B2: BorrowMut<L2>,
+    L2: TrChunkLoader<T>,
+    L2::IoAbort: Error,
+    T: Clone,
+{
+    fn poll_load_with<C>(
+        &mut self,
+        source: &[T],
+        min_len: usize,
+        perform_len: &mut usize,
+        mut cancel: Pin<&mut C>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<usize, Self::IoAbort>>
+    where
+        C: TrCancellationToken,
+    {
+        loop {
+            let done_len = *perform_len;
+            if self.ahead_len_ > 0 {
+                let second = self.second_.borrow_mut();
+                let ahead = &source[done_len..done_len + self.ahead_len_];
+                let r = poll_load_some(second, ahead, cancel.as_mut(), cx);
+                let Poll::Ready(r) = r else {
+                    return Poll::Pending;
+                };
+                match r {
+                    Result::Ok(m) if m > 0 => {
+                        *perform_len += m;
+                        self.ahead_len_ -= m;
+                        continue;
+                    },
+                    Result::Ok(_) => {
+                        let n = done_len + self.ahead_len_;
+                        self.ahead_len_ = 0;
+                        self.first_abort_ = Option::None;
+                        let e = TeeError::SecondStalled;
+                        let a = TeeAbort::new(n, done_len, e);
+                        return Poll::Ready(Result::Err(a));
+                    },
+                    Result::Err(a) => {
+                        let n = done_len + self.ahead_len_;
+                        let m = done_len + a.perform_len();
+                        self.ahead_len_ = 0;
+                        self.first_abort_ = Option::None;
+                        let e = TeeError::Second(a);
+                        let a = TeeAbort::new(n, m, e);
+                        return Poll::Ready(Result::Err(a));
+                    },
+                }
+            }
+            if let Option::Some(a) = self.first_abort_.take() {
+                let e = TeeError::First(a);
+                let a = TeeAbort::new(done_len, done_len, e);
+                return Poll::Ready(Result::Err(a));
+            }
+            if done_len >= min_len {
+                return Poll::Ready(Result::Ok(done_len));
+            }
+            let first = self.first_.borrow_mut();
+            let rest = &source[done_len..];
+            let r = poll_load_some(first, rest, cancel.as_mut(), cx);
+            let Poll::Ready(r) = r else {
+                return Poll::Pending;
+            };
+            match r {
+                Result::Ok(0) => return Poll::Ready(Result::Ok(done_len)),
+                Result::Ok(n) => self.ahead_len_ = n,
+                Result::Err(a) => {
+                    self.ahead_len_ = a.perform_len();
+                    self.first_abort_ = Option::Some(a.add_perform_len(done_len));
+                },
+            }
+        }
+    }
+}
@@ -0,0 +1,377 @@
+use core::{
+    cmp,
+    error::Error,
+    fmt,
+    future::{self, Future},
+    pin::{pin, Pin},
+    task::{Context, Poll, Waker},
+};
+use std::vec::Vec;
+
+use abs_buff::{
+    x_deps::abs_sync, TrBuffIterPeek, TrBuffIterRead, TrBuffIterWrite,
+};
+use abs_sync::cancellation::*;
+
+use crate::{
+    chunk_async_::{
+        ChunkFillAsync, ChunkLoadAsync, TrChunkFillWith, TrChunkLoadWith,
+    },
+    ChunkIoAbort, TrBuffClosed, TrChunkFiller, TrChunkLoader,
+};
+
+/// Polls the future to completion, the fillers, loaders and buffers in the
+/// tests wake the task before they pend, if they ever pend.
+pub(crate) fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = pin!(future);
+    let mut cx = Context::from_waker(Waker::noop());
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+            return output;
+        }
+    }
+}
+
+pub(crate) fn no_cancel() -> Pin<&'static mut NonCancellableToken> {
+    NonCancellableToken::pinned()
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct MockError;
+
+impl fmt::Display for MockError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "mock error")
+    }
+}
+
+impl Error for MockError {}
+
+/// Fills the units of a slice, at most `seg_len` units per fill, and aborts
+/// with the end of stream once the slice is exhausted.
+pub(crate) struct SliceFiller<'s> {
+    source_: &'s [u8],
+    seg_len_: usize,
+    fill_count_: usize,
+}
+
+impl<'s> SliceFiller<'s> {
+    pub(crate) const fn new(source: &'s [u8], seg_len: usize) -> Self {
+        SliceFiller {
+            source_: source,
+            seg_len_: seg_len,
+            fill_count_: 0,
+        }
+    }
+
+    /// Number of units not filled yet.
+    pub(crate) const fn remain_len(&self) -> usize {
+        self.source_.len()
+    }
+
+    /// Number of fills called, including those aborted.
+    pub(crate) const fn fill_count(&self) -> usize {
+        self.fill_count_
+    }
+}
+
+impl TrChunkFiller<u8> for SliceFiller<'_> {
+    type IoAbort = ChunkIoAbort<MockError>;
+    type FillAsync<'a> = ChunkFillAsync<'a, Self, u8> where Self: 'a;
+
+    fn fill_async<'a>(
+        &'a mut self,
+        target: &'a mut [u8],
+    ) -> Self::FillAsync<'a> {
+        ChunkFillAsync::new(self, target)
+    }
+
+    fn fill_at_least_async<'a>(
+        &'a mut self,
+        target: &'a mut [u8],
+        min_len: usize,
+    ) -> Self::FillAsync<'a> {
+        ChunkFillAsync::new_at_least(self, target, min_len)
+    }
+}
+
+impl TrChunkFillWith<u8> for SliceFiller<'_> {
+    fn poll_fill_with<C>(
+        &mut self,
+        target: &mut [u8],
+        min_len: usize,
+        perform_len: &mut usize,
+        _: Pin<&mut C>,
+        _: &mut Context<'_>,
+    ) -> Poll<Result<usize, Self::IoAbort>>
+    where
+        C: TrCancellationToken,
+    {
+        self.fill_count_ += 1;
+        while *perform_len < min_len.max(1) && *perform_len < target.len() {
+            if self.source_.is_empty() {
+                let n = *perform_len;
+                let abort = ChunkIoAbort::end_of_stream(n, MockError);
+                return Poll::Ready(Result::Err(abort));
+            }
+            let opr_len = self.seg_len_
+                .min(self.source_.len())
+                .min(target.len() - *perform_len);
+            let (src, rest) = self.source_.split_at(opr_len);
+            target[*perform_len..*perform_len + opr_len].copy_from_slice(src);
+            self.source_ = rest;
+            *perform_len += opr_len;
+        }
+        Poll::Ready(Result::Ok(*perform_len))
+    }
+}
+
+/// Loads every unit into a vector.
+#[derive(Default)]
+pub(crate) struct VecLoader(pub(crate) Vec<u8>);
+
+impl TrChunkLoader<u8> for VecLoader {
+    type IoAbort = ChunkIoAbort<MockError>;
+    type LoadAsync<'a> = ChunkLoadAsync<'a, Self, u8> where Self: 'a;
+
+    fn load_async<'a>(
+        &'a mut self,
+        source: &'a [u8],
+    ) -> Self::LoadAsync<'a> {
+        ChunkLoadAsync::new(self, source)
+    }
+}
+
+impl TrChunkLoadWith<u8> for VecLoader {
+    fn poll_load_with<C>(
+        &mut self,
+        source: &[u8],
+        _: usize,
+        perform_len: &mut usize,
+        _: Pin<&mut C>,
+        _: &mut Context<'_>,
+    ) -> Poll<Result<usize, Self::IoAbort>>
+    where
+        C: TrCancellationToken,
+    {
+        self.0.extend_from_slice(&source[*perform_len..]);
+        *perform_len = source.len();
+        Poll::Ready(Result::Ok(source.len()))
+    }
+}
+
+/// A buffer that pends `stall` polls before each read, peek or write
+/// completes, to test the resumption of the fills and loads across polls.
+///
+/// The units to read arrive by `seg_len` units on every completed read or
+/// peek, and are handed out in segments of at most `seg_len` units. A write
+/// reserves at most `seg_len` units at the end of the written units. Reading
+/// or peeking with nothing arrived, or writing to a closed buffer, fails.
+pub(crate) struct MockBuff {
+    source_: Vec<u8>,
+    arrive_len_: usize,
+    read_len_: usize,
+    written_: Vec<u8>,
+    seg_len_: usize,
+    stall_: usize,
+    stall_left_: usize,
+    pending_count_: usize,
+    closed_: bool,
+}
+
+impl MockBuff {
+    pub(crate) fn new(source: &[u8], seg_len: usize, stall: usize) -> Self {
+        assert!(seg_len > 0);
+        MockBuff {
+            source_: source.to_vec(),
+            arrive_len_: 0,
+            read_len_: 0,
+            written_: Vec::new(),
+            seg_len_: seg_len,
+            stall_: stall,
+            stall_left_: stall,
+            pending_count_: 0,
+            closed_: false,
+        }
+    }
+
+    pub(crate) fn close(&mut self) {
+        self.closed_ = true;
+    }
+
+    /// Number of polls that returned `Pending`.
+    pub(crate) const fn pending_count(&self) -> usize {
+        self.pending_count_
+    }
+
+    pub(crate) fn written(&self) -> &[u8] {
+        &self.written_
+    }
+
+    fn poll_stall_(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        if self.stall_left_ > 0 {
+            self.stall_left_ -= 1;
+            self.pending_count_ += 1;
+            cx.waker().wake_by_ref();
+            return Poll::Pending;
+        }
+        self.stall_left_ = self.stall_;
+        Poll::Ready(())
+    }
+
+    fn arrive_(&mut self) -> Result<(), MockError> {
+        self.arrive_len_ =
+            cmp::min(self.arrive_len_ + self.seg_len_, self.source_.len());
+        if self.read_len_ < self.arrive_len_ {
+            Result::Ok(())
+        } else {
+            Result::Err(MockError)
+        }
+    }
+
+    fn read_(&mut self, length: usize) -> Result<Vec<&[u8]>, MockError> {
+        self.arrive_()?;
+        let start = self.read_len_;
+        let end = cmp::min(start + length, self.arrive_len_);
+        self.read_len_ = end;
+        let seg_len = self.seg_len_;
+        Result::Ok(self.source_[start..end].chunks(seg_len).collect())
+    }
+
+    fn peek_(&mut self) -> Result<Vec<&[u8]>, MockError> {
+        self.arrive_()?;
+        let seg_len = self.seg_len_;
+        let visible = &self.source_[self.read_len_..self.arrive_len_];
+        Result::Ok(visible.chunks(seg_len).collect())
+    }
+
+    fn write_(&mut self, length: usize) -> Result<Vec<&mut [u8]>, MockError> {
+        if self.closed_ {
+            return Result::Err(MockError);
+        }
+        let start = self.written_.len();
+        let end = start + cmp::min(length, self.seg_len_);
+        self.written_.resize(end, 0);
+        let seg_len = self.seg_len_;
+        Result::Ok(self.written_[start..].chunks_mut(seg_len).collect())
+    }
+}
+
+impl TrBuffClosed for MockBuff {
+    fn is_closed(&self) -> bool {
+        self.closed_
+    }
+}
+
+pub(crate) struct MockBuffRead<'a>(&'a mut MockBuff, usize);
+
+impl<'a> TrIntoFutureMayCancel<'a> for MockBuffRead<'a> {
+    type MayCancelOutput = Result<Vec<&'a [u8]>, MockError>;
+
+    fn may_cancel_with<C>(
+        self,
+        _: Pin<&'a mut C>,
+    ) -> impl Future<Output = Self::MayCancelOutput>
+    where
+        C: TrCancellationToken,
+    {
+        let MockBuffRead(buff, length) = self;
+        let mut buff = Option::Some(buff);
+        future::poll_fn(move |cx| {
+            let Option::Some(b) = buff.as_mut() else {
+                panic!("polled after completion");
+            };
+            if b.poll_stall_(cx).is_pending() {
+                return Poll::Pending;
+            }
+            let b = buff.take().unwrap();
+            Poll::Ready(b.read_(length))
+        })
+    }
+}
+
+impl TrBuffIterRead<u8> for MockBuff {
+    type Err = MockError;
+    type SliceRef<'a> = &'a [u8];
+    type BuffIter<'a> = Vec<&'a [u8]>;
+    type ReadAsync<'a> = MockBuffRead<'a>;
+
+    fn read_async(&mut self, length: usize) -> Self::ReadAsync<'_> {
+        MockBuffRead(self, length)
+    }
+}
+
+pub(crate) struct MockBuffPeek<'a>(&'a mut MockBuff);
+
+impl<'a> TrIntoFutureMayCancel<'a> for MockBuffPeek<'a> {
+    type MayCancelOutput = Result<Vec<&'a [u8]>, MockError>;
+
+    fn may_cancel_with<C>(
+        self,
+        _: Pin<&'a mut C>,
+    ) -> impl Future<Output = Self::MayCancelOutput>
+    where
+        C: TrCancellationToken,
+    {
+        let mut buff = Option::Some(self.0);
+        future::poll_fn(move |cx| {
+            let Option::Some(b) = buff.as_mut() else {
+                panic!("polled after completion");
+            };
+            if b.poll_stall_(cx).is_pending() {
+                return Poll::Pending;
+            }
+            let b = buff.take().unwrap();
+            Poll::Ready(b.peek_())
+        })
+    }
+}
+
+impl TrBuffIterPeek<u8> for MockBuff {
+    type Err = MockError;
+    type SliceRef<'a> = &'a [u8];
+    type BuffIter<'a> = Vec<&'a [u8]>;
+    type PeekAsync<'a> = MockBuffPeek<'a>;
+
+    fn peek_async(&mut self) -> Self::PeekAsync<'_> {
+        MockBuffPeek(self)
+    }
+}
+
+pub(crate) struct MockBuffWrite<'a>(&'a mut MockBuff, usize);
+
+impl<'a> TrIntoFutureMayCancel<'a> for MockBuffWrite<'a> {
+    type MayCancelOutput = Result<Vec<&'a mut [u8]>, MockError>;
+
+    fn may_cancel_with<C>(
+        self,
+        _: Pin<&'a mut C>,
+    ) -> impl Future<Output = Self::MayCancelOutput>
+    where
+        C: TrCancellationToken,
+    {
+        let MockBuffWrite(buff, length) = self;
+        let mut buff = Option::Some(buff);
+        future::poll_fn(move |cx| {
+            let Option::Some(b) = buff.as_mut() else {
+                panic!("polled after completion");
+            };
+            if b.poll_stall_(cx).is_pending() {
+                return Poll::Pending;
+            }
+            let b = buff.take().unwrap();
+            Poll::Ready(b.write_(length))
+        })
+    }
+}
+
+impl TrBuffIterWrite<u8> for MockBuff {
+    type Err = MockError;
+    type SliceMut<'a> = &'a mut [u8];
+    type BuffIter<'a> = Vec<&'a mut [u8]>;
+    type WriteAsync<'a> = MockBuffWrite<'a>;
+
+    fn write_async(&mut self, length: usize) -> Self::WriteAsync<'_> {
+        MockBuffWrite(self, length)
+    }
+}
@@ -0,0 +1,547 @@
+use core::{
+    error::Error,
+    fmt,
+    future::{Future, IntoFuture},
+    marker::PhantomData,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use pin_utils::pin_mut;
+
+use abs_buff::x_deps::abs_sync;
+use abs_sync::{cancellation::*, x_deps::pin_utils};
+
+use crate::{
+    chunk_async_::poll_load_some,
+    TrChunkFiller, TrChunkIoAbort, TrChunkLoader,
+};
+
+/// The max length of an LEB128 encoded `u128`.
+pub(crate) const VARINT_BUF_LEN: usize = 19;
+
+/// Encodes the raw value as LEB128, returns the bytes and the encoded length.
+pub(crate) fn encode_varint(mut raw: u128) -> ([u8; VARINT_BUF_LEN], usize) {
+    let mut bytes = [0u8; VARINT_BUF_LEN];
+    let mut len = 0usize;
+    loop {
+        let byte = (raw & 0x7F) as u8;
+        raw >>= 7;
+        if raw == 0 {
+            bytes[len] = byte;
+            len += 1;
+            break (bytes, len);
+        }
+        bytes[len] = byte | 0x80;
+        len += 1;
+    }
+}
+
+/// Decodes a complete LEB128 encoding, returns `None` on overflow.
+pub(crate) fn decode_varint(bytes: &[u8]) -> Option<u128> {
+    let mut raw = 0u128;
+    for (i, byte) in bytes.iter().enumerate() {
+        let shift = i as u32 * 7;
+        let bits = (byte & 0x7F) as u128;
+        if shift >= u128::BITS || (bits << shift) >> shift != bits {
+            return Option::None;
+        }
+        raw |= bits << shift;
+    }
+    Option::Some(raw)
+}
+
+/// An integer that can be encoded as an unsigned LEB128, or a zig-zag signed
+/// LEB128.
+pub trait TrVarInt: Sized {
+    /// The max number of bytes of the encoded integer.
+    const MAX_LEN: usize;
+
+    /// Converts from the raw LEB128 value, returns `None` on overflow.
+    fn from_raw(raw: u128) -> Option<Self>;
+
+    /// Converts into the raw LEB128 value.
+    fn into_raw(self) -> u128;
+}
+
+macro_rules! impl_varint_unsigned {
+    ($($t:ty),*) => {$(
+        impl TrVarInt for $t {
+            const MAX_LEN: usize = (<$t>::BITS as usize).div_ceil(7);
+
+            #[inline(always)]
+            fn from_raw(raw: u128) -> Option<Self> {
+                <$t>::try_from(raw).ok()
+            }
+
+            #[inline(always)]
+            fn into_raw(self) -> u128 {
+                self as u128
+            }
+        }
+    )*};
+}
+
+macro_rules! impl_varint_zigzag {
+    ($($t:ty: $u:ty),*) => {$(
+        impl TrVarInt for $t {
+            const MAX_LEN: usize = (<$t>::BITS as usize).div_ceil(7);
+
+            #[inline(always)]
+            fn from_raw(raw: u128) -> Option<Self> {
+                let u = <$u>::try_from(raw).ok()?;
+                Option::Some(((u >> 1) as $t) ^ -((u & 1) as $t))
+            }
+
+            #[inline(always)]
+            fn into_raw(self) -> u128 {
+                ((self << 1) ^ (self >> (<$t>::BITS - 1))) as $u as u128
+            }
+        }
+    )*};
+}
+
+impl_varint_unsigned!(u8, u16, u32, u64, u128);
+impl_varint_zigzag!(i8: u8, i16: u16, i32: u32, i64: u64, i128: u128);
+
+/// The error of decoding an LEB128 integer.
+#[derive(Debug)]
+pub enum VarIntError<A> {
+    /// The encoding is longer than the max length, or the value overflows
+    /// the integer type. Reports the number of bytes consumed.
+    Overflow(usize),
+    /// The filler is aborted. Reports the number of bytes consumed before
+    /// the abort, including the `perform_len` of the abort itself.
+    Io(usize, A),
+}
+
+impl<A> fmt::Display for VarIntError<A>
+where
+    A: Error,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VarIntError::Overflow(n) => {
+                write!(f, "varint overflow after {n} bytes")
+            },
+            VarIntError::Io(n, a) => {
+                write!(f, "varint aborted after {n} bytes: {a}")
+            },
+        }
+    }
+}
+
+impl<A> Error for VarIntError<A>
+where
+    A: Error,
+{}
+
+/// Decodes LEB128 integers from a chunk filler, one byte per fill.
+///
+/// So the filler has to consume the bytes it fills. A `BuffPeekAsChunkFiller`
+/// copies every fill from the head of the buffer, thus it decodes only the
+/// integers encoded in a single byte, and a longer encoding is reported as
+/// `VarIntError::Overflow`.
+pub trait TrChunkVarIntFiller: TrChunkFiller<u8> {
+    /// Decodes an unsigned LEB128 integer, or a zig-zag signed one for the
+    /// signed integer types.
+    #[inline(always)]
+    fn read_varint<N>(&mut self) -> ChunkReadVarIntAsync<'_, Self, N>
+    where
+        N: TrVarInt,
+    {
+        ChunkReadVarIntAsync::new(self)
+    }
+}
+
+impl<F> TrChunkVarIntFiller for F
+where
+    F: TrChunkFiller<u8> + ?Sized,
+{}
+
+pub struct ChunkReadVarIntAsync<'a, F, N>
+where
+    F: TrChunkFiller<u8> + ?Sized,
+    N: TrVarInt,
+{
+    _use_n_: PhantomData<N>,
+    filler_: &'a mut F,
+    max_len_: usize,
+}
+
+impl<'a, F, N> ChunkReadVarIntAsync<'a, F, N>
+where
+    F: TrChunkFiller<u8> + ?Sized,
+    N: TrVarInt,
+{
+    pub fn new(filler: &'a mut F) -> Self {
+        ChunkReadVarIntAsync {
+            _use_n_: PhantomData,
+            filler_: filler,
+            max_len_: N::MAX_LEN,
+        }
+    }
+
+    /// Treat the encoding longer than `max_len` bytes as overflow. The
+    /// `max_len` is capped at `N::MAX_LEN`.
+    pub fn with_max_len(self, max_len: usize) -> Self {
+        ChunkReadVarIntAsync {
+            max_len_: core::cmp::min(max_len, N::MAX_LEN),
+            ..self
+        }
+    }
+
+    pub fn may_cancel_with<C>(
+        self,
+        cancel: Pin<&'a mut C>,
+    ) -> ChunkReadVarIntFuture<'a, C, F, N>
+    where
+        C: TrCancellationToken,
+    {
+        ChunkReadVarIntFuture::new(self, cancel)
+    }
+}
+
+impl<'a, F, N> IntoFuture for ChunkReadVarIntAsync<'a, F, N>
+where
+    F: TrChunkFiller<u8> + ?Sized,
+    N: TrVarInt,
+{
+    type IntoFuture = ChunkReadVarIntFuture<'a, NonCancellableToken, F, N>;
+    type Output = <Self::IntoFuture as Future>::Output;
+
+    fn into_future(self) -> Self::IntoFuture {
+        let cancel = NonCancellableToken::pinned();
+        ChunkReadVarIntFuture::new(self, cancel)
+    }
+}
+
+impl<'a, F, N> TrIntoFutureMayCancel<'a> for ChunkReadVarIntAsync<'a, F, N>
+where
+    F: TrChunkFiller<u8> + ?Sized,
+    N: 'a + TrVarInt,
+{
+    type MayCancelOutput = <Self as IntoFuture>::Output;
+
+    #[inline(always)]
+    fn may_cancel_with<C>(
+        self,
+        cancel: Pin<&'a mut C>,
+    ) -> impl Future<Output = Self::MayCancelOutput>
+    where
+        C: TrCancellationToken,
+    {
+        ChunkReadVarIntAsync::may_cancel_with(self, cancel)
+    }
+}
+
+/// The future that decodes an LEB128 integer from a chunk filler.
+///
+/// The bytes of the encoding filled so far are kept in the future, so that
+/// a read that spans several wakeups resumes from where it stops.
+pub struct ChunkReadVarIntFuture<'a, C, F, N>
+where
+    C: TrCancellationToken,
+    F: TrChunkFiller<u8> + ?Sized,
+    N: TrVarInt,
+{
+    _use_n_: PhantomData<N>,
+    filler_: &'a mut F,
+    max_len_: usize,
+    bytes_: [u8; VARINT_BUF_LEN],
+    perform_len_: usize,
+    cancel_: Pin<&'a mut C>,
+}
+
+// None of the fields is pinned.
+impl<C, F, N> Unpin for ChunkReadVarIntFuture<'_, C, F, N>
+where
+    C: TrCancellationToken,
+    F: TrChunkFiller<u8> + ?Sized,
+    N: TrVarInt,
+{}
+
+impl<'a, C, F, N> ChunkReadVarIntFuture<'a, C, F, N>
+where
+    C: TrCancellationToken,
+    F: TrChunkFiller<u8> + ?Sized,
+    N: TrVarInt,
+{
+    pub fn new(
+        read: ChunkReadVarIntAsync<'a, F, N>,
+        cancel: Pin<&'a mut C>,
+    ) -> Self {
+        ChunkReadVarIntFuture {
+            _use_n_: PhantomData,
+            filler_: read.filler_,
+            max_len_: read.max_len_,
+            bytes_: [0u8; VARINT_BUF_LEN],
+            perform_len_: 0usize,
+            cancel_: cancel,
+        }
+    }
+
+    /// Number of bytes of the encoding that has been filled so far.
+    pub fn perform_len(&self) -> usize {
+        self.perform_len_
+    }
+}
+
+impl<C, F, N> Future for ChunkReadVarIntFuture<'_, C, F, N>
+where
+    C: TrCancellationToken,
+    F: TrChunkFiller<u8> + ?Sized,
+    N: TrVarInt,
+{
+    type Output = Result<N, VarIntError<F::IoAbort>>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        loop {
+            let perform_len = this.perform_len_;
+            if perform_len >= this.max_len_ {
+                let e = VarIntError::Overflow(perform_len);
+                return Poll::Ready(Result::Err(e));
+            }
+            // A pending fill of a single byte has filled nothing, so it is
+            // issued again by the next poll.
+            let r = {
+                let byte = &mut this.bytes_[perform_len..perform_len + 1];
+                let fill = this.filler_
+                    .fill_async(byte)
+                    .may_cancel_with(this.cancel_.as_mut());
+                pin_mut!(fill);
+                fill.poll(cx)
+            };
+            let Poll::Ready(r) = r else {
+                return Poll::Pending;
+            };
+            if let Result::Err(a) = r {
+                let n = perform_len + a.perform_len();
+                return Poll::Ready(Result::Err(VarIntError::Io(n, a)));
+            }
+            this.perform_len_ += 1;
+            if this.bytes_[perform_len] & 0x80 == 0 {
+                let bytes = &this.bytes_[..this.perform_len_];
+                let r = decode_varint(bytes)
+                    .and_then(N::from_raw)
+                    .ok_or(VarIntError::Overflow(this.perform_len_));
+                return Poll::Ready(r);
+            }
+        }
+    }
+}
+
+/// Encodes LEB128 integers into a chunk loader.
+pub trait TrChunkVarIntLoader: TrChunkLoader<u8> {
+    /// Encodes an unsigned LEB128 integer, or a zig-zag signed one for the
+    /// signed integer types.
+    #[inline(always)]
+    fn write_varint<N>(&mut self, num: N) -> ChunkWriteVarIntAsync<'_, Self>
+    where
+        N: TrVarInt,
+    {
+        ChunkWriteVarIntAsync::new(self, num)
+    }
+}
+
+impl<L> TrChunkVarIntLoader for L
+where
+    L: TrChunkLoader<u8> + ?Sized,
+{}
+
+pub struct ChunkWriteVarIntAsync<'a, L>
+where
+    L: TrChunkLoader<u8> + ?Sized,
+{
+    loader_: &'a mut L,
+    bytes_: [u8; VARINT_BUF_LEN],
+    len_: usize,
+}
+
+impl<'a, L> ChunkWriteVarIntAsync<'a, L>
+where
+    L: TrChunkLoader<u8> + ?Sized,
+{
+    pub fn new<N>(loader: &'a mut L, num: N) -> Self
+    where
+        N: TrVarInt,
+    {
+        let (bytes, len) = encode_varint(num.into_raw());
+        ChunkWriteVarIntAsync {
+            loader_: loader,
+            bytes_: bytes,
+            len_: len,
+        }
+    }
+
+    /// Number of bytes of the encoded integer.
+    pub fn encoded_len(&self) -> usize {
+        self.len_
+    }
+
+    pub fn may_cancel_with<C>(
+        self,
+        cancel: Pin<&'a mut C>,
+    ) -> ChunkWriteVarIntFuture<'a, C, L>
+    where
+        C: TrCancellationToken,
+    {
+        ChunkWriteVarIntFuture::new(self, cancel)
+    }
+}
+
+impl<'a, L> IntoFuture for ChunkWriteVarIntAsync<'a, L>
+where
+    L: TrChunkLoader<u8> + ?Sized,
+{
+    type IntoFuture = ChunkWriteVarIntFuture<'a, NonCancellableToken, L>;
+    type Output = <Self::IntoFuture as Future>::Output;
+
+    fn into_future(self) -> Self::IntoFuture {
+        let cancel = NonCancellableToken::pinned();
+        ChunkWriteVarIntFuture::new(self, cancel)
+    }
+}
+
+impl<'a, L> TrIntoFutureMayCancel<'a> for ChunkWriteVarIntAsync<'a, L>
+where
+    L: TrChunkLoader<u8> + ?Sized,
+{
+    type MayCancelOutput = <Self as IntoFuture>::Output;
+
+    #[inline(always)]
+    fn may_cancel_with<C>(
+        self,
+        cancel: Pin<&'a mut C>,
+    ) -> impl Future<Output = Self::MayCancelOutput>
+    where
+        C: TrCancellationToken,
+    {
+        ChunkWriteVarIntAsync::may_cancel_with(self, cancel)
+    }
+}
+
+/// The future that encodes an LEB128 integer into a chunk loader.
+///
+/// How many bytes of the encoding are loaded is kept in the future, so that
+/// a write that spans several wakeups resumes from where it stops.
+pub struct ChunkWriteVarIntFuture<'a, C, L>
+where
+    C: TrCancellationToken,
+    L: TrChunkLoader<u8> + ?Sized,
+{
+    loader_: &'a mut L,
+    bytes_: [u8; VARINT_BUF_LEN],
+    len_: usize,
+    perform_len_: usize,
+    cancel_: Pin<&'a mut C>,
+}
+
+impl<'a, C, L> ChunkWriteVarIntFuture<'a, C, L>
+where
+    C: TrCancellationToken,
+    L: TrChunkLoader<u8> + ?Sized,
+{
+    pub fn new(
+        write: ChunkWriteVarIntAsync<'a, L>,
+        cancel: Pin<&'a mut C>,
+    ) -> Self {
+        ChunkWriteVarIntFuture {
+            loader_: write.loader_,
+            bytes_: write.bytes_,
+            len_: write.len_,
+            perform_len_: 0usize,
+            cancel_: cancel,
+        }
+    }
+}
+
+impl<C, L> Future for ChunkWriteVarIntFuture<'_, C, L>
+where
+    C: TrCancellationToken,
+    L: TrChunkLoader<u8> + ?Sized,
+{
+    type Output = Result<(), L::IoAbort>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        while this.perform_len_ < this.len_ {
+            let source = &this.bytes_[this.perform_len_..this.len_];
+            let cancel = this.cancel_.as_mut();
+            let r = poll_load_some(this.loader_, source, cancel, cx);
+            let Poll::Ready(r) = r else {
+                return Poll::Pending;
+            };
+            match r {
+                Result::Ok(0) => break,
+                Result::Ok(n) => this.perform_len_ += n,
+                Result::Err(a) => {
+                    let a = a.add_perform_len(this.perform_len_);
+                    return Poll::Ready(Result::Err(a));
+                },
+            }
+        }
+        Poll::Ready(Result::Ok(()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::vec::Vec;
+
+    use super::*;
+    use crate::{test_utils_::*, BuffPeekAsChunkFiller, BuffReadAsChunkFiller};
+
+    #[test]
+    fn read_multi_byte_across_pending_reads() {
+        let mut source = Vec::new();
+        source.extend_from_slice(&[0xAC, 0x02]);
+        let (bytes, len) = encode_varint(u64::MAX as u128);
+        source.extend_from_slice(&bytes[..len]);
+        let (bytes, len) = encode_varint((-70_000i32).into_raw());
+        source.extend_from_slice(&bytes[..len]);
+        let mut buff = MockBuff::new(&source, 1, 2);
+        let mut filler = BuffReadAsChunkFiller::from(&mut buff);
+
+        let x: u32 = block_on(
+            filler.read_varint().may_cancel_with(no_cancel()),
+        )
+        .unwrap();
+        assert_eq!(x, 300);
+        let x: u64 = block_on(
+            filler.read_varint().may_cancel_with(no_cancel()),
+        )
+        .unwrap();
+        assert_eq!(x, u64::MAX);
+        let x: i32 = block_on(
+            filler.read_varint().may_cancel_with(no_cancel()),
+        )
+        .unwrap();
+        assert_eq!(x, -70_000);
+        assert!(buff.pending_count() >= 2 * source.len());
+    }
+
+    #[test]
+    fn peek_decodes_single_byte_only() {
+        let source = [0x05u8, 0xAC, 0x02];
+        let mut buff = MockBuff::new(&source, 1, 2);
+        let mut filler = BuffPeekAsChunkFiller::from(&mut buff);
+        for _ in 0..2 {
+            let x: i8 = block_on(
+                filler.read_varint().may_cancel_with(no_cancel()),
+            )
+            .unwrap();
+            assert_eq!(x, -3);
+        }
+
+        let source = [0xACu8, 0x02];
+        let mut buff = MockBuff::new(&source, 1, 2);
+        let mut filler = BuffPeekAsChunkFiller::from(&mut buff);
+        let e = block_on(
+            filler.read_varint::<u32>().may_cancel_with(no_cancel()),
+        )
+        .unwrap_err();
+        assert!(matches!(e, VarIntError::Overflow(5)));
+    }
+}
@@ -7,13 +7,12 @@
     task::{Context, Poll},
 };
 
-use pin_project::pin_project;
 use pin_utils::pin_mut;
 
 use abs_buff::{x_deps::abs_sync, TrBuffIterWrite};
 use abs_sync::{cancellation::*, x_deps::pin_utils};
 
-use crate::{ChunkIoAbort, TrChunkLoader};
+use crate::{ChunkIoAbort, ChunkIoAbortKind, TrBuffClosed, TrChunkLoader};
 
 pub struct BuffWriteAsChunkLoader<B, W, T>
 where
@@ -24,6 +23,21 @@ where
     _use_w_: PhantomData<W>,
     _use_t_: PhantomData<[T]>,
     buffer_: B,
+    classify_: fn(&W, &<W as TrBuffIterWrite<T>>::Err) -> ChunkIoAbortKind,
+}
+
+impl<B, W, T> BuffWriteAsChunkLoader<B, W, T>
+where
+    B: BorrowMut<W>,
+    W: TrBuffIterWrite<T> + TrBuffClosed,
+    T: Clone,
+{
+    /// An error from the buffer is classified as the end of stream once the
+    /// buffer is closed, and as a fault otherwise.
+    pub const fn new_with_closed_state(buffer: B) -> Self {
+        let classify = ChunkIoAbortKind::from_closed_state;
+        BuffWriteAsChunkLoader::new_with_classify(buffer, classify)
+    }
 }
 
 impl<B, W, T> BuffWriteAsChunkLoader<B, W, T>
@@ -32,11 +46,28 @@ where
     W: TrBuffIterWrite<T>,
     T: Clone,
 {
+    /// Every error from the buffer is classified as a fault, see
+    /// `new_with_closed_state` and `new_with_classify` to tell the end of
+    /// stream.
     pub const fn new(buffer: B) -> Self {
+        let classify = ChunkIoAbortKind::always_fault;
+        BuffWriteAsChunkLoader::new_with_classify(buffer, classify)
+    }
+
+    /// Create with how an error from the buffer is classified in the abort,
+    /// for example for a buffer that does not tell whether it is closed.
+    ///
+    /// An error while the cancellation token is signalled is always
+    /// classified as `ChunkIoAbortKind::Cancelled`.
+    pub const fn new_with_classify(
+        buffer: B,
+        classify: fn(&W, &<W as TrBuffIterWrite<T>>::Err) -> ChunkIoAbortKind,
+    ) -> Self {
         BuffWriteAsChunkLoader {
             _use_w_: PhantomData,
             _use_t_: PhantomData,
             buffer_: buffer,
+            classify_: classify,
         }
     }
 
@@ -46,6 +77,73 @@ where
     ) -> BuffWriteChunkLoadAsync<'a, B, W, T> {
         BuffWriteChunkLoadAsync::new(self, source)
     }
+
+    pub fn load_at_least_async<'a>(
+        &'a mut self,
+        source: &'a [T],
+        min_len: usize,
+    ) -> BuffWriteChunkLoadAsync<'a, B, W, T> {
+        BuffWriteChunkLoadAsync::new_at_least(self, source, min_len)
+    }
+
+    /// Polls the load of `source`, of which `perform_len` units are already
+    /// loaded, until at least `min_len` units are loaded.
+    fn poll_load_<C>(
+        &mut self,
+        source: &[T],
+        min_len: usize,
+        perform_len: &mut usize,
+        mut cancel: Pin<&mut C>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<usize, ChunkIoAbort<<W as TrBuffIterWrite<T>>::Err>>>
+    where
+        C: TrCancellationToken,
+    {
+        let classify = self.classify_;
+        let buffer = self.buffer_.borrow_mut();
+        let source_len = source.len();
+        let min_len = cmp::min(min_len, source_len);
+        let last_error = loop {
+            if *perform_len >= min_len {
+                return Poll::Ready(Result::Ok(*perform_len));
+            }
+            #[cfg(test)]
+            log::trace!(
+                "[BuffWriteAsChunkLoader::poll_load_] \
+                source_len({source_len}), perform_len({perform_len})"
+            );
+            let w = buffer
+                .write_async(source_len - *perform_len)
+                .may_cancel_with(cancel.as_mut());
+            pin_mut!(w);
+            let dst_iter = match w.poll(cx) {
+                Poll::Pending => return Poll::Pending,
+                Poll::Ready(Result::Ok(dst_iter)) => dst_iter,
+                Poll::Ready(Result::Err(last_error)) => break last_error,
+            };
+            for mut dst in dst_iter.into_iter() {
+                let dst_len = dst.len();
+                let opr_len = cmp::min(dst_len, source_len - *perform_len);
+                if opr_len == 0 {
+                    break;
+                }
+                debug_assert!(opr_len + *perform_len <= source_len);
+                let src = &source[*perform_len..*perform_len + opr_len];
+                dst[..opr_len].clone_from_slice(src);
+                *perform_len += opr_len;
+            }
+        };
+        // The units loaded so far stay in the buffer, so that a cancelled IO
+        // can be resumed from `perform_len`.
+        let perform_len = *perform_len;
+        let abort = if cancel.is_cancelled() {
+            ChunkIoAbort::cancelled(perform_len, last_error)
+        } else {
+            let kind = classify(buffer, &last_error);
+            ChunkIoAbort::with_kind(perform_len, last_error, kind)
+        };
+        Poll::Ready(Result::Err(abort))
+    }
 }
 
 impl<'a, W, T> From<&'a mut W> for BuffWriteAsChunkLoader<&'a mut W, W, T>
@@ -71,6 +169,15 @@ where
     fn load_async<'a>(&'a mut self, source: &'a [T]) -> Self::LoadAsync<'a> {
         BuffWriteAsChunkLoader::load_async(self, source)
     }
+
+    #[inline(always)]
+    fn load_at_least_async<'a>(
+        &'a mut self,
+        source: &'a [T],
+        min_len: usize,
+    ) -> Self::LoadAsync<'a> {
+        BuffWriteAsChunkLoader::load_at_least_async(self, source, min_len)
+    }
 }
 
 pub struct BuffWriteChunkLoadAsync<'a, B, W, T>
@@ -81,6 +188,7 @@ where
 {
     loader_: &'a mut BuffWriteAsChunkLoader<B, W, T>,
     source_: &'a [T],
+    min_len_: usize,
 }
 
 impl<'a, B, W, T> BuffWriteChunkLoadAsync<'a, B, W, T>
@@ -92,10 +200,19 @@ where
     pub fn new(
         loader: &'a mut BuffWriteAsChunkLoader<B, W, T>,
         source: &'a [T],
+    ) -> Self {
+        BuffWriteChunkLoadAsync::new_at_least(loader, source, source.len())
+    }
+
+    pub fn new_at_least(
+        loader: &'a mut BuffWriteAsChunkLoader<B, W, T>,
+        source: &'a [T],
+        min_len: usize,
     ) -> Self {
         BuffWriteChunkLoadAsync {
             loader_: loader,
             source_: source,
+            min_len_: min_len,
         }
     }
 
@@ -106,7 +223,7 @@ where
     where
         C: TrCancellationToken,
     {
-        BuffWriteChunkLoadFuture::new(self.loader_, self.source_, cancel)
+        BuffWriteChunkLoadFuture::new(self, cancel)
     }
 }
 
@@ -116,12 +233,13 @@ where
     W: TrBuffIterWrite<T>,
     T: Clone,
 {
-    type IntoFuture = BuffWriteChunkLoadFuture<'a, NonCancellableToken, B, W, T>;
+    type IntoFuture =
+        BuffWriteChunkLoadFuture<'a, NonCancellableToken, B, W, T>;
     type Output = <Self::IntoFuture as Future>::Output;
 
     fn into_future(self) -> Self::IntoFuture {
         let cancel = NonCancellableToken::pinned();
-        BuffWriteChunkLoadFuture::new(self.loader_, self.source_, cancel)
+        BuffWriteChunkLoadFuture::new(self, cancel)
     }
 }
 
@@ -131,7 +249,7 @@ where
     W: TrBuffIterWrite<T>,
     T: Clone,
 {
-    type MayCancelOutput = <<Self as IntoFuture>::IntoFuture as Future>::Output;
+    type MayCancelOutput = <Self as IntoFuture>::Output;
 
     #[inline(always)]
     fn may_cancel_with<C>(
@@ -145,7 +263,14 @@ where
     }
 }
 
-#[pin_project]
+/// The future that loads the units of the source into the buffer.
+///
+/// The number of units loaded from the source is kept in the future, so a
+/// load that spans several wakeups resumes from where it stops, and never
+/// writes a unit into the buffer twice. The pending `write_async` borrows the
+/// buffer that the future borrows as well, so it is not kept across polls,
+/// but issued again on the next poll. A pending write has not handed out any
+/// segment, so no unit is lost or duplicated.
 pub struct BuffWriteChunkLoadFuture<'a, C, B, W, T>
 where
     C: TrCancellationToken,
@@ -153,82 +278,82 @@ where
     W: TrBuffIterWrite<T>,
     T: Clone,
 {
-    #[pin]loader_: &'a mut BuffWriteAsChunkLoader<B, W, T>,
+    loader_: &'a mut BuffWriteAsChunkLoader<B, W, T>,
     source_: &'a [T],
+    min_len_: usize,
+    perform_len_: usize,
     cancel_: Pin<&'a mut C>,
 }
 
-impl<C, B, W, T> Future for BuffWriteChunkLoadFuture<'_, C, B, W, T>
+impl<'a, C, B, W, T> BuffWriteChunkLoadFuture<'a, C, B, W, T>
 where
     C: TrCancellationToken,
     B: BorrowMut<W>,
     W: TrBuffIterWrite<T>,
     T: Clone,
 {
-    type Output = Result<usize, ChunkIoAbort<<W as TrBuffIterWrite<T>>::Err>>;
+    pub fn new(
+        load: BuffWriteChunkLoadAsync<'a, B, W, T>,
+        cancel: Pin<&'a mut C>,
+    ) -> Self {
+        BuffWriteChunkLoadFuture {
+            loader_: load.loader_,
+            source_: load.source_,
+            min_len_: load.min_len_,
+            perform_len_: 0usize,
+            cancel_: cancel,
+        }
+    }
 
-    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
-        let f = self.load_async_();
-        pin_mut!(f);
-        f.poll(cx)
+    /// Number of units that has been loaded into the buffer so far.
+    pub fn perform_len(&self) -> usize {
+        self.perform_len_
     }
 }
 
-impl<'a, C, B, W, T> BuffWriteChunkLoadFuture<'a, C, B, W, T>
+impl<C, B, W, T> Future for BuffWriteChunkLoadFuture<'_, C, B, W, T>
 where
     C: TrCancellationToken,
     B: BorrowMut<W>,
     W: TrBuffIterWrite<T>,
     T: Clone,
 {
-    pub fn new(
-        loader: &'a mut BuffWriteAsChunkLoader<B, W, T>,
-        source: &'a [T],
-        cancel: Pin<&'a mut C>,
-    ) -> Self {
-        BuffWriteChunkLoadFuture {
-            loader_: loader,
-            source_: source,
-            cancel_: cancel,
-        }
+    type Output = Result<usize, ChunkIoAbort<<W as TrBuffIterWrite<T>>::Err>>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        this.loader_.poll_load_(
+            this.source_,
+            this.min_len_,
+            &mut this.perform_len_,
+            this.cancel_.as_mut(),
+            cx,
+        )
     }
+}
 
-    async fn load_async_(
-        self: Pin<&mut Self>,
-    ) -> Result<usize, ChunkIoAbort<<W as TrBuffIterWrite<T>>::Err>> {
-        let mut this = self.project();
-        let mut loader = this.loader_.as_mut();
-        let buffer = loader.buffer_.borrow_mut();
-        let source = *this.source_;
-        let source_len = source.len();
-        let mut perform_len = 0usize;
-        loop {
-            if perform_len >= source_len {
-                break Result::Ok(perform_len);
-            }
-            #[cfg(test)]
-            log::trace!(
-                "[BuffWriteChunkLoadFuture::load_async_] \
-                source_len({source_len}), perform_len({perform_len})"
-            );
-            let w = buffer
-                .write_async(source_len - perform_len)
-                .may_cancel_with(this.cancel_.as_mut())
-                .await;
-            let Result::Ok(dst_iter) = w else {
-                let Result::Err(last_error) = w else {
-                    unreachable!("[BuffWriteChunkLoadFuture::load_async_]")
-                };
-                break Result::Err(ChunkIoAbort::new(perform_len, last_error));
-            };
-            for mut dst in dst_iter.into_iter() {
-                let dst_len = dst.len();
-                let opr_len = cmp::min(dst_len, source_len - perform_len);
-                debug_assert!(opr_len + perform_len <= source_len);
-                let src = &source[perform_len..perform_len + opr_len];
-                dst.clone_from_slice(src);
-                perform_len += opr_len;
-            }
-        }
+#[cfg(test)]
+mod tests {
+    use std::vec::Vec;
+
+    use super::*;
+    use crate::test_utils_::*;
+
+    #[test]
+    fn load_resumes_across_pending_writes() {
+        let source: Vec<u8> = (0..25).collect();
+        let mut buff = MockBuff::new(&[], 4, 2);
+        let mut loader = BuffWriteAsChunkLoader::from(&mut buff);
+        let r = block_on(
+            loader.load_async(&source[..10]).may_cancel_with(no_cancel()),
+        );
+        assert_eq!(r.unwrap(), 10);
+        let r = block_on(
+            loader.load_async(&source[10..]).may_cancel_with(no_cancel()),
+        );
+        assert_eq!(r.unwrap(), 15);
+        assert_eq!(buff.written(), &source[..]);
+        // Every write of at most 4 units pended twice.
+        assert!(buff.pending_count() >= 2 * 25 / 4);
     }
 }